@@ -1,7 +1,14 @@
-use rodio::{OutputStream, Sink, buffer::SamplesBuffer};
+use crate::constants;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+/// Maximum number of interleaved samples kept in the output buffer. If the
+/// consumer does not drain the buffer, new samples are dropped.
+const MAX_BUFFERED_SAMPLES: usize = constants::AUDIO_SAMPLE_RATE as usize * 2;
 
 /// # APU
 /// The Audio Processing Unit, which manages the sound system.
+/// Generated samples are stereo, interleaved (L, R), at [constants::AUDIO_SAMPLE_RATE],
+/// and are collected with [Apu::take_samples].
 pub struct Apu {
     /// APU Registers 0xFF10-0xFF3F.
     regs: [u8; 0x30],
@@ -10,11 +17,6 @@ pub struct Apu {
     /// APU interrupt mask for registers IE and IF.
     pub i_mask: u8,
 
-    /// Audio device.
-    sink: Sink,
-    /// We must keep the stream alive for audio to play.
-    _stream: OutputStream,
-
     /// Audio buffer.
     buffer: Vec<f32>,
 
@@ -77,22 +79,15 @@ pub struct Apu {
 
 impl Apu {
     pub fn new() -> Self {
-        // Initialize Rodio.
-        let mut stream_handle =
-            rodio::OutputStreamBuilder::open_default_stream().expect("open default audio stream");
-        stream_handle.log_on_drop(false);
-        let sink = rodio::Sink::connect_new(stream_handle.mixer());
-
         Self {
             regs: [0; 0x30],
             wave_ram: [0; 16],
             i_mask: 0,
-            sink,
-            _stream: stream_handle,
-            buffer: Vec::with_capacity(1024),
+            buffer: Vec::with_capacity(4096),
             sample_timer: 0.0,
             // 4194304 Hz / 44100 Hz = 95.1089...
-            t_cycles_per_sample: 4194304.0 / 44100.0,
+            t_cycles_per_sample: constants::CPU_FREQ_HZ as f32
+                / constants::AUDIO_SAMPLE_RATE as f32,
 
             frame_sequencer: 0,
             frame_timer: 8192,
@@ -230,19 +225,17 @@ impl Apu {
                 }
             }
 
-            0xFF26 => {
-                // NR52 - Audio master control.
-                // If bit 7 is being toggled OFF.
-                if value & 0x80 == 0 {
-                    // Clear all registers $FF10-$FF25.
-                    for i in 0..0x16 {
-                        self.regs[i] = 0;
-                    }
-                    self.ch1_enabled = false;
-                    self.ch2_enabled = false;
-                    self.ch3_enabled = false;
-                    self.ch4_enabled = false;
+            // NR52 - Audio master control.
+            // If bit 7 is being toggled OFF.
+            0xFF26 if value & 0x80 == 0 => {
+                // Clear all registers $FF10-$FF25.
+                for i in 0..0x16 {
+                    self.regs[i] = 0;
                 }
+                self.ch1_enabled = false;
+                self.ch2_enabled = false;
+                self.ch3_enabled = false;
+                self.ch4_enabled = false;
             }
             _ => {}
         }
@@ -405,24 +398,18 @@ impl Apu {
             if self.accumulated_count > 0 {
                 let avg_l = self.accumulated_l / self.accumulated_count as f32;
                 let avg_r = self.accumulated_r / self.accumulated_count as f32;
-                self.buffer.push(avg_l);
-                self.buffer.push(avg_r);
+                // --- 5. Buffer Management ---
+                // Drop samples if nobody is consuming them.
+                if self.buffer.len() < MAX_BUFFERED_SAMPLES {
+                    self.buffer.push(avg_l);
+                    self.buffer.push(avg_r);
+                }
 
                 // Reset accumulators for next sample
                 self.accumulated_l = 0.0;
                 self.accumulated_r = 0.0;
                 self.accumulated_count = 0;
             }
-
-            // --- 5. Buffer Management & Throttling ---
-            if self.buffer.len() >= 1024 {
-                // Increased sink limit slightly to prevent underflow "tearing"
-                if self.sink.len() < 10 {
-                    let source = rodio::buffer::SamplesBuffer::new(2, 44100, self.buffer.clone());
-                    self.sink.append(source);
-                }
-                self.buffer.clear();
-            }
         }
     }
 
@@ -753,12 +740,120 @@ impl Apu {
         self.calculate_sweep_freq();
     }
 
-    /// Flush the buffer.
-    pub fn flush(&mut self) {
-        if !self.buffer.is_empty() {
-            let source = SamplesBuffer::new(2, 44100, self.buffer.clone());
-            self.sink.append(source);
-            self.buffer.clear();
-        }
+    /// Takes the samples generated since the last call, as interleaved
+    /// stereo (L, R) values at [constants::AUDIO_SAMPLE_RATE].
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Apu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.regs);
+        w.bytes(&self.wave_ram);
+        w.u8(self.i_mask);
+        w.f32(self.sample_timer);
+        w.u8(self.frame_sequencer);
+        w.u64(self.frame_timer);
+        // Channel 1.
+        w.bool(self.ch1_enabled);
+        w.i32(self.ch1_timer);
+        w.usize(self.ch1_duty_step);
+        w.u8(self.ch1_volume);
+        w.u8(self.ch1_envelope_timer);
+        w.bool(self.ch1_envelope_running);
+        w.u8(self.ch1_sweep_timer);
+        w.u16(self.ch1_sweep_shadow_freq);
+        w.bool(self.ch1_sweep_enabled);
+        // Channel 2.
+        w.bool(self.ch2_enabled);
+        w.i32(self.ch2_timer);
+        w.usize(self.ch2_duty_step);
+        w.u8(self.ch2_volume);
+        w.u8(self.ch2_envelope_timer);
+        w.bool(self.ch2_envelope_running);
+        // Channel 3.
+        w.bool(self.ch3_enabled);
+        w.i32(self.ch3_timer);
+        w.usize(self.ch3_sample_idx);
+        // Channel 4.
+        w.bool(self.ch4_enabled);
+        w.i32(self.ch4_timer);
+        w.u16(self.ch4_lfsr);
+        w.u8(self.ch4_volume);
+        w.u8(self.ch4_envelope_timer);
+        w.bool(self.ch4_envelope_running);
+        // Length counters.
+        w.u16(self.ch1_length_timer);
+        w.bool(self.ch1_length_enabled);
+        w.u16(self.ch2_length_timer);
+        w.bool(self.ch2_length_enabled);
+        w.u16(self.ch3_length_timer);
+        w.bool(self.ch3_length_enabled);
+        w.u16(self.ch4_length_timer);
+        w.bool(self.ch4_length_enabled);
+        // Accumulated.
+        w.f32(self.accumulated_l);
+        w.f32(self.accumulated_r);
+        w.u32(self.accumulated_count);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.regs)?;
+        r.bytes_into(&mut self.wave_ram)?;
+        self.i_mask = r.u8()?;
+        self.sample_timer = r.f32()?;
+        self.frame_sequencer = r.u8()?;
+        self.frame_timer = r.u64()?;
+        // Channel 1.
+        self.ch1_enabled = r.bool()?;
+        self.ch1_timer = r.i32()?;
+        self.ch1_duty_step = r.usize()? % 8;
+        self.ch1_volume = r.u8()?;
+        self.ch1_envelope_timer = r.u8()?;
+        self.ch1_envelope_running = r.bool()?;
+        self.ch1_sweep_timer = r.u8()?;
+        self.ch1_sweep_shadow_freq = r.u16()?;
+        self.ch1_sweep_enabled = r.bool()?;
+        // Channel 2.
+        self.ch2_enabled = r.bool()?;
+        self.ch2_timer = r.i32()?;
+        self.ch2_duty_step = r.usize()? % 8;
+        self.ch2_volume = r.u8()?;
+        self.ch2_envelope_timer = r.u8()?;
+        self.ch2_envelope_running = r.bool()?;
+        // Channel 3.
+        self.ch3_enabled = r.bool()?;
+        self.ch3_timer = r.i32()?;
+        self.ch3_sample_idx = r.usize()? % 32;
+        // Channel 4.
+        self.ch4_enabled = r.bool()?;
+        self.ch4_timer = r.i32()?;
+        self.ch4_lfsr = r.u16()?;
+        self.ch4_volume = r.u8()?;
+        self.ch4_envelope_timer = r.u8()?;
+        self.ch4_envelope_running = r.bool()?;
+        // Length counters.
+        self.ch1_length_timer = r.u16()?;
+        self.ch1_length_enabled = r.bool()?;
+        self.ch2_length_timer = r.u16()?;
+        self.ch2_length_enabled = r.bool()?;
+        self.ch3_length_timer = r.u16()?;
+        self.ch3_length_enabled = r.bool()?;
+        self.ch4_length_timer = r.u16()?;
+        self.ch4_length_enabled = r.bool()?;
+        // Accumulated.
+        self.accumulated_l = r.f32()?;
+        self.accumulated_r = r.f32()?;
+        self.accumulated_count = r.u32()?;
+        // Drop any pending samples from before the load.
+        self.buffer.clear();
+        Ok(())
     }
 }
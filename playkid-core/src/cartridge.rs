@@ -0,0 +1,506 @@
+mod header;
+mod info;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+
+use crate::cheats::RomPatch;
+use crate::savestate::{self, SaveState, StateReader, StateWriter};
+use colored::Colorize;
+pub use header::{CartridgeError, CartridgeHeader};
+pub use info::RomInfo;
+use mbc1::MBC1;
+use mbc2::MBC2;
+use mbc3::{MBC3, RTC_FOOTER_SIZE};
+use mbc5::MBC5;
+use std::fs::File;
+use std::io::Result;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+/// # Cartridge type
+/// The type of cartridge, as an enum.
+pub enum CartridgeType {
+    RomOnly,
+    MBC1(Box<MBC1>),
+    MBC2(Box<MBC2>),
+    MBC3(Box<MBC3>),
+    MBC5(Box<MBC5>),
+}
+
+/// # Cartridge
+/// A representation of a Game Boy cartridge. Has a [CartridgeType].
+/// Checks the [CartridgeHeader], and detects Memory Bank Controller (MBC) type.
+/// MBC1/2/3/5 implemented in dedicated files.
+pub struct Cartridge {
+    /// Path to the ROM file, if the cartridge was loaded from disk.
+    rom: Option<PathBuf>,
+    pub cart_type: CartridgeType,
+    /// Holds the ROM data in an array of bytes.
+    data: Vec<u8>,
+    /// Hash of the ROM data, to identify it in save states.
+    hash: u64,
+    /// Parsed header.
+    header: CartridgeHeader,
+    /// Problems found when loading the ROM, which did not prevent it from running.
+    warnings: Vec<CartridgeError>,
+    /// Flag to keep track of dirty (unsaved) RAM.
+    dirty: bool,
+    /// Game Genie patches applied to ROM reads.
+    rom_patches: Vec<RomPatch>,
+}
+
+impl Cartridge {
+    /// Loads the cartridge from the ROM file at the given path. The SRAM file
+    /// is placed next to it.
+    pub fn new(rom: &str, skip_checksum: bool) -> std::result::Result<Self, CartridgeError> {
+        let data = std::fs::read(rom)?;
+        let mut cart = Self::from_bytes(data, skip_checksum)?;
+        cart.rom = Some(PathBuf::from(rom));
+        Ok(cart)
+    }
+
+    /// Creates the cartridge from the raw bytes of a ROM. The cartridge has
+    /// no SRAM file attached. Unless `skip_checksum` is set, the logo and
+    /// the header checksum must be right, like the boot ROM requires. A bad
+    /// global checksum, or a short file, only produce warnings.
+    pub fn from_bytes(
+        data: Vec<u8>,
+        skip_checksum: bool,
+    ) -> std::result::Result<Self, CartridgeError> {
+        let header = CartridgeHeader::parse(&data)?;
+        let mut warnings = Vec::new();
+
+        // Check Nintendo logo in ROM file.
+        if !skip_checksum {
+            CartridgeHeader::check_logo(&data)?;
+            println!("{}: Logo sequence", "OK".green());
+        }
+
+        println!("{}: Title: {}", "OK".green(), header.title.bright_blue());
+
+        // Color or not color.
+        if header.is_cgb() {
+            println!("{}: GB Color cartridge", "OK".green());
+        } else {
+            println!("{}: DMG Game Boy cartridge", "OK".green());
+        }
+
+        // Super Game Boy.
+        if header.sgb_flag == 0x03 {
+            println!("{}: Super Game Boy functions supported", "OK".green());
+        }
+
+        println!(
+            " -> Cartridge type: {} ({})",
+            header.cart_type_name().yellow(),
+            header.cart_type
+        );
+        println!(" -> ROM size: {}", header.rom_size_name());
+        println!(" -> RAM size: {}", header.ram_size_name());
+        println!(" -> Destination code: {}", header.destination_name());
+
+        // Header checksum.
+        if !skip_checksum {
+            header.check_header_checksum(&data)?;
+            println!(
+                "{}: Header checksum: {:#04x}",
+                "OK".green(),
+                header.header_checksum
+            );
+        }
+        // Global checksum and size. Real hardware does not check them.
+        if !skip_checksum {
+            for check in [
+                header.check_global_checksum(&data),
+                header.check_size(&data),
+            ] {
+                match check {
+                    Ok(()) => (),
+                    Err(e) => {
+                        println!("{}: {}", "WARN".yellow(), e);
+                        warnings.push(e);
+                    }
+                }
+            }
+            if warnings.is_empty() {
+                println!(
+                    "{}: Global checksum: {:#06x}",
+                    "OK".green(),
+                    header.global_checksum
+                );
+            }
+        }
+
+        // Check supported modes.
+        let cart_type = header.cart_type;
+        let rom_size_code = header.rom_size;
+        let ram_size_code = header.ram_size;
+        let cart_type_enum = match cart_type {
+            0x00 => {
+                println!("{}: Using ROM ONLY mode", "OK".green());
+                CartridgeType::RomOnly
+            }
+            0x01..=0x03 => {
+                println!("{}: Using MBC1 mode", "OK".green());
+                CartridgeType::MBC1(Box::new(MBC1::new(
+                    data.clone(),
+                    rom_size_code,
+                    ram_size_code,
+                )))
+            }
+            0x05 | 0x06 => {
+                println!("{}: Using MBC2 mode", "OK".green());
+                CartridgeType::MBC2(Box::new(MBC2::new(data.clone(), rom_size_code)))
+            }
+            0x0F..=0x13 => {
+                println!("{}: Using MBC3 mode", "OK".green());
+                let has_rtc = cart_type <= 0x10;
+                CartridgeType::MBC3(Box::new(MBC3::new(
+                    data.clone(),
+                    rom_size_code,
+                    ram_size_code,
+                    has_rtc,
+                )))
+            }
+            0x19..=0x1E => {
+                println!("{}: Using MBC5 mode", "OK".green());
+                let has_rumble = cart_type >= 0x1C;
+                CartridgeType::MBC5(Box::new(MBC5::new(
+                    data.clone(),
+                    rom_size_code,
+                    ram_size_code,
+                    has_rumble,
+                )))
+            }
+            _ => return Err(CartridgeError::UnsupportedMapper(cart_type)),
+        };
+
+        Ok(Self {
+            rom: None,
+            cart_type: cart_type_enum,
+            hash: savestate::fnv1a64(&data),
+            data,
+            header,
+            warnings,
+            dirty: false,
+            rom_patches: Vec::new(),
+        })
+    }
+
+    /// Gets the parsed header.
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
+    /// Gets the problems found when loading the ROM that did not prevent
+    /// it from running, like a bad global checksum.
+    pub fn warnings(&self) -> &[CartridgeError] {
+        &self.warnings
+    }
+
+    /// Gets the path of the ROM file, if the cartridge was loaded from disk.
+    pub fn get_rom_path(&self) -> Option<&PathBuf> {
+        self.rom.as_ref()
+    }
+
+    /// Gets the raw title bytes from the header (0x134-0x143).
+    pub fn title_bytes(&self) -> [u8; 16] {
+        let mut title = [0u8; 16];
+        if let Some(slice) = self.data.get(0x134..0x144) {
+            title.copy_from_slice(slice);
+        }
+        title
+    }
+
+    /// Is this cartridge CGB-enhanced or CGB-only? Checks the CGB flag
+    /// in the header (0x143).
+    pub fn is_cgb(&self) -> bool {
+        self.header.is_cgb()
+    }
+
+    /// Does this cartridge support the Super Game Boy functions? Checks the
+    /// SGB flag (0x146), and the old licensee code (0x14B), which must be 0x33.
+    pub fn is_sgb(&self) -> bool {
+        self.header.is_sgb()
+    }
+
+    /// Is this cartridge licensed by Nintendo? Checks the old licensee code
+    /// (0x14B), or the new licensee code (0x144-0x145) when the old one is 0x33.
+    pub fn is_nintendo_licensee(&self) -> bool {
+        match self.data.get(0x14B) {
+            Some(0x01) => true,
+            Some(0x33) => self.data.get(0x144..0x146) == Some(b"01"),
+            _ => false,
+        }
+    }
+
+    /// Gets the hash (FNV-1a) of the ROM data.
+    pub fn rom_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Is the RAM dirty?
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Consume the RAM so that it is clean again.
+    pub fn consume_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    /// ROM read, with the Game Genie patches applied.
+    pub fn read(&self, address: u16) -> u8 {
+        let value = self.read_rom(address);
+        if self.rom_patches.is_empty() {
+            return value;
+        }
+        self.rom_patches
+            .iter()
+            .find_map(|patch| patch.apply(address, value))
+            .unwrap_or(value)
+    }
+
+    /// Sets the Game Genie patches applied to ROM reads.
+    pub fn set_rom_patches(&mut self, patches: Vec<RomPatch>) {
+        self.rom_patches = patches;
+    }
+
+    /// ROM read, from the banks mapped by the MBC.
+    fn read_rom(&self, address: u16) -> u8 {
+        match &self.cart_type {
+            CartridgeType::RomOnly => {
+                if address < self.data.len() as u16 {
+                    self.data[address as usize]
+                } else {
+                    0xFF
+                }
+            }
+            CartridgeType::MBC1(mbc) => mbc.read(address),
+            CartridgeType::MBC2(mbc) => mbc.read(address),
+            CartridgeType::MBC3(mbc) => mbc.read(address),
+            CartridgeType::MBC5(mbc) => mbc.read(address),
+        }
+    }
+
+    /// ROM write.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match &mut self.cart_type {
+            CartridgeType::RomOnly => {
+                // ROM only has no banking, ignore writes
+            }
+            CartridgeType::MBC1(mbc) => mbc.write(address, value),
+            CartridgeType::MBC2(mbc) => mbc.write(address, value),
+            CartridgeType::MBC3(mbc) => mbc.write(address, value),
+            CartridgeType::MBC5(mbc) => mbc.write(address, value),
+        }
+    }
+
+    /// Read from the given RAM address.
+    pub fn read_ram(&self, address: u16) -> u8 {
+        match &self.cart_type {
+            CartridgeType::RomOnly => 0xFF, // No RAM for ROM only
+            CartridgeType::MBC1(mbc) => mbc.read_ram(address),
+            CartridgeType::MBC2(mbc) => mbc.read_ram(address),
+            CartridgeType::MBC3(mbc) => mbc.read_ram(address),
+            CartridgeType::MBC5(mbc) => mbc.read_ram(address),
+        }
+    }
+
+    /// Write value to the given address of RAM.
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        match &mut self.cart_type {
+            CartridgeType::RomOnly => {} // No RAM for ROM only
+            CartridgeType::MBC1(mbc) => mbc.write_ram(address, value),
+            CartridgeType::MBC2(mbc) => mbc.write_ram(address, value),
+            CartridgeType::MBC3(mbc) => mbc.write_ram(address, value),
+            CartridgeType::MBC5(mbc) => mbc.write_ram(address, value),
+        }
+        // Mark RAM dirty.
+        self.dirty = true;
+    }
+
+    /// Advances the clock of the cartridge, if any, by the given T-cycles
+    /// at normal speed.
+    pub fn cycle(&mut self, t_cycles: u64) {
+        if let CartridgeType::MBC3(mbc) = &mut self.cart_type {
+            mbc.cycle(t_cycles);
+        }
+    }
+
+    /// Was the rumble motor on since the last call? Only rumble cartridges
+    /// have a motor.
+    pub fn take_rumble(&mut self) -> bool {
+        match &mut self.cart_type {
+            CartridgeType::MBC5(mbc) => mbc.take_rumble(),
+            _ => false,
+        }
+    }
+
+    /// Get cartridge type as a descriptive string.
+    pub fn cart_type_str(cart_type: u8) -> String {
+        match cart_type {
+            0x00 => "ROM ONLY".to_string(),
+            0x01 => "MBC1".to_string(),
+            0x02 => "MBC1+RAM".to_string(),
+            0x03 => "MBC1+RAM+BATTERY".to_string(),
+            0x05 => "MBC2".to_string(),
+            0x06 => "MBC2+BATTERY".to_string(),
+            0x08 => "ROM+RAM".to_string(),
+            0x09 => "ROM+RAM+BATTERY".to_string(),
+            0x0B => "MMM01".to_string(),
+            0x0C => "MMM01+RAM".to_string(),
+            0x0D => "MMM01+RAM+BATTERY".to_string(),
+            0x0F => "MBC3+TIMER+BATTERY".to_string(),
+            0x10 => "MBC3+TIMER+RAM+BATTERY".to_string(),
+            0x11 => "MBC3".to_string(),
+            0x12 => "MBC3+RAM".to_string(),
+            0x13 => "MBC3+RAM+BATTERY".to_string(),
+            0x19 => "MBC5".to_string(),
+            0x1A => "MBC5+RAM".to_string(),
+            0x1B => "MBC5+RAM+BATTERY".to_string(),
+            0x1C => "MBC5+RUMBLE".to_string(),
+            0x1D => "MBC5+RUMBLE+RAM".to_string(),
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY".to_string(),
+            0x20 => "MBC6".to_string(),
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY".to_string(),
+            0xFC => "POCKET CAMERA".to_string(),
+            0xFD => "BANDAI TAMA5".to_string(),
+            0xFE => "HuC3".to_string(),
+            0xFF => "MuC1+RAM+BATTERY".to_string(),
+            _ => format!("Unknown ({:#04x})", cart_type),
+        }
+    }
+
+    /// Gathers the information of the ROM, for the user.
+    pub fn info(&self) -> RomInfo {
+        // The header was parsed when the cartridge was created, so this does
+        // not fail.
+        RomInfo::new(&self.data, self.rom.as_deref()).expect("ROM header was already parsed")
+    }
+
+    /// Sets the path of the ROM file, which places the SRAM file and the save
+    /// states next to it. Used when the ROM data does not come straight from
+    /// a file, like with archives.
+    pub fn set_rom_path(&mut self, path: PathBuf) {
+        self.rom = Some(path);
+    }
+
+    /// Gets the path of the `.sav` file, if the cartridge was loaded from disk.
+    pub fn get_sram_path(&self) -> Option<PathBuf> {
+        self.rom.as_ref().map(|rom| rom.with_extension("sav"))
+    }
+
+    /// Save SRAM of current cartridge to `.sav` file.
+    pub fn save_sram(&self) {
+        let Some(save_path) = self.get_sram_path() else {
+            return;
+        };
+
+        // Only save if the mapper actually has RAM.
+        let ram_data = match &self.cart_type {
+            CartridgeType::MBC1(mbc) => mbc.get_ram().to_vec(),
+            CartridgeType::MBC2(mbc) => mbc.get_ram().to_vec(),
+            CartridgeType::MBC3(mbc) => {
+                // The RTC goes in a footer after the RAM.
+                let mut data = mbc.get_ram().to_vec();
+                if let Some(footer) = mbc.rtc_footer() {
+                    data.extend_from_slice(&footer);
+                }
+                data
+            }
+            CartridgeType::MBC5(mbc) => mbc.get_ram().to_vec(),
+            _ => return,
+        };
+
+        if (!ram_data.is_empty() || matches!(self.cart_type, CartridgeType::MBC2(_)))
+            && let Ok(mut file) = File::create(&save_path)
+        {
+            let _ = file.write_all(&ram_data);
+            println!(
+                "{}: SRAM written to disk: {}",
+                "WR".magenta(),
+                save_path.display()
+            );
+        }
+    }
+
+    /// Load `.sav` file into SRAM.
+    pub fn load_sram(&mut self) {
+        let Some(save_path) = self.get_sram_path() else {
+            return;
+        };
+
+        if !save_path.exists() {
+            return;
+        }
+
+        if let Ok(mut file) = File::open(&save_path) {
+            let mut buffer = Vec::new();
+            if file.read_to_end(&mut buffer).is_ok() {
+                match &mut self.cart_type {
+                    CartridgeType::MBC1(mbc) => {
+                        if mbc.get_ram().len() == buffer.len() {
+                            mbc.set_ram(buffer);
+                        } else {
+                            println!("{}: SRAM file size mismatch!", "WARN".yellow());
+                        }
+                    }
+                    CartridgeType::MBC2(mbc) => mbc.set_ram(&buffer),
+                    CartridgeType::MBC3(mbc) => {
+                        let ram_size = mbc.get_ram().len();
+                        if buffer.len() >= ram_size && buffer.len() <= ram_size + RTC_FOOTER_SIZE {
+                            // The RTC footer, if any, follows the RAM.
+                            mbc.load_rtc_footer(&buffer[ram_size..]);
+                            buffer.truncate(ram_size);
+                            mbc.set_ram(buffer);
+                        } else {
+                            println!("{}: SRAM file size mismatch!", "WARN".yellow());
+                        }
+                    }
+                    CartridgeType::MBC5(mbc) => {
+                        if mbc.get_ram().len() == buffer.len() {
+                            mbc.set_ram(buffer);
+                        } else {
+                            println!("{}: SRAM file size mismatch!", "WARN".yellow());
+                        }
+                    }
+                    _ => (),
+                }
+
+                println!(
+                    "{}: SRAM loaded from disk: {}",
+                    "LD".magenta(),
+                    save_path.display()
+                );
+            }
+        }
+    }
+}
+
+impl SaveState for Cartridge {
+    fn save_state(&self, w: &mut StateWriter) {
+        match &self.cart_type {
+            CartridgeType::RomOnly => (),
+            CartridgeType::MBC1(mbc) => mbc.save_state(w),
+            CartridgeType::MBC2(mbc) => mbc.save_state(w),
+            CartridgeType::MBC3(mbc) => mbc.save_state(w),
+            CartridgeType::MBC5(mbc) => mbc.save_state(w),
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<()> {
+        match &mut self.cart_type {
+            CartridgeType::RomOnly => (),
+            CartridgeType::MBC1(mbc) => mbc.load_state(r)?,
+            CartridgeType::MBC2(mbc) => mbc.load_state(r)?,
+            CartridgeType::MBC3(mbc) => mbc.load_state(r)?,
+            CartridgeType::MBC5(mbc) => mbc.load_state(r)?,
+        }
+        // The RAM may differ from the one on disk now.
+        self.dirty = true;
+        Ok(())
+    }
+}
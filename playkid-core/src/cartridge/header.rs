@@ -0,0 +1,324 @@
+use super::Cartridge;
+use super::mbc3::RTC_FOOTER_SIZE;
+use std::fmt;
+
+/// Size of the header, which ends at 0x14F.
+pub const HEADER_END: usize = 0x150;
+
+/// Game Boy logo sequence.
+const LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// # Cartridge error
+/// Everything that can be wrong with a ROM file.
+#[derive(Debug)]
+pub enum CartridgeError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file is shorter than the header, or than the ROM size in it.
+    Truncated { size: usize, expected: usize },
+    /// The Nintendo logo in 0x104-0x133 does not match.
+    BadLogo,
+    /// The header checksum in 0x14D does not match.
+    BadHeaderChecksum { stored: u8, computed: u8 },
+    /// The global checksum in 0x14E-0x14F does not match. Real hardware does
+    /// not check it.
+    BadGlobalChecksum { stored: u16, computed: u16 },
+    /// The cartridge type in 0x147 is not supported.
+    UnsupportedMapper(u8),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(e) => write!(f, "{}", e),
+            CartridgeError::Truncated { size, expected } => write!(
+                f,
+                "ROM file is truncated: {} bytes, expected {}",
+                size, expected
+            ),
+            CartridgeError::BadLogo => write!(f, "Incorrect Nintendo logo sequence in 0x104-0x133"),
+            CartridgeError::BadHeaderChecksum { stored, computed } => write!(
+                f,
+                "Header checksum incorrect: {:#04x} != {:#04x}",
+                stored, computed
+            ),
+            CartridgeError::BadGlobalChecksum { stored, computed } => write!(
+                f,
+                "Global checksum incorrect: {:#06x} != {:#06x}",
+                stored, computed
+            ),
+            CartridgeError::UnsupportedMapper(t) => write!(
+                f,
+                "Unsupported cartridge type: {}",
+                Cartridge::cart_type_str(*t)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CartridgeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(e: std::io::Error) -> Self {
+        CartridgeError::Io(e)
+    }
+}
+
+impl From<CartridgeError> for std::io::Error {
+    fn from(e: CartridgeError) -> Self {
+        match e {
+            CartridgeError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+/// # Cartridge header
+/// The header of a ROM, in 0x100-0x14F. It describes the game and the
+/// hardware in the cartridge.
+#[derive(Debug, Clone)]
+pub struct CartridgeHeader {
+    /// Title, in upper case ASCII.
+    pub title: String,
+    /// CGB flag (0x143). Bit 7 means CGB-enhanced, and 0xC0 CGB-only.
+    pub cgb_flag: u8,
+    /// New licensee code (0x144-0x145), used when the old one is 0x33.
+    pub new_licensee: [u8; 2],
+    /// SGB flag (0x146). 0x03 means SGB functions are supported.
+    pub sgb_flag: u8,
+    /// Cartridge type (0x147), which gives the mapper.
+    pub cart_type: u8,
+    /// ROM size code (0x148).
+    pub rom_size: u8,
+    /// RAM size code (0x149).
+    pub ram_size: u8,
+    /// Destination code (0x14A): 0 for Japan, 1 for overseas.
+    pub destination: u8,
+    /// Old licensee code (0x14B).
+    pub old_licensee: u8,
+    /// Mask ROM version number (0x14C).
+    pub version: u8,
+    /// Header checksum (0x14D).
+    pub header_checksum: u8,
+    /// Global checksum (0x14E-0x14F, big-endian).
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    /// Parses the header of the given ROM data.
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.len() < HEADER_END {
+            return Err(CartridgeError::Truncated {
+                size: data.len(),
+                expected: HEADER_END,
+            });
+        }
+        let cgb_flag = data[0x143];
+        // The CGB flag takes the last byte of the title.
+        let title_end = if cgb_flag & 0x80 != 0 { 0x143 } else { 0x144 };
+        let title = data[0x134..title_end]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| {
+                if c.is_ascii_graphic() || c == b' ' {
+                    c as char
+                } else {
+                    '?'
+                }
+            })
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+        Ok(Self {
+            title,
+            cgb_flag,
+            new_licensee: [data[0x144], data[0x145]],
+            sgb_flag: data[0x146],
+            cart_type: data[0x147],
+            rom_size: data[0x148],
+            ram_size: data[0x149],
+            destination: data[0x14A],
+            old_licensee: data[0x14B],
+            version: data[0x14C],
+            header_checksum: data[0x14D],
+            global_checksum: u16::from_be_bytes([data[0x14E], data[0x14F]]),
+        })
+    }
+
+    /// Checks the Nintendo logo, which the boot ROM compares.
+    pub fn check_logo(data: &[u8]) -> Result<(), CartridgeError> {
+        match data.get(0x104..0x134) {
+            Some(logo) if logo == LOGO => Ok(()),
+            _ => Err(CartridgeError::BadLogo),
+        }
+    }
+
+    /// Checks the header checksum, which the boot ROM verifies.
+    pub fn check_header_checksum(&self, data: &[u8]) -> Result<(), CartridgeError> {
+        let computed = Self::compute_header_checksum(data);
+        if computed == self.header_checksum {
+            Ok(())
+        } else {
+            Err(CartridgeError::BadHeaderChecksum {
+                stored: self.header_checksum,
+                computed,
+            })
+        }
+    }
+
+    /// Checks the global checksum. Real hardware does not.
+    pub fn check_global_checksum(&self, data: &[u8]) -> Result<(), CartridgeError> {
+        let computed = Self::compute_global_checksum(data);
+        if computed == self.global_checksum {
+            Ok(())
+        } else {
+            Err(CartridgeError::BadGlobalChecksum {
+                stored: self.global_checksum,
+                computed,
+            })
+        }
+    }
+
+    /// Computes the header checksum of the given ROM data, which must
+    /// contain the whole header.
+    pub fn compute_header_checksum(data: &[u8]) -> u8 {
+        data[0x134..0x14D]
+            .iter()
+            .fold(0u8, |cs, &b| cs.wrapping_sub(b).wrapping_sub(1))
+    }
+
+    /// Computes the global checksum of the given ROM data: the sum of all
+    /// its bytes, except the checksum itself.
+    pub fn compute_global_checksum(data: &[u8]) -> u16 {
+        data.iter()
+            .enumerate()
+            .filter(|(address, _)| *address != 0x14E && *address != 0x14F)
+            .fold(0u16, |cs, (_, &b)| cs.wrapping_add(b as u16))
+    }
+
+    /// Checks that the data is as long as the ROM size in the header.
+    pub fn check_size(&self, data: &[u8]) -> Result<(), CartridgeError> {
+        match self.rom_bytes() {
+            Some(expected) if data.len() < expected => Err(CartridgeError::Truncated {
+                size: data.len(),
+                expected,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// ROM size in bytes, if the size code is known.
+    pub fn rom_bytes(&self) -> Option<usize> {
+        match self.rom_size {
+            0x00..=0x08 => Some(32768 << self.rom_size),
+            0x52 => Some(72 * 16384),
+            0x53 => Some(80 * 16384),
+            0x54 => Some(96 * 16384),
+            _ => None,
+        }
+    }
+
+    /// SRAM size in bytes, from the RAM size code. MBC2 has 512 half-bytes
+    /// built in.
+    pub fn ram_bytes(&self) -> usize {
+        match (self.cart_type, self.ram_size) {
+            (0x05 | 0x06, _) => 512,
+            (_, 0x01) => 2 * 1024,
+            (_, 0x02) => 8 * 1024,
+            (_, 0x03) => 32 * 1024,
+            (_, 0x04) => 128 * 1024,
+            (_, 0x05) => 64 * 1024,
+            _ => 0,
+        }
+    }
+
+    /// Size in bytes of the `.sav` file Play Kid writes: the SRAM, followed
+    /// by the RTC footer for MBC3 cartridges with a timer.
+    pub fn save_file_bytes(&self) -> usize {
+        match self.cart_type {
+            0x0F | 0x10 => self.ram_bytes() + RTC_FOOTER_SIZE,
+            _ => self.ram_bytes(),
+        }
+    }
+
+    /// Gets the mapper Play Kid uses for the cartridge type, if it is
+    /// supported.
+    pub fn mapper(&self) -> Option<&'static str> {
+        match self.cart_type {
+            0x00 => Some("ROM ONLY"),
+            0x01..=0x03 => Some("MBC1"),
+            0x05 | 0x06 => Some("MBC2"),
+            0x0F..=0x13 => Some("MBC3"),
+            0x19..=0x1E => Some("MBC5"),
+            _ => None,
+        }
+    }
+
+    /// Describes the cartridge type.
+    pub fn cart_type_name(&self) -> String {
+        Cartridge::cart_type_str(self.cart_type)
+    }
+
+    /// Describes the ROM size.
+    pub fn rom_size_name(&self) -> String {
+        match self.rom_size {
+            0 => "32 KiB (2 banks)".to_string(),
+            1 => "64 KiB (4 banks)".to_string(),
+            2 => "128 KiB (8 banks)".to_string(),
+            3 => "256 KiB".to_string(),
+            4 => "512 KiB".to_string(),
+            5 => "1 MiB".to_string(),
+            6 => "2 MiB".to_string(),
+            7 => "4 MiB".to_string(),
+            8 => "8 MiB".to_string(),
+            0x52 => "1.1 MiB".to_string(),
+            0x53 => "1.2 MiB".to_string(),
+            0x54 => "1.5 MiB".to_string(),
+            rs => format!("Unknown ({:#04x})", rs),
+        }
+    }
+
+    /// Describes the RAM size.
+    pub fn ram_size_name(&self) -> String {
+        match self.ram_size {
+            0 => "No RAM".to_string(),
+            1 => "Error, unused value!".to_string(),
+            2 => "8 KiB (1 bank)".to_string(),
+            3 => "32 KiB (4 banks of 8 KiB each)".to_string(),
+            4 => "128 KiB (16 banks of 8 KiB each)".to_string(),
+            5 => "64 KiB (8 banks of 8 KiB each)".to_string(),
+            rs => format!("Unknown ({:#04x})", rs),
+        }
+    }
+
+    /// Describes the destination code.
+    pub fn destination_name(&self) -> String {
+        match self.destination {
+            0 => "Japan".to_string(),
+            1 => "Overseas only".to_string(),
+            dc => format!("Unknown ({:#04x})", dc),
+        }
+    }
+
+    /// Is this cartridge CGB-enhanced or CGB-only?
+    pub fn is_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    /// Does this cartridge support the Super Game Boy functions? The SGB
+    /// also requires the old licensee code to be 0x33.
+    pub fn is_sgb(&self) -> bool {
+        self.sgb_flag == 0x03 && self.old_licensee == 0x33
+    }
+}
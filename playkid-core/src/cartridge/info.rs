@@ -0,0 +1,463 @@
+use super::{CartridgeError, CartridgeHeader};
+use std::path::{Path, PathBuf};
+
+/// # ROM information
+/// Everything there is to know about a ROM file, for the user: the header,
+/// the licensee, the mapper, the checksums, and the file sizes. Unlike
+/// loading the cartridge, it does not fail on a bad logo or bad checksums,
+/// so that broken dumps can be inspected too.
+#[derive(Debug, Clone)]
+pub struct RomInfo {
+    /// Path to the ROM file, if any.
+    pub rom_path: Option<PathBuf>,
+    /// Size of the ROM data, in bytes.
+    pub rom_file_size: usize,
+    /// Parsed header.
+    pub header: CartridgeHeader,
+    /// Name of the licensee, from the old or the new code.
+    pub licensee: String,
+    /// Mapper Play Kid uses, or none if the cartridge type is not supported.
+    pub mapper: Option<&'static str>,
+    /// Whether the Nintendo logo is right.
+    pub logo_ok: bool,
+    /// Header checksum computed from the data.
+    pub header_checksum: u8,
+    /// Global checksum computed from the data.
+    pub global_checksum: u16,
+    /// Path to the `.sav` file, if the ROM has a path.
+    pub sram_path: Option<PathBuf>,
+    /// Size of the `.sav` file, if it exists.
+    pub sram_file_size: Option<u64>,
+}
+
+impl RomInfo {
+    /// Gathers the information of the given ROM data, loaded from the given
+    /// path, if any. Fails only if the data is too short for a header.
+    pub fn new(data: &[u8], rom_path: Option<&Path>) -> Result<Self, CartridgeError> {
+        let header = CartridgeHeader::parse(data)?;
+        let sram_path = rom_path.map(|rom| rom.with_extension("sav"));
+        let sram_file_size = sram_path
+            .as_ref()
+            .and_then(|sav| std::fs::metadata(sav).ok())
+            .map(|m| m.len());
+        Ok(Self {
+            rom_path: rom_path.map(Path::to_path_buf),
+            rom_file_size: data.len(),
+            licensee: licensee_name(&header),
+            mapper: header.mapper(),
+            logo_ok: CartridgeHeader::check_logo(data).is_ok(),
+            header_checksum: CartridgeHeader::compute_header_checksum(data),
+            global_checksum: CartridgeHeader::compute_global_checksum(data),
+            header,
+            sram_path,
+            sram_file_size,
+        })
+    }
+
+    /// Reads the ROM file at the given path, and gathers its information.
+    pub fn from_file(path: &Path) -> Result<Self, CartridgeError> {
+        let data = std::fs::read(path)?;
+        Self::new(&data, Some(path))
+    }
+
+    /// Whether the stored header checksum matches the computed one.
+    pub fn header_checksum_ok(&self) -> bool {
+        self.header_checksum == self.header.header_checksum
+    }
+
+    /// Whether the stored global checksum matches the computed one.
+    pub fn global_checksum_ok(&self) -> bool {
+        self.global_checksum == self.header.global_checksum
+    }
+
+    /// Lists the information as label and value pairs, for display.
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let h = &self.header;
+        let ok = |ok: bool| if ok { "OK" } else { "MISMATCH" };
+        let rom_expected = match h.rom_bytes() {
+            Some(expected) => format!("{} bytes expected", expected),
+            None => "unknown size expected".to_string(),
+        };
+        vec![
+            (
+                "ROM file",
+                self.rom_path
+                    .as_ref()
+                    .map_or("-".to_string(), |p| p.display().to_string()),
+            ),
+            (
+                "ROM file size",
+                format!("{} bytes ({})", self.rom_file_size, rom_expected),
+            ),
+            ("Title", h.title.clone()),
+            ("Version", format!("{}", h.version)),
+            ("Licensee", self.licensee.clone()),
+            (
+                "Licensee code",
+                if h.old_licensee == 0x33 {
+                    format!("0x33, new code {}", new_licensee_code(h))
+                } else {
+                    format!("{:#04x}", h.old_licensee)
+                },
+            ),
+            ("Destination", h.destination_name()),
+            (
+                "CGB flag",
+                format!(
+                    "{:#04x} ({})",
+                    h.cgb_flag,
+                    match h.cgb_flag {
+                        0xC0 => "CGB only",
+                        f if f & 0x80 != 0 => "CGB enhanced",
+                        _ => "DMG",
+                    }
+                ),
+            ),
+            (
+                "SGB flag",
+                format!(
+                    "{:#04x} ({})",
+                    h.sgb_flag,
+                    if h.is_sgb() { "SGB" } else { "no SGB" }
+                ),
+            ),
+            ("Cartridge type", h.cart_type_name()),
+            ("Mapper", self.mapper.unwrap_or("Unsupported").to_string()),
+            ("ROM size", h.rom_size_name()),
+            ("RAM size", h.ram_size_name()),
+            ("Nintendo logo", ok(self.logo_ok).to_string()),
+            (
+                "Header checksum",
+                format!(
+                    "stored {:#04x}, computed {:#04x} ({})",
+                    h.header_checksum,
+                    self.header_checksum,
+                    ok(self.header_checksum_ok())
+                ),
+            ),
+            (
+                "Global checksum",
+                format!(
+                    "stored {:#06x}, computed {:#06x} ({})",
+                    h.global_checksum,
+                    self.global_checksum,
+                    ok(self.global_checksum_ok())
+                ),
+            ),
+            (
+                "SRAM file",
+                self.sram_path
+                    .as_ref()
+                    .map_or("-".to_string(), |p| p.display().to_string()),
+            ),
+            (
+                "SRAM file size",
+                match self.sram_file_size {
+                    Some(size) => format!("{} bytes ({} expected)", size, h.save_file_bytes()),
+                    None => format!("no file ({} bytes expected)", h.save_file_bytes()),
+                },
+            ),
+        ]
+    }
+
+    /// Writes the information as a JSON object.
+    pub fn to_json(&self) -> String {
+        let h = &self.header;
+        let path = |p: &Option<PathBuf>| {
+            p.as_ref().map_or("null".to_string(), |p| {
+                json_string(&p.display().to_string())
+            })
+        };
+        let fields = [
+            ("rom_path", path(&self.rom_path)),
+            ("rom_file_size", self.rom_file_size.to_string()),
+            (
+                "rom_size_expected",
+                h.rom_bytes().map_or("null".to_string(), |s| s.to_string()),
+            ),
+            ("title", json_string(&h.title)),
+            ("version", h.version.to_string()),
+            ("licensee", json_string(&self.licensee)),
+            ("old_licensee", h.old_licensee.to_string()),
+            ("new_licensee", json_string(&new_licensee_code(h))),
+            ("destination", h.destination.to_string()),
+            ("destination_name", json_string(&h.destination_name())),
+            ("cgb_flag", h.cgb_flag.to_string()),
+            ("sgb_flag", h.sgb_flag.to_string()),
+            ("cart_type", h.cart_type.to_string()),
+            ("cart_type_name", json_string(&h.cart_type_name())),
+            (
+                "mapper",
+                self.mapper.map_or("null".to_string(), json_string),
+            ),
+            ("rom_size", h.rom_size.to_string()),
+            ("rom_size_name", json_string(&h.rom_size_name())),
+            ("ram_size", h.ram_size.to_string()),
+            ("ram_size_name", json_string(&h.ram_size_name())),
+            ("logo_ok", self.logo_ok.to_string()),
+            (
+                "header_checksum",
+                format!(
+                    "{{\"stored\": {}, \"computed\": {}, \"ok\": {}}}",
+                    h.header_checksum,
+                    self.header_checksum,
+                    self.header_checksum_ok()
+                ),
+            ),
+            (
+                "global_checksum",
+                format!(
+                    "{{\"stored\": {}, \"computed\": {}, \"ok\": {}}}",
+                    h.global_checksum,
+                    self.global_checksum,
+                    self.global_checksum_ok()
+                ),
+            ),
+            ("sram_path", path(&self.sram_path)),
+            (
+                "sram_file_size",
+                self.sram_file_size
+                    .map_or("null".to_string(), |s| s.to_string()),
+            ),
+            ("sram_size_expected", h.save_file_bytes().to_string()),
+        ];
+        let body = fields
+            .iter()
+            .map(|(key, value)| format!("  \"{}\": {}", key, value))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        format!("{{\n{}\n}}", body)
+    }
+}
+
+/// Quotes and escapes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Gets the new licensee code as text. Bytes that are not printable become
+/// '?'.
+fn new_licensee_code(header: &CartridgeHeader) -> String {
+    header
+        .new_licensee
+        .iter()
+        .map(|&c| if c.is_ascii_graphic() { c as char } else { '?' })
+        .collect()
+}
+
+/// Gets the name of the licensee. The old code 0x33 means that the new,
+/// two-character code is used instead.
+pub fn licensee_name(header: &CartridgeHeader) -> String {
+    let name = if header.old_licensee == 0x33 {
+        new_licensee_name(&header.new_licensee)
+    } else {
+        old_licensee_name(header.old_licensee)
+    };
+    name.unwrap_or("Unknown").to_string()
+}
+
+/// Names of the old licensee codes (0x14B).
+fn old_licensee_name(code: u8) -> Option<&'static str> {
+    Some(match code {
+        0x00 => "None",
+        0x01 | 0x31 => "Nintendo",
+        0x08 | 0x38 => "Capcom",
+        0x09 => "HOT-B",
+        0x0A | 0xE0 => "Jaleco",
+        0x0B => "Coconuts Japan",
+        0x0C | 0x6E => "Elite Systems",
+        0x13 | 0x69 => "EA (Electronic Arts)",
+        0x18 => "Hudson Soft",
+        0x19 => "ITC Entertainment",
+        0x1A => "Yanoman",
+        0x1D => "Japan Clary",
+        0x1F | 0x4A | 0x61 => "Virgin Games Ltd.",
+        0x24 => "PCM Complete",
+        0x25 => "San-X",
+        0x28 | 0x7F | 0x97 | 0xC2 => "Kemco",
+        0x29 => "SETA Corporation",
+        0x30 | 0x70 => "Infogrames",
+        0x32 | 0xA2 | 0xB2 => "Bandai",
+        0x34 | 0xA4 => "Konami",
+        0x35 => "HectorSoft",
+        0x39 | 0x9D | 0xD9 => "Banpresto",
+        0x3C => "Entertainment Interactive",
+        0x3E => "Gremlin",
+        0x41 => "Ubi Soft",
+        0x42 | 0xEB => "Atlus",
+        0x44 | 0x4D => "Malibu Interactive",
+        0x46 | 0xCF => "Angel",
+        0x47 => "Spectrum HoloByte",
+        0x49 => "Irem",
+        0x4F => "U.S. Gold",
+        0x50 => "Absolute",
+        0x51 | 0xB0 => "Acclaim Entertainment",
+        0x52 => "Activision",
+        0x53 => "Sammy USA Corporation",
+        0x54 => "GameTek",
+        0x55 => "Park Place",
+        0x56 | 0xDB | 0xFF => "LJN",
+        0x57 => "Matchbox",
+        0x59 => "Milton Bradley Company",
+        0x5A => "Mindscape",
+        0x5B => "Romstar",
+        0x5C | 0xD6 => "Naxat Soft",
+        0x5D => "Tradewest",
+        0x60 => "Titus Interactive",
+        0x67 => "Ocean Software",
+        0x6F => "Electro Brain",
+        0x71 => "Interplay Entertainment",
+        0x72 | 0xAA => "Broderbund",
+        0x73 => "Sculptured Software",
+        0x75 => "The Sales Curve Limited",
+        0x78 => "THQ",
+        0x79 => "Accolade",
+        0x7A => "Triffix Entertainment",
+        0x7C => "MicroProse",
+        0x80 => "Misawa Entertainment",
+        0x83 => "LOZC G.",
+        0x86 | 0xC4 => "Tokuma Shoten",
+        0x8B => "Bullet-Proof Software",
+        0x8C => "Vic Tokai Corp.",
+        0x8E => "Ape Inc.",
+        0x8F => "I'Max",
+        0x91 => "Chunsoft Co.",
+        0x92 => "Video System",
+        0x93 => "Tsubaraya Productions",
+        0x95 | 0xE3 => "Varie",
+        0x96 => "Yonezawa/S'Pal",
+        0x99 => "Arc",
+        0x9A => "Nihon Bussan",
+        0x9B => "Tecmo",
+        0x9C => "Imagineer",
+        0x9F => "Nova",
+        0xA1 => "Hori Electric",
+        0xA6 => "Kawada",
+        0xA7 => "Takara",
+        0xA9 => "Technos Japan",
+        0xAC => "Toei Animation",
+        0xAD => "Toho",
+        0xAF => "Namco",
+        0xB1 => "ASCII Corporation or Nexsoft",
+        0xB4 => "Square Enix",
+        0xB6 => "HAL Laboratory",
+        0xB7 => "SNK",
+        0xB9 | 0xCE => "Pony Canyon",
+        0xBA => "Culture Brain",
+        0xBB => "Sunsoft",
+        0xBD => "Sony Imagesoft",
+        0xBF => "Sammy Corporation",
+        0xC0 | 0xD0 => "Taito",
+        0xC3 => "Square",
+        0xC5 => "Data East",
+        0xC6 => "Tonkin House",
+        0xC8 => "Koei",
+        0xC9 => "UFL",
+        0xCA => "Ultra Games",
+        0xCB => "VAP, Inc.",
+        0xCC => "Use Corporation",
+        0xCD => "Meldac",
+        0xD1 => "SOFEL",
+        0xD2 => "Quest",
+        0xD3 => "Sigma Enterprises",
+        0xD4 => "ASK Kodansha Co.",
+        0xD7 => "Copya System",
+        0xDA => "Tomy",
+        0xDD => "Nippon Computer Systems",
+        0xDE => "Human Ent.",
+        0xDF => "Altron",
+        0xE1 => "Towa Chiki",
+        0xE2 => "Yutaka",
+        0xE5 => "Epoch",
+        0xE7 => "Athena",
+        0xE8 => "Asmik Ace Entertainment",
+        0xE9 => "Natsume",
+        0xEA => "King Records",
+        0xEC => "Epic/Sony Records",
+        0xEE => "IGS",
+        0xF0 => "A Wave",
+        0xF3 => "Extreme Entertainment",
+        _ => return None,
+    })
+}
+
+/// Names of the new licensee codes (0x144-0x145).
+fn new_licensee_name(code: &[u8; 2]) -> Option<&'static str> {
+    Some(match code {
+        b"00" => "None",
+        b"01" => "Nintendo Research & Development 1",
+        b"08" => "Capcom",
+        b"13" | b"69" => "EA (Electronic Arts)",
+        b"18" | b"38" => "Hudson Soft",
+        b"19" => "B-AI",
+        b"20" => "KSS",
+        b"22" => "Planning Office WADA",
+        b"24" => "PCM Complete",
+        b"25" => "San-X",
+        b"28" => "Kemco",
+        b"29" => "SETA Corporation",
+        b"30" => "Viacom",
+        b"31" => "Nintendo",
+        b"32" => "Bandai",
+        b"33" | b"93" => "Ocean Software/Acclaim Entertainment",
+        b"34" | b"54" => "Konami",
+        b"35" => "HectorSoft",
+        b"37" => "Taito",
+        b"39" => "Banpresto",
+        b"41" => "Ubi Soft",
+        b"42" => "Atlus",
+        b"44" => "Malibu Interactive",
+        b"46" => "Angel",
+        b"47" => "Bullet-Proof Software",
+        b"49" => "Irem",
+        b"50" => "Absolute",
+        b"51" => "Acclaim Entertainment",
+        b"52" => "Activision",
+        b"53" => "Sammy USA Corporation",
+        b"55" => "Hi Tech Expressions",
+        b"56" => "LJN",
+        b"57" => "Matchbox",
+        b"58" => "Mattel",
+        b"59" => "Milton Bradley Company",
+        b"60" => "Titus Interactive",
+        b"61" => "Virgin Games Ltd.",
+        b"64" => "Lucasfilm Games",
+        b"67" => "Ocean Software",
+        b"70" => "Infogrames",
+        b"71" => "Interplay Entertainment",
+        b"72" => "Broderbund",
+        b"73" => "Sculptured Software",
+        b"75" => "The Sales Curve Limited",
+        b"78" => "THQ",
+        b"79" => "Accolade",
+        b"80" => "Misawa Entertainment",
+        b"83" => "LOZC G.",
+        b"86" => "Tokuma Shoten",
+        b"87" => "Tsukuda Original",
+        b"91" => "Chunsoft Co.",
+        b"92" => "Video System",
+        b"95" => "Varie",
+        b"96" => "Yonezawa/S'Pal",
+        b"97" => "Kaneko",
+        b"99" => "Pack-In-Video",
+        b"9H" => "Bottom Up",
+        b"A4" => "Konami (Yu-Gi-Oh!)",
+        b"BL" => "MTO",
+        b"DK" => "Kodansha",
+        _ => return None,
+    })
+}
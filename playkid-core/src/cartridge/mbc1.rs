@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 /// MBC1 Memory Bank Controller.
 pub struct MBC1 {
     rom: Vec<u8>,
@@ -168,3 +170,22 @@ impl MBC1 {
         }
     }
 }
+
+impl SaveState for MBC1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.usize(self.rom_bank);
+        w.usize(self.ram_bank);
+        w.bool(self.ram_enabled);
+        w.u8(self.banking_mode);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.ram)?;
+        self.rom_bank = r.usize()? & 0x7F;
+        self.ram_bank = r.usize()? & 0x03;
+        self.ram_enabled = r.bool()?;
+        self.banking_mode = r.u8()? & 0x01;
+        Ok(())
+    }
+}
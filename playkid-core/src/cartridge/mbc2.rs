@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 /// MBC2 Memory Bank Controller.
 pub struct MBC2 {
     rom: Vec<u8>,
@@ -86,3 +88,18 @@ impl MBC2 {
         self.ram[..len].copy_from_slice(&data[..len]);
     }
 }
+
+impl SaveState for MBC2 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.u8(self.rom_bank);
+        w.bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.ram)?;
+        self.rom_bank = r.u8()? & 0x0F;
+        self.ram_enabled = r.bool()?;
+        Ok(())
+    }
+}
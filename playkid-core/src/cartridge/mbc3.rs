@@ -0,0 +1,539 @@
+use crate::constants::CPU_FREQ_HZ;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size of the RTC footer appended to the `.sav` file.
+pub const RTC_FOOTER_SIZE: usize = 48;
+
+/// MBC3 Memory Bank Controller.
+#[derive(Debug)]
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_bank: usize,
+    /// The RAM bank, also used to select RTC registers.
+    ram_bank: usize,
+    ram_enabled: bool,
+    /// The cartridge has a Real Time Clock.
+    has_rtc: bool,
+    /// Real Time Clock.
+    rtc: Rtc,
+}
+
+/// The RTC registers, as seen by the game.
+#[derive(Debug, Clone, Copy, Default)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days_low: u8,
+    /// - Bit 0: bit 8 of the day counter
+    /// - Bit 6: halt
+    /// - Bit 7: day counter carry
+    days_high: u8,
+}
+
+/// # Real Time Clock
+/// The clock ticks with the emulated time while running. While the emulator
+/// is closed, it catches up with the wall-clock time using the timestamp
+/// stored in the `.sav` file.
+#[derive(Debug, Default)]
+struct Rtc {
+    /// Registers that tick.
+    live: RtcRegisters,
+    /// Registers read by the game, copied from `live` on latch.
+    latched: RtcRegisters,
+    /// T-cycles into the current second.
+    cycles: u64,
+    /// Last value written to the latch register.
+    latch: u8,
+}
+
+impl RtcRegisters {
+    fn days(&self) -> u64 {
+        self.days_low as u64 | ((self.days_high as u64 & 0x01) << 8)
+    }
+
+    fn set_days(&mut self, days: u64) {
+        self.days_low = days as u8;
+        self.days_high = (self.days_high & 0xFE) | ((days >> 8) & 0x01) as u8;
+    }
+
+    fn is_halted(&self) -> bool {
+        self.days_high & 0x40 != 0
+    }
+
+    /// Advances the clock by one second. Registers with values out of range
+    /// wrap around at their bit width, without carrying into the next one,
+    /// like the hardware does.
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.add_days(1);
+    }
+
+    /// Adds days to the day counter, setting the carry flag on overflow.
+    fn add_days(&mut self, days: u64) {
+        let days = self.days() + days;
+        if days > 0x1FF {
+            self.days_high |= 0x80;
+        }
+        self.set_days(days & 0x1FF);
+    }
+
+    /// Advances the clock by the given number of seconds.
+    fn advance(&mut self, mut seconds: u64) {
+        // Out of range values only tick back into range one by one.
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.tick_second();
+            seconds -= 1;
+        }
+        let total =
+            self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600 + seconds;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        self.add_days(total / 86400);
+    }
+}
+
+impl Rtc {
+    /// Advances the clock by the given T-cycles.
+    fn cycle(&mut self, t_cycles: u64) {
+        if self.live.is_halted() {
+            return;
+        }
+        self.cycles += t_cycles;
+        while self.cycles >= CPU_FREQ_HZ as u64 {
+            self.cycles -= CPU_FREQ_HZ as u64;
+            self.live.tick_second();
+        }
+    }
+
+    /// Reads the latched register selected with the given RAM bank.
+    fn read(&self, register: usize) -> u8 {
+        match register {
+            0x08 => self.latched.seconds,
+            0x09 => self.latched.minutes,
+            0x0A => self.latched.hours,
+            0x0B => self.latched.days_low,
+            0x0C => self.latched.days_high,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes the live register selected with the given RAM bank.
+    fn write(&mut self, register: usize, value: u8) {
+        match register {
+            0x08 => {
+                // Writing the seconds resets the sub-second counter.
+                self.live.seconds = value & 0x3F;
+                self.cycles = 0;
+            }
+            0x09 => self.live.minutes = value & 0x3F,
+            0x0A => self.live.hours = value & 0x1F,
+            0x0B => self.live.days_low = value,
+            0x0C => self.live.days_high = value & 0xC1,
+            _ => {}
+        }
+    }
+
+    /// Writes the latch register. Writing 0 and then 1 copies the live
+    /// registers to the latched ones.
+    fn write_latch(&mut self, value: u8) {
+        if self.latch == 0 && value == 1 {
+            self.latched = self.live;
+        }
+        self.latch = value;
+    }
+
+    /// Serializes the clock to the de-facto standard RTC footer of the
+    /// `.sav` file: the live and latched registers as little-endian u32,
+    /// followed by the UNIX timestamp as u64.
+    fn to_footer(&self) -> [u8; RTC_FOOTER_SIZE] {
+        let mut footer = [0u8; RTC_FOOTER_SIZE];
+        let registers = [self.live, self.latched];
+        let values = registers
+            .iter()
+            .flat_map(|r| [r.seconds, r.minutes, r.hours, r.days_low, r.days_high]);
+        for (chunk, value) in footer.chunks_exact_mut(4).zip(values) {
+            chunk.copy_from_slice(&(value as u32).to_le_bytes());
+        }
+        footer[40..48].copy_from_slice(&now().to_le_bytes());
+        footer
+    }
+
+    /// Restores the clock from an RTC footer, and catches up with the
+    /// wall-clock time elapsed since it was written. Both the 48-byte
+    /// footer and the older 44-byte one (with a 32-bit timestamp) are
+    /// supported.
+    fn load_footer(&mut self, footer: &[u8]) {
+        let value = |i: usize| footer[i * 4];
+        let read = |i: usize| RtcRegisters {
+            seconds: value(i) & 0x3F,
+            minutes: value(i + 1) & 0x3F,
+            hours: value(i + 2) & 0x1F,
+            days_low: value(i + 3),
+            days_high: value(i + 4) & 0xC1,
+        };
+        self.live = read(0);
+        self.latched = read(5);
+        let timestamp = if footer.len() >= 48 {
+            u64::from_le_bytes(footer[40..48].try_into().unwrap())
+        } else {
+            u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64
+        };
+        if !self.live.is_halted() {
+            self.live.advance(now().saturating_sub(timestamp));
+        }
+    }
+}
+
+/// Current UNIX time, in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl MBC3 {
+    pub fn new(rom_data: Vec<u8>, rom_size_code: u8, ram_size_code: u8, has_rtc: bool) -> Self {
+        let rom_size = match rom_size_code {
+            0x00..=0x08 => 32768 << rom_size_code,
+            _ => 32 * 1024,
+        };
+
+        let ram_size = match ram_size_code {
+            0x01 => 2 * 1024,
+            0x02 => 8 * 1024,
+            0x03 => 32 * 1024,
+            0x04 => 128 * 1024,
+            0x05 => 64 * 1024,
+            _ => 0,
+        };
+
+        let mut rom = vec![0; rom_size];
+        let data_len = rom_data.len().min(rom_size);
+        rom[..data_len].copy_from_slice(&rom_data[..data_len]);
+
+        Self {
+            rom,
+            ram: vec![0xFF; ram_size],
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            has_rtc,
+            rtc: Rtc::default(),
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let offset = (self.rom_bank * 0x4000) + (address as usize - 0x4000);
+                self.rom[offset % self.rom.len()]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM/RTC Enable.
+            0x0000..=0x1FFF => self.ram_enabled = (value & 0x0F) == 0x0A,
+
+            // ROM Bank Select (Full 7 bits, bank 0 becomes 1).
+            0x2000..=0x3FFF => {
+                let mut bank = (value & 0x7F) as usize;
+                if bank == 0 {
+                    bank = 1;
+                }
+                self.rom_bank = bank;
+            }
+
+            // RAM Bank / RTC Register Select.
+            0x4000..=0x5FFF => {
+                self.ram_bank = value as usize;
+            }
+
+            // Latch Clock Data.
+            0x6000..=0x7FFF => self.rtc.write_latch(value),
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match self.ram_bank {
+            0x00..=0x03 => {
+                // Standard RAM Banks.
+                let offset = (self.ram_bank * 0x2000) + (address as usize - 0xA000);
+                let len = self.ram.len();
+                if len > 0 {
+                    self.ram[offset % len]
+                } else {
+                    0xFF
+                }
+            }
+            0x08..=0x0C if self.has_rtc => self.rtc.read(self.ram_bank),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+
+        match self.ram_bank {
+            0x00..=0x03 => {
+                let offset = (self.ram_bank * 0x2000) + (address as usize - 0xA000);
+                let len = self.ram.len();
+                if len > 0 {
+                    self.ram[offset % len] = value;
+                }
+            }
+            0x08..=0x0C if self.has_rtc => self.rtc.write(self.ram_bank, value),
+            _ => {}
+        }
+    }
+
+    /// Advances the RTC by the given T-cycles.
+    pub fn cycle(&mut self, t_cycles: u64) {
+        if self.has_rtc {
+            self.rtc.cycle(t_cycles);
+        }
+    }
+
+    /// Gets the RTC footer for the `.sav` file, if the cartridge has an RTC.
+    pub fn rtc_footer(&self) -> Option<[u8; RTC_FOOTER_SIZE]> {
+        self.has_rtc.then(|| self.rtc.to_footer())
+    }
+
+    /// Restores the RTC from the footer of a `.sav` file.
+    pub fn load_rtc_footer(&mut self, footer: &[u8]) {
+        if self.has_rtc && footer.len() >= 44 {
+            self.rtc.load_footer(footer);
+        }
+    }
+
+    pub fn get_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn set_ram(&mut self, data: Vec<u8>) {
+        if data.len() == self.ram.len() {
+            self.ram = data;
+        }
+    }
+}
+
+impl SaveState for MBC3 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.usize(self.rom_bank);
+        w.usize(self.ram_bank);
+        w.bool(self.ram_enabled);
+        for r in [self.rtc.live, self.rtc.latched] {
+            w.u8(r.seconds);
+            w.u8(r.minutes);
+            w.u8(r.hours);
+            w.u8(r.days_low);
+            w.u8(r.days_high);
+        }
+        w.u64(self.rtc.cycles);
+        w.u8(self.rtc.latch);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.ram)?;
+        self.rom_bank = r.usize()? & 0x7F;
+        self.ram_bank = r.usize()? & 0xFF;
+        self.ram_enabled = r.bool()?;
+        for registers in [&mut self.rtc.live, &mut self.rtc.latched] {
+            registers.seconds = r.u8()?;
+            registers.minutes = r.u8()?;
+            registers.hours = r.u8()?;
+            registers.days_low = r.u8()?;
+            registers.days_high = r.u8()?;
+        }
+        self.rtc.cycles = r.u64()?;
+        self.rtc.latch = r.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an MBC3 cartridge with an RTC and no RAM, with the RTC
+    /// registers mapped in.
+    fn timer_cart() -> MBC3 {
+        let mut mbc = MBC3::new(vec![0; 0x8000], 0x00, 0x00, true);
+        mbc.write(0x0000, 0x0A);
+        mbc
+    }
+
+    /// Reads an RTC register through the cartridge, after latching it.
+    fn read_register(mbc: &mut MBC3, register: u8) -> u8 {
+        mbc.write(0x6000, 0);
+        mbc.write(0x6000, 1);
+        mbc.write(0x4000, register);
+        mbc.read_ram(0xA000)
+    }
+
+    #[test]
+    fn day_counter_carry() {
+        let mut r = RtcRegisters {
+            seconds: 59,
+            minutes: 59,
+            hours: 23,
+            days_low: 0xFF,
+            days_high: 0x01,
+        };
+        r.tick_second();
+        assert_eq!((r.seconds, r.minutes, r.hours, r.days()), (0, 0, 0, 0));
+        assert_eq!(r.days_high & 0x80, 0x80);
+        // The carry stays set until the game clears it.
+        r.advance(86400);
+        assert_eq!(r.days(), 1);
+        assert_eq!(r.days_high & 0x80, 0x80);
+    }
+
+    #[test]
+    fn out_of_range_wrap() {
+        // Out of range registers wrap at their bit width, without carry.
+        let mut r = RtcRegisters {
+            seconds: 63,
+            minutes: 10,
+            ..Default::default()
+        };
+        r.tick_second();
+        assert_eq!((r.seconds, r.minutes), (0, 10));
+        let mut r = RtcRegisters {
+            seconds: 59,
+            minutes: 59,
+            hours: 31,
+            ..Default::default()
+        };
+        r.tick_second();
+        assert_eq!((r.seconds, r.minutes, r.hours, r.days()), (0, 0, 0, 0));
+        // Advancing ticks them back into range one by one first.
+        let mut r = RtcRegisters {
+            seconds: 62,
+            ..Default::default()
+        };
+        r.advance(3);
+        assert_eq!((r.seconds, r.minutes), (1, 0));
+    }
+
+    #[test]
+    fn advance_many_seconds() {
+        let mut r = RtcRegisters::default();
+        r.advance(2 * 86400 + 3600 + 60 + 1);
+        assert_eq!((r.seconds, r.minutes, r.hours, r.days()), (1, 1, 1, 2));
+        assert_eq!(r.days_high & 0x80, 0);
+        r.advance(510 * 86400);
+        assert_eq!(r.days(), 0);
+        assert_eq!(r.days_high & 0x80, 0x80);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut mbc = timer_cart();
+        mbc.cycle(CPU_FREQ_HZ as u64 - 1);
+        assert_eq!(read_register(&mut mbc, 0x08), 0);
+        mbc.cycle(1);
+        assert_eq!(read_register(&mut mbc, 0x08), 1);
+        // Halt.
+        mbc.write(0x4000, 0x0C);
+        mbc.write_ram(0xA000, 0x40);
+        mbc.cycle(10 * CPU_FREQ_HZ as u64);
+        assert_eq!(read_register(&mut mbc, 0x08), 1);
+        assert_eq!(read_register(&mut mbc, 0x0C), 0x40);
+        // Resume.
+        mbc.write(0x4000, 0x0C);
+        mbc.write_ram(0xA000, 0x00);
+        mbc.cycle(CPU_FREQ_HZ as u64);
+        assert_eq!(read_register(&mut mbc, 0x08), 2);
+    }
+
+    #[test]
+    fn latch_needs_zero_then_one() {
+        let mut mbc = timer_cart();
+        assert_eq!(read_register(&mut mbc, 0x08), 0);
+        mbc.cycle(3 * CPU_FREQ_HZ as u64);
+        // Writing 1 again without 0 first keeps the old values.
+        mbc.write(0x6000, 1);
+        assert_eq!(mbc.read_ram(0xA000), 0);
+        assert_eq!(read_register(&mut mbc, 0x08), 3);
+    }
+
+    #[test]
+    fn footer_round_trip() {
+        let mut mbc = timer_cart();
+        mbc.write(0x4000, 0x0A);
+        mbc.write_ram(0xA000, 5);
+        // Halted, so that no time passes between save and load.
+        mbc.write(0x4000, 0x0C);
+        mbc.write_ram(0xA000, 0x41);
+        let footer = mbc.rtc_footer().unwrap();
+        let mut loaded = timer_cart();
+        loaded.load_rtc_footer(&footer);
+        assert_eq!(read_register(&mut loaded, 0x0A), 5);
+        assert_eq!(read_register(&mut loaded, 0x0C), 0x41);
+        // Carts without a timer have no footer.
+        assert!(
+            MBC3::new(vec![0; 0x8000], 0, 0, false)
+                .rtc_footer()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn footer_catches_up() {
+        // 44-byte footer, with a 32-bit timestamp from 1 day, 1 hour,
+        // 1 minute, and 1 second ago.
+        let mut footer = [0u8; 44];
+        let timestamp = now() - (86400 + 3600 + 60 + 1);
+        footer[40..44].copy_from_slice(&(timestamp as u32).to_le_bytes());
+        let mut mbc = timer_cart();
+        mbc.load_rtc_footer(&footer);
+        assert_eq!(read_register(&mut mbc, 0x0B), 1);
+        assert_eq!(read_register(&mut mbc, 0x0A), 1);
+        assert_eq!(read_register(&mut mbc, 0x09), 1);
+        // A second can pass while the test runs.
+        assert!((1..=2).contains(&read_register(&mut mbc, 0x08)));
+        // Shorter footers are ignored.
+        let mut mbc = timer_cart();
+        mbc.load_rtc_footer(&footer[..40]);
+        assert_eq!(read_register(&mut mbc, 0x0B), 0);
+    }
+
+    #[test]
+    fn no_ram_reads_open_bus() {
+        let mut mbc = timer_cart();
+        mbc.write(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+}
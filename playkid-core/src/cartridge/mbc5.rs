@@ -0,0 +1,145 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+/// MBC5 Memory Bank Controller.
+/// Up to 512 ROM banks (8 MiB) and 16 RAM banks (128 KiB). On rumble
+/// cartridges, bit 3 of the RAM bank register drives the motor, so only 8
+/// RAM banks are available.
+#[derive(Debug)]
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    /// 9-bit ROM bank. Unlike the other MBCs, bank 0 can be mapped to 0x4000.
+    rom_bank: usize,
+    ram_bank: usize,
+    ram_enabled: bool,
+    /// The cartridge has a rumble motor.
+    has_rumble: bool,
+    /// The rumble motor is on.
+    rumble: bool,
+    /// The rumble motor was on at some point since the last query.
+    rumble_latch: bool,
+}
+
+impl MBC5 {
+    pub fn new(rom_data: Vec<u8>, rom_size_code: u8, ram_size_code: u8, has_rumble: bool) -> Self {
+        let rom_size = match rom_size_code {
+            0x00..=0x08 => 32768 << rom_size_code,
+            _ => 32 * 1024,
+        };
+
+        let ram_size = match ram_size_code {
+            0x02 => 8 * 1024,
+            0x03 => 32 * 1024,
+            0x04 => 128 * 1024,
+            0x05 => 64 * 1024,
+            _ => 0,
+        };
+
+        let mut rom = vec![0; rom_size];
+        let data_len = rom_data.len().min(rom_size);
+        rom[..data_len].copy_from_slice(&rom_data[..data_len]);
+
+        Self {
+            rom,
+            ram: vec![0xFF; ram_size],
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            has_rumble,
+            rumble: false,
+            rumble_latch: false,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let offset = (self.rom_bank * 0x4000) + (address as usize - 0x4000);
+                self.rom[offset % self.rom.len()]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM Enable. Unlike MBC1, the full byte must be 0x0A.
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+
+            // ROM Bank Select, lower 8 bits.
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as usize,
+
+            // ROM Bank Select, bit 8.
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (((value & 0x01) as usize) << 8)
+            }
+
+            // RAM Bank Select, and rumble motor.
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = value & 0x08 != 0;
+                    self.rumble_latch |= self.rumble;
+                    self.ram_bank = (value & 0x07) as usize;
+                } else {
+                    self.ram_bank = (value & 0x0F) as usize;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+        let offset = (self.ram_bank * 0x2000) + (address as usize - 0xA000);
+        self.ram[offset % self.ram.len()]
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let offset = (self.ram_bank * 0x2000) + (address as usize - 0xA000);
+        let len = self.ram.len();
+        self.ram[offset % len] = value;
+    }
+
+    /// Was the rumble motor on since the last call? Games drive the motor
+    /// with pulses, so this reports any pulse in between.
+    pub fn take_rumble(&mut self) -> bool {
+        let rumble = self.rumble || self.rumble_latch;
+        self.rumble_latch = false;
+        rumble
+    }
+
+    pub fn get_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn set_ram(&mut self, data: Vec<u8>) {
+        if data.len() == self.ram.len() {
+            self.ram = data;
+        }
+    }
+}
+
+impl SaveState for MBC5 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.usize(self.rom_bank);
+        w.usize(self.ram_bank);
+        w.bool(self.ram_enabled);
+        w.bool(self.rumble);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.ram)?;
+        self.rom_bank = r.usize()? & 0x1FF;
+        self.ram_bank = r.usize()? & 0x0F;
+        self.ram_enabled = r.bool()?;
+        self.rumble = r.bool()?;
+        Ok(())
+    }
+}
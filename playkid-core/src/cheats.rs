@@ -0,0 +1,446 @@
+use colored::Colorize;
+use std::fmt;
+use std::path::Path;
+
+/// First line of the cheat files.
+const CHEATS_HEADER: &str = "# Play Kid cheats";
+
+/// # ROM patch
+/// A Game Genie code. Reads of the ROM address give the new value instead,
+/// but only if the original value matches the compare value, if any. The
+/// compare value tells apart the ROM banks mapped at the same address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomPatch {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl RomPatch {
+    /// Applies the patch to a value read from the given ROM address.
+    pub fn apply(&self, address: u16, original: u8) -> Option<u8> {
+        (address == self.address && self.compare.is_none_or(|c| c == original))
+            .then_some(self.value)
+    }
+}
+
+/// # Cheat code
+/// A single cheat code, decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatCode {
+    /// Game Genie code, `ABC-DEF` or `ABC-DEF-GHI`.
+    GameGenie(RomPatch),
+    /// GameShark code, `01VVAAAA`: writes the value `VV` to the address
+    /// `AAAA` (little-endian) every frame.
+    GameShark { address: u16, value: u8 },
+    /// Raw freeze, `ADDR=VV`: writes the value to the WRAM or HRAM address
+    /// every frame.
+    Freeze { address: u16, value: u8 },
+}
+
+/// # Cheat error
+/// Everything that can be wrong with a cheat code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheatError {
+    /// The cheat has no code.
+    Empty,
+    /// The code is not a Game Genie, GameShark, or freeze code.
+    InvalidCode(String),
+    /// Game Genie codes only patch the ROM, in 0x0000-0x7FFF.
+    NotRomAddress(u16),
+    /// GameShark codes and freezes only write to RAM.
+    NotRamAddress(u16),
+    /// Only GameShark codes of type 01 are supported.
+    UnsupportedGameShark(u8),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::Empty => write!(f, "No code given"),
+            CheatError::InvalidCode(code) => write!(
+                f,
+                "Invalid code '{}', expected ABC-DEF(-GHI), 01VVAAAA, or ADDR=VV",
+                code
+            ),
+            CheatError::NotRomAddress(a) => {
+                write!(f, "Game Genie address ${:04X} is not in the ROM", a)
+            }
+            CheatError::NotRamAddress(a) => write!(f, "Address ${:04X} is not in RAM", a),
+            CheatError::UnsupportedGameShark(t) => {
+                write!(f, "Unsupported GameShark code type {:02X}, expected 01", t)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+impl CheatCode {
+    /// Decodes a code. Spaces are ignored, and letters can be in any case.
+    pub fn parse(code: &str) -> Result<Self, CheatError> {
+        let code: String = code
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_uppercase();
+        let invalid = || CheatError::InvalidCode(code.clone());
+        if code.is_empty() {
+            return Err(CheatError::Empty);
+        }
+
+        // Freeze.
+        if let Some((address, value)) = code.split_once('=') {
+            let address = parse_hex(address).ok_or_else(invalid)?;
+            let value = parse_hex(value)
+                .and_then(|v| u8::try_from(v).ok())
+                .ok_or_else(invalid)?;
+            return match address {
+                0xC000..=0xDFFF | 0xFF80..=0xFFFE => Ok(CheatCode::Freeze { address, value }),
+                _ => Err(CheatError::NotRamAddress(address)),
+            };
+        }
+
+        let digits: Vec<u8> = code
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        match digits.len() {
+            // Game Genie: ABC-DEF(-GHI). AB is the value, FCDE the address,
+            // with F inverted, and GI the compare value, XORed with 0xBA and
+            // rotated left by 2.
+            6 | 9 => {
+                let value = (digits[0] << 4) | digits[1];
+                let address = (((digits[5] ^ 0x0F) as u16) << 12)
+                    | ((digits[2] as u16) << 8)
+                    | ((digits[3] as u16) << 4)
+                    | digits[4] as u16;
+                if address >= 0x8000 {
+                    return Err(CheatError::NotRomAddress(address));
+                }
+                let compare = (digits.len() == 9)
+                    .then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(CheatCode::GameGenie(RomPatch {
+                    address,
+                    value,
+                    compare,
+                }))
+            }
+            // GameShark: 01VVAAAA.
+            8 => {
+                let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+                let kind = byte(0);
+                if kind != 0x01 {
+                    return Err(CheatError::UnsupportedGameShark(kind));
+                }
+                let value = byte(2);
+                let address = u16::from_le_bytes([byte(4), byte(6)]);
+                match address {
+                    0xA000..=0xDFFF | 0xFF80..=0xFFFE => {
+                        Ok(CheatCode::GameShark { address, value })
+                    }
+                    _ => Err(CheatError::NotRamAddress(address)),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Writes the code that freezes the given RAM address to the value: a
+    /// freeze for WRAM and HRAM, and a GameShark code for cartridge RAM.
+    pub fn freeze_code(address: u16, value: u8) -> String {
+        match address {
+            0xC000..=0xDFFF | 0xFF80..=0xFFFE => format!("{:04X}={:02X}", address, value),
+            _ => {
+                let [low, high] = address.to_le_bytes();
+                format!("01{:02X}{:02X}{:02X}", value, low, high)
+            }
+        }
+    }
+
+    /// Gets the RAM write of the code, if it is a GameShark code or a
+    /// freeze.
+    pub fn ram_write(&self) -> Option<(u16, u8)> {
+        match *self {
+            CheatCode::GameShark { address, value } | CheatCode::Freeze { address, value } => {
+                Some((address, value))
+            }
+            CheatCode::GameGenie(_) => None,
+        }
+    }
+}
+
+/// Parses a hex number, with an optional `$` or `0x` prefix.
+fn parse_hex(s: &str) -> Option<u16> {
+    let digits = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// # Cheat
+/// A named cheat, made of one or more codes separated by `+`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    /// The codes, as entered by the user, without spaces.
+    pub code: String,
+    pub enabled: bool,
+    codes: Vec<CheatCode>,
+}
+
+impl Cheat {
+    /// Creates an enabled cheat with the given name and codes.
+    pub fn new(name: &str, code: &str) -> Result<Self, CheatError> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let codes = code
+            .split('+')
+            .map(CheatCode::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            name: name.trim().to_string(),
+            code: code.to_ascii_uppercase(),
+            enabled: true,
+            codes,
+        })
+    }
+
+    /// Gets the decoded codes.
+    pub fn codes(&self) -> &[CheatCode] {
+        &self.codes
+    }
+}
+
+/// # Cheat list
+/// The cheats of a ROM. They are saved to a text file, with one cheat per
+/// line: `[x]` or `[ ]` for enabled or disabled, the codes, and the name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheatList {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the cheats from the file at the given path.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Saves the cheats to the file at the given path.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    /// Parses the contents of a cheat file. Invalid lines are skipped with
+    /// a warning.
+    pub fn parse(text: &str) -> Self {
+        let mut cheats = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, rest) = if let Some(rest) = line.strip_prefix("[x]") {
+                (true, rest)
+            } else if let Some(rest) = line.strip_prefix("[ ]") {
+                (false, rest)
+            } else {
+                (true, line)
+            };
+            let rest = rest.trim_start();
+            let (code, name) = rest.split_once(' ').unwrap_or((rest, ""));
+            match Cheat::new(name, code) {
+                Ok(mut cheat) => {
+                    cheat.enabled = enabled;
+                    cheats.push(cheat);
+                }
+                Err(e) => println!("{}: Skipping cheat '{}': {}", "WARN".yellow(), line, e),
+            }
+        }
+        Self { cheats }
+    }
+
+    /// Writes the cheats in the format of the cheat files.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", CHEATS_HEADER);
+        for cheat in &self.cheats {
+            let state = if cheat.enabled { "[x]" } else { "[ ]" };
+            text.push_str(&format!("{} {} {}\n", state, cheat.code, cheat.name));
+        }
+        text
+    }
+
+    /// Adds a cheat, enabled.
+    pub fn add(&mut self, name: &str, code: &str) -> Result<(), CheatError> {
+        self.cheats.push(Cheat::new(name, code)?);
+        Ok(())
+    }
+
+    /// Removes the cheat at the given index.
+    pub fn remove(&mut self, index: usize) {
+        if index < self.cheats.len() {
+            self.cheats.remove(index);
+        }
+    }
+
+    /// Enables or disables the cheat at the given index.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+        }
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    /// Gets the codes of the enabled cheats.
+    fn enabled_codes(&self) -> impl Iterator<Item = &CheatCode> {
+        self.cheats
+            .iter()
+            .filter(|c| c.enabled)
+            .flat_map(|c| c.codes.iter())
+    }
+
+    /// Gets the ROM patches of the enabled Game Genie codes.
+    pub fn rom_patches(&self) -> Vec<RomPatch> {
+        self.enabled_codes()
+            .filter_map(|c| match c {
+                CheatCode::GameGenie(patch) => Some(*patch),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Gets the RAM writes of the enabled GameShark codes and freezes.
+    pub fn ram_writes(&self) -> Vec<(u16, u8)> {
+        self.enabled_codes()
+            .filter_map(CheatCode::ram_write)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie_six_digits() {
+        // Value 3E, address 091A: F is inverted, so F becomes 0.
+        let code = CheatCode::parse("3E9-1AF").unwrap();
+        assert_eq!(
+            code,
+            CheatCode::GameGenie(RomPatch {
+                address: 0x091A,
+                value: 0x3E,
+                compare: None,
+            })
+        );
+        // Spaces and lowercase are accepted.
+        assert_eq!(CheatCode::parse("3e9 1af").unwrap(), code);
+    }
+
+    #[test]
+    fn game_genie_nine_digits() {
+        // GI is A2, rotated right by 2 is A8, XOR BA is 12. H is ignored.
+        let code = CheatCode::parse("3E9-1AF-AB2").unwrap();
+        let CheatCode::GameGenie(patch) = code else {
+            panic!("not a Game Genie code: {:?}", code);
+        };
+        assert_eq!(patch.address, 0x091A);
+        assert_eq!(patch.value, 0x3E);
+        assert_eq!(patch.compare, Some(0x12));
+        assert_eq!(patch.apply(0x091A, 0x12), Some(0x3E));
+        assert_eq!(patch.apply(0x091A, 0x13), None);
+        assert_eq!(patch.apply(0x091B, 0x12), None);
+    }
+
+    #[test]
+    fn game_genie_out_of_rom() {
+        // F is 0, inverted to F: address F91A.
+        assert_eq!(
+            CheatCode::parse("3E9-1A0"),
+            Err(CheatError::NotRomAddress(0xF91A))
+        );
+    }
+
+    #[test]
+    fn gameshark() {
+        assert_eq!(
+            CheatCode::parse("01FF25C1"),
+            Ok(CheatCode::GameShark {
+                address: 0xC125,
+                value: 0xFF,
+            })
+        );
+        assert_eq!(
+            CheatCode::parse("02FF25C1"),
+            Err(CheatError::UnsupportedGameShark(0x02))
+        );
+        assert_eq!(
+            CheatCode::parse("01FF0080"),
+            Err(CheatError::NotRamAddress(0x8000))
+        );
+    }
+
+    #[test]
+    fn freeze() {
+        assert_eq!(
+            CheatCode::parse("C100=63"),
+            Ok(CheatCode::Freeze {
+                address: 0xC100,
+                value: 0x63,
+            })
+        );
+        assert_eq!(
+            CheatCode::parse("$FF80=1"),
+            Ok(CheatCode::Freeze {
+                address: 0xFF80,
+                value: 0x01,
+            })
+        );
+        assert_eq!(
+            CheatCode::parse("A000=01"),
+            Err(CheatError::NotRamAddress(0xA000))
+        );
+        assert!(matches!(
+            CheatCode::parse("C100=100"),
+            Err(CheatError::InvalidCode(_))
+        ));
+        assert_eq!(CheatCode::parse(" "), Err(CheatError::Empty));
+        assert!(matches!(
+            CheatCode::parse("XYZ"),
+            Err(CheatError::InvalidCode(_))
+        ));
+    }
+
+    #[test]
+    fn freeze_code() {
+        assert_eq!(CheatCode::freeze_code(0xC100, 0x63), "C100=63");
+        assert_eq!(CheatCode::freeze_code(0xA012, 0x05), "010512A0");
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut list = CheatList::new();
+        list.add("Infinite lives", "C100=63").unwrap();
+        list.add("Big jump", "3E9-1AF-AB2 + 01FF25C1").unwrap();
+        list.add("", "3E9-1AF").unwrap();
+        list.set_enabled(1, false);
+        let parsed = CheatList::parse(&list.to_text());
+        assert_eq!(parsed, list);
+        assert_eq!(parsed.cheats()[1].codes().len(), 2);
+        assert!(!parsed.cheats()[1].enabled);
+        assert_eq!(parsed.ram_writes(), vec![(0xC100, 0x63)]);
+        assert_eq!(parsed.rom_patches().len(), 1);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::cartridge::Cartridge;
+
+/// Compatibility palettes of the CGB boot ROM, 4 RGB555 colors each.
+const PALETTES: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, // 0
+    0x639F, 0x4279, 0x15B0, 0x04CB, // 1
+    0x7FFF, 0x6E31, 0x454A, 0x0000, // 2
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, // 3
+    0x7FFF, 0x421F, 0x1CF2, 0x0000, // 4
+    0x7FFF, 0x5294, 0x294A, 0x0000, // 5
+    0x7FFF, 0x03FF, 0x012F, 0x0000, // 6
+    0x7FFF, 0x03EF, 0x01D6, 0x0000, // 7
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000, // 8
+    0x7E74, 0x03FF, 0x0180, 0x0000, // 9
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, // 10
+    0x7ED6, 0x4BFF, 0x2175, 0x0000, // 11
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, // 12
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, // 13
+    0x03ED, 0x7FFF, 0x255F, 0x0000, // 14
+    0x036A, 0x021F, 0x03FF, 0x7FFF, // 15
+    0x7FFF, 0x01DF, 0x0112, 0x0000, // 16
+    0x231F, 0x035F, 0x00F2, 0x0009, // 17
+    0x7FFF, 0x03EA, 0x011F, 0x0000, // 18
+    0x299F, 0x001A, 0x000C, 0x0000, // 19
+    0x7FFF, 0x027F, 0x001F, 0x0000, // 20
+    0x7FFF, 0x03E0, 0x0206, 0x0120, // 21
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00, // 22
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F, // 23
+    0x7FFF, 0x03FF, 0x001F, 0x0000, // 24
+    0x03FF, 0x001F, 0x000C, 0x0000, // 25
+    0x7FFF, 0x033F, 0x0193, 0x0000, // 26
+    0x0000, 0x4200, 0x037F, 0x7FFF, // 27
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000, // 28
+    0x7FFF, 0x1BEF, 0x6180, 0x0000, // 29
+];
+
+/// Palette combinations, as color offsets into [PALETTES] for OBJ0, OBJ1,
+/// and BG. Like in the boot ROM, some of them start in the middle of a
+/// palette.
+const COMBINATIONS: [(usize, usize, usize); 51] = [
+    (4 * 4, 4 * 4, 29 * 4),
+    (18 * 4, 18 * 4, 18 * 4),
+    (20 * 4, 20 * 4, 20 * 4),
+    (24 * 4, 24 * 4, 24 * 4),
+    (9 * 4, 9 * 4, 9 * 4),
+    (0, 0, 0),
+    (27 * 4, 27 * 4, 27 * 4),
+    (5 * 4, 5 * 4, 5 * 4),
+    (12 * 4, 12 * 4, 12 * 4),
+    (26 * 4, 26 * 4, 26 * 4),
+    (16 * 4, 8 * 4, 8 * 4),
+    (4 * 4, 28 * 4, 28 * 4),
+    (4 * 4, 2 * 4, 2 * 4),
+    (3 * 4, 4 * 4, 4 * 4),
+    (4 * 4, 29 * 4, 29 * 4),
+    (28 * 4, 4 * 4, 28 * 4),
+    (2 * 4, 17 * 4, 2 * 4),
+    (16 * 4, 16 * 4, 8 * 4),
+    (4 * 4, 4 * 4, 7 * 4),
+    (4 * 4, 4 * 4, 18 * 4),
+    (4 * 4, 4 * 4, 20 * 4),
+    (19 * 4, 19 * 4, 9 * 4),
+    (4 * 4 - 1, 4 * 4 - 1, 11 * 4),
+    (17 * 4, 17 * 4, 2 * 4),
+    (4 * 4, 4 * 4, 2 * 4),
+    (4 * 4, 4 * 4, 3 * 4),
+    (28 * 4, 28 * 4, 0),
+    (3 * 4, 3 * 4, 0),
+    (0, 0, 4),
+    (18 * 4, 22 * 4, 18 * 4),
+    (20 * 4, 22 * 4, 20 * 4),
+    (24 * 4, 22 * 4, 24 * 4),
+    (16 * 4, 22 * 4, 8 * 4),
+    (17 * 4, 4 * 4, 13 * 4),
+    (28 * 4 - 1, 0, 14 * 4),
+    (28 * 4 - 1, 4 * 4, 15 * 4),
+    (19 * 4, 22 * 4, 9 * 4),
+    (16 * 4, 28 * 4, 10 * 4),
+    (4 * 4, 23 * 4, 28 * 4),
+    (17 * 4, 22 * 4, 2 * 4),
+    (4 * 4, 0, 2 * 4),
+    (4 * 4, 28 * 4, 3 * 4),
+    (28 * 4, 3 * 4, 0),
+    (3 * 4, 28 * 4, 4 * 4),
+    (21 * 4, 28 * 4, 4 * 4),
+    (3 * 4, 28 * 4, 0),
+    (25 * 4, 3 * 4, 28 * 4),
+    (0, 28 * 4, 8 * 4),
+    (4 * 4, 3 * 4, 28 * 4),
+    (28 * 4, 3 * 4, 6 * 4),
+    (4 * 4, 28 * 4, 29 * 4),
+];
+
+/// Title checksums known by the boot ROM. The checksums from
+/// [FIRST_DUPLICATE] on are shared by several games, which are told apart
+/// by the fourth letter of the title.
+const TITLE_CHECKSUMS: [u8; 79] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4,
+];
+
+/// Index of the first checksum that is shared by several games.
+const FIRST_DUPLICATE: usize = 65;
+
+/// Fourth letters of the titles with shared checksums, in rows of
+/// `TITLE_CHECKSUMS.len() - FIRST_DUPLICATE` letters.
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// Palette combination of every known title.
+const COMBINATION_PER_TITLE: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 34, 23, 18, 29, 28,
+];
+
+/// # Colorization
+/// Separate BG, OBJ0, and OBJ1 palettes for a DMG game, as RGB888 colors.
+/// The CGB boot ROM picks them for DMG games licensed by Nintendo, by
+/// looking the title checksum up in a compatibility table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Colorization {
+    pub bg: [u8; 12],
+    pub obj0: [u8; 12],
+    pub obj1: [u8; 12],
+}
+
+impl Colorization {
+    /// Picks the colorization of the given cartridge, like the CGB boot ROM
+    /// does. Unknown games get the default combination.
+    pub fn for_cartridge(cart: &Cartridge) -> Self {
+        Self::from_combination(COMBINATION_PER_TITLE[Self::title_index(cart)] as usize)
+    }
+
+    /// Gets the colorization of a palette combination of the boot ROM.
+    fn from_combination(index: usize) -> Self {
+        let (obj0, obj1, bg) = COMBINATIONS[index];
+        Self {
+            bg: Self::palette(bg),
+            obj0: Self::palette(obj0),
+            obj1: Self::palette(obj1),
+        }
+    }
+
+    /// Converts the 4 colors at the given offset of [PALETTES] to RGB888.
+    fn palette(offset: usize) -> [u8; 12] {
+        let mut colors = [0u8; 12];
+        for (i, rgb) in colors.chunks_exact_mut(3).enumerate() {
+            let c = PALETTES[offset + i];
+            let expand = |v: u16| ((v << 3) | (v >> 2)) as u8;
+            rgb[0] = expand(c & 0x1F);
+            rgb[1] = expand((c >> 5) & 0x1F);
+            rgb[2] = expand((c >> 10) & 0x1F);
+        }
+        colors
+    }
+
+    /// Looks the title of the cartridge up in the compatibility table.
+    /// Returns 0 (the default) if it is not there.
+    fn title_index(cart: &Cartridge) -> usize {
+        if !cart.is_nintendo_licensee() {
+            return 0;
+        }
+        let title = cart.title_bytes();
+        let checksum = title.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+        let Some(index) = TITLE_CHECKSUMS.iter().position(|c| *c == checksum) else {
+            return 0;
+        };
+        if index < FIRST_DUPLICATE {
+            return index;
+        }
+        // Shared checksum: the fourth letter decides.
+        let row = TITLE_CHECKSUMS.len() - FIRST_DUPLICATE;
+        (index - FIRST_DUPLICATE..FOURTH_LETTERS.len())
+            .step_by(row)
+            .find(|i| FOURTH_LETTERS[*i] == title[3])
+            .map_or(0, |i| FIRST_DUPLICATE + i)
+    }
+}
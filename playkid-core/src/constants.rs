@@ -1,6 +1,6 @@
 pub const NAME: &str = "Play Kid";
-/// Work RAM size (8 KiB).
-pub const WRAM_SIZE: usize = 8192;
+/// Work RAM size (8 banks of 4 KiB). The DMG only uses the first two.
+pub const WRAM_SIZE: usize = 8 * 4096;
 /// Video RAM size (8 KiB).
 pub const VRAM_SIZE: usize = 8 * 1024;
 // High RAM size (128 bytes).
@@ -13,6 +13,10 @@ pub const IO_SIZE: usize = 128;
 pub const DISPLAY_WIDTH: usize = 160;
 /// Display height.
 pub const DISPLAY_HEIGHT: usize = 144;
+/// Super Game Boy display width, with the border.
+pub const SGB_DISPLAY_WIDTH: usize = 256;
+/// Super Game Boy display height, with the border.
+pub const SGB_DISPLAY_HEIGHT: usize = 224;
 /// Target frame rate.
 pub const TARGET_FPS: u64 = 60;
 /// Target frame duration.
@@ -20,6 +24,8 @@ pub const TARGET_FRAME_DURATION: std::time::Duration =
     std::time::Duration::from_millis(1000 / TARGET_FPS);
 /// CPU frequency [Hz].
 pub const CPU_FREQ_HZ: usize = 4194304;
+/// Audio sample rate [Hz].
+pub const AUDIO_SAMPLE_RATE: u32 = 44100;
 /// CPU cycles per frame.
 pub const CYCLES_PER_FRAME: usize = (CPU_FREQ_HZ as f64 / TARGET_FPS as f64) as usize;
 /// Maximum number of sprites per line.
@@ -0,0 +1,213 @@
+use crate::ramsearch::ValueSize;
+use colored::Colorize;
+
+/// Debugger commands that run the machine until a condition is met.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunCommand {
+    /// Step one instruction, running CALL and RST to their return.
+    StepOver,
+    /// Run until the current function returns.
+    StepOut,
+    /// Run until the program counter reaches the given address.
+    RunTo(u16),
+    /// Run until the next VBlank entry.
+    StepFrame,
+}
+
+/// Condition that stops a running [RunCommand].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunTarget {
+    /// Stop when the program counter is at `pc` with the stack pointer at
+    /// `sp` or above.
+    Address { pc: u16, sp: u16 },
+    /// Stop after a return that leaves the stack pointer above `sp`.
+    Return { sp: u16 },
+    /// Stop when the PPU enters VBlank.
+    VBlank,
+}
+
+/// A memory address whose value is shown live in the debug panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watch {
+    pub address: u16,
+    pub size: ValueSize,
+}
+
+/// Manage the debug status and debug input events.
+pub struct DebugManager {
+    /// The debug UI is visible.
+    debugging: bool,
+    /// The CPU is waiting for steps.
+    paused: bool,
+    /// Step request.
+    step_instruction: bool,
+    /// Step scanline request.
+    step_line: bool,
+    /// Breakpoints list.
+    breakpoints: Vec<u16>,
+    /// Watches list.
+    watches: Vec<Watch>,
+    /// Run command request.
+    run_command: Option<RunCommand>,
+    /// Target of the run command in progress.
+    run_target: Option<RunTarget>,
+    /// Address selected in the disassembly, for run-to-cursor.
+    cursor: Option<u16>,
+}
+
+impl DebugManager {
+    pub fn new(active: bool) -> Self {
+        Self {
+            debugging: active,
+            paused: active,
+            step_instruction: false,
+            step_line: false,
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            run_command: None,
+            run_target: None,
+            cursor: None,
+        }
+    }
+
+    pub fn toggle_debugging(&mut self) -> bool {
+        self.debugging = !self.debugging;
+        self.debugging
+    }
+
+    pub fn is_debugging(&self) -> bool {
+        self.debugging
+    }
+
+    pub fn set_paused(&mut self, p: bool) {
+        self.paused = p;
+        if p {
+            self.run_target = None;
+        }
+    }
+
+    pub fn toggle_paused(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn get_breakpoints_vec(&self) -> &Vec<u16> {
+        &self.breakpoints
+    }
+
+    pub fn has_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if !self.has_breakpoint(addr) {
+            println!("{}: Add breakpoint: {:#04x}", "OK".green(), addr);
+            self.breakpoints.push(addr);
+        }
+    }
+    pub fn delete_breakpoint(&mut self, addr: u16) {
+        if self.has_breakpoint(addr) {
+            println!("{}: Remove breakpoint: {:#04x}", "OK".green(), addr);
+            self.breakpoints.retain(|&x| x != addr);
+        }
+    }
+
+    pub fn toggle_breakpoint(&mut self, addr: u16) {
+        if self.has_breakpoint(addr) {
+            self.delete_breakpoint(addr);
+        } else {
+            self.add_breakpoint(addr);
+        }
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        if !self.breakpoints.is_empty() {
+            println!("{}: Clear breakpoints", "OK".green());
+            self.breakpoints.clear();
+        }
+    }
+
+    pub fn get_watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    pub fn add_watch(&mut self, watch: Watch) {
+        if !self.watches.contains(&watch) {
+            println!("{}: Add watch: {:#04x}", "OK".green(), watch.address);
+            self.watches.push(watch);
+        }
+    }
+
+    pub fn delete_watch(&mut self, watch: Watch) {
+        if self.watches.contains(&watch) {
+            println!("{}: Remove watch: {:#04x}", "OK".green(), watch.address);
+            self.watches.retain(|&w| w != watch);
+        }
+    }
+
+    pub fn request_step_instruction(&mut self) {
+        if self.debugging {
+            self.step_instruction = true;
+        }
+    }
+
+    pub fn request_step_scanline(&mut self) {
+        if self.debugging {
+            self.step_line = true;
+        }
+    }
+
+    /// Requests a run command. Only honored while paused.
+    pub fn request_run(&mut self, command: RunCommand) {
+        if self.debugging && self.paused {
+            self.run_command = Some(command);
+        }
+    }
+
+    /// Requests running to the cursor, if there is one.
+    pub fn request_run_to_cursor(&mut self) {
+        if let Some(addr) = self.cursor {
+            self.request_run(RunCommand::RunTo(addr));
+        }
+    }
+
+    /// Take the requested run command, if any.
+    pub fn take_run_command(&mut self) -> Option<RunCommand> {
+        self.run_command.take()
+    }
+
+    /// Resumes execution until the given target is reached.
+    pub fn start_run(&mut self, target: RunTarget) {
+        self.run_target = Some(target);
+        self.paused = false;
+    }
+
+    pub fn run_target(&self) -> Option<RunTarget> {
+        self.run_target
+    }
+
+    pub fn get_cursor(&self) -> Option<u16> {
+        self.cursor
+    }
+
+    pub fn set_cursor(&mut self, addr: Option<u16>) {
+        self.cursor = addr;
+    }
+
+    /// Check if a single instruction step was requested and reset the flag.
+    pub fn take_step_instruction(&mut self) -> bool {
+        let val = self.step_instruction;
+        self.step_instruction = false;
+        val
+    }
+
+    /// Check if a scanline step was requested and reset the flag.
+    pub fn take_step_line(&mut self) -> bool {
+        let val = self.step_line;
+        self.step_line = false;
+        val
+    }
+}
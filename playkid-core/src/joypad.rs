@@ -1,5 +1,53 @@
-use crate::eventhandler;
-use gilrs::{Button, EventType};
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+/// # Joypad state
+/// Plain snapshot of the eight Game Boy buttons, used by frontends to feed input
+/// into the [Joypad]. `true` means pressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JoypadState {
+    pub start: bool,
+    pub select: bool,
+    pub a: bool,
+    pub b: bool,
+    pub down: bool,
+    pub up: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl JoypadState {
+    /// Packs the buttons into a byte, one bit per button, in the order
+    /// start, select, A, B, down, up, left, right (bit 7 to 0).
+    pub fn to_bits(&self) -> u8 {
+        [
+            self.start,
+            self.select,
+            self.a,
+            self.b,
+            self.down,
+            self.up,
+            self.left,
+            self.right,
+        ]
+        .iter()
+        .fold(0, |bits, &pressed| (bits << 1) | pressed as u8)
+    }
+
+    /// Unpacks the buttons from a byte created with [JoypadState::to_bits].
+    pub fn from_bits(bits: u8) -> Self {
+        let bit = |n: u8| bits & (1 << n) != 0;
+        Self {
+            start: bit(7),
+            select: bit(6),
+            a: bit(5),
+            b: bit(4),
+            down: bit(3),
+            up: bit(2),
+            left: bit(1),
+            right: bit(0),
+        }
+    }
+}
 
 /// # Joypad
 /// This class manages the state of the Joypad of the Game Boy.
@@ -36,76 +84,6 @@ pub struct Joypad {
     cycles: usize,
 }
 
-impl eventhandler::EventHandler for Joypad {
-    fn handle_event(&mut self, i: &egui::InputState) -> bool {
-        if i.key_pressed(egui::Key::ArrowDown) {
-            self.down = true;
-            return true;
-        }
-        if i.key_released(egui::Key::ArrowDown) {
-            self.down = false;
-            return true;
-        }
-        if i.key_pressed(egui::Key::ArrowUp) {
-            self.up = true;
-            return true;
-        }
-        if i.key_released(egui::Key::ArrowUp) {
-            self.up = false;
-            return true;
-        }
-        if i.key_pressed(egui::Key::ArrowRight) {
-            self.right = true;
-            return true;
-        }
-        if i.key_released(egui::Key::ArrowRight) {
-            self.right = false;
-            return true;
-        }
-        if i.key_pressed(egui::Key::ArrowLeft) {
-            self.left = true;
-            return true;
-        }
-        if i.key_released(egui::Key::ArrowLeft) {
-            self.left = false;
-            return true;
-        }
-        if i.key_pressed(egui::Key::A) {
-            self.a = true;
-            return true;
-        }
-        if i.key_released(egui::Key::A) {
-            self.a = false;
-            return true;
-        }
-        if i.key_pressed(egui::Key::B) {
-            self.b = true;
-            return true;
-        }
-        if i.key_released(egui::Key::B) {
-            self.b = false;
-            return true;
-        }
-        if i.key_pressed(egui::Key::Enter) {
-            self.start = true;
-            return true;
-        }
-        if i.key_released(egui::Key::Enter) {
-            self.start = false;
-            return true;
-        }
-        if i.key_pressed(egui::Key::Space) {
-            self.select = true;
-            return true;
-        }
-        if i.key_released(egui::Key::Space) {
-            self.select = false;
-            return true;
-        }
-        false
-    }
-}
-
 impl Joypad {
     pub fn new() -> Self {
         Joypad {
@@ -153,8 +131,8 @@ impl Joypad {
     }
 
     /// Implements a Joypad cycle.
-    /// Assumes `handle_event()` has been called previously and the state
-    /// of the joypad is up to date.
+    /// Assumes the state of the buttons has been updated previously
+    /// (see [Joypad::set_state]) and is up to date.
     pub fn cycle(&mut self) {
         // Update state and raise interrupt if necessary.
         self.update_state();
@@ -215,37 +193,59 @@ impl Joypad {
         }
     }
 
-    /// Main game controller handler.
-    pub fn handle_controller_input(&mut self, event: EventType) {
-        match event {
-            EventType::ButtonPressed(button, _) => self.update_button(button, true),
-            EventType::ButtonReleased(button, _) => self.update_button(button, false),
-            EventType::AxisChanged(axis, value, _) => {
-                if axis == gilrs::Axis::LeftStickX {
-                    self.left = value < -0.5;
-                    self.right = value > 0.5;
-                }
-                if axis == gilrs::Axis::LeftStickY {
-                    self.up = value > 0.5;
-                    self.down = value < -0.5;
-                }
-            }
-            _ => (),
+    /// Gets the current state of the buttons.
+    pub fn state(&self) -> JoypadState {
+        JoypadState {
+            start: self.start,
+            select: self.select,
+            a: self.a,
+            b: self.b,
+            down: self.down,
+            up: self.up,
+            left: self.left,
+            right: self.right,
         }
     }
 
-    /// Game controller button handling.
-    fn update_button(&mut self, button: Button, pressed: bool) {
-        match button {
-            Button::South | Button::East => self.a = pressed,
-            Button::North | Button::West => self.b = pressed,
-            Button::Start => self.start = pressed,
-            Button::Select => self.select = pressed,
-            Button::DPadUp => self.up = pressed,
-            Button::DPadDown => self.down = pressed,
-            Button::DPadLeft => self.left = pressed,
-            Button::DPadRight => self.right = pressed,
-            _ => (),
-        }
+    /// Sets the state of all the buttons at once. The JOYP register and the
+    /// joypad interrupt are updated in the next cycle.
+    pub fn set_state(&mut self, state: JoypadState) {
+        self.start = state.start;
+        self.select = state.select;
+        self.a = state.a;
+        self.b = state.b;
+        self.down = state.down;
+        self.up = state.up;
+        self.left = state.left;
+        self.right = state.right;
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Joypad {
+    /// The button states are not saved, since they reflect the live input
+    /// of the frontend.
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.joyp);
+        w.bool(self.select_buttons);
+        w.bool(self.select_dpad);
+        w.bool(self.request_interrupt);
+        w.u8(self.i_mask);
+        w.usize(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        self.joyp = r.u8()?;
+        self.select_buttons = r.bool()?;
+        self.select_dpad = r.bool()?;
+        self.request_interrupt = r.bool()?;
+        self.i_mask = r.u8()?;
+        self.cycles = r.usize()?;
+        Ok(())
     }
 }
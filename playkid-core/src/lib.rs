@@ -0,0 +1,49 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! # Play Kid core
+//! The frontend-independent emulation core of Play Kid. It contains the
+//! [Machine](machine::Machine) and all its components (CPU, memory, PPU, APU,
+//! timer, joypad and cartridge), and does not depend on any windowing,
+//! input or audio library.
+//!
+//! A typical consumer creates a machine from ROM bytes, feeds it the joypad
+//! state, steps it one frame at a time, and then queries the frame buffer and
+//! the audio samples:
+//!
+//! ```no_run
+//! use playkid_core::joypad::JoypadState;
+//! use playkid_core::machine::Machine;
+//!
+//! let rom = std::fs::read("game.gb").unwrap();
+//! let mut machine = Machine::from_bytes(rom, false).unwrap();
+//! machine.set_joypad(JoypadState {
+//!     start: true,
+//!     ..Default::default()
+//! });
+//! machine.update();
+//! let rgba = machine.framebuffer();
+//! let samples = machine.take_audio_samples();
+//! ```
+
+pub mod apu;
+pub mod cartridge;
+pub mod cheats;
+pub mod colorization;
+pub mod constants;
+pub mod debugmanager;
+pub mod instruction;
+pub mod joypad;
+pub mod machine;
+pub mod memory;
+pub mod model;
+pub mod movie;
+pub mod patch;
+pub mod ppu;
+pub mod printer;
+pub mod ramsearch;
+pub mod registers;
+pub mod rewind;
+pub mod savestate;
+pub mod serial;
+pub mod sgb;
+pub mod timer;
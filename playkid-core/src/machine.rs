@@ -1,15 +1,23 @@
 use crate::cartridge;
+use crate::cheats::CheatList;
+use crate::colorization::Colorization;
 use crate::constants;
-use crate::eventhandler;
 use crate::instruction;
 use crate::memory;
+use crate::model::Model;
 use crate::registers;
+use crate::savestate::{self, SaveState, StateHeader, StateReader, StateWriter};
+use crate::serial::Link;
+use colored::Colorize;
 
-use crate::debugmanager::DebugManager;
+use crate::debugmanager::{DebugManager, RunCommand, RunTarget};
+use crate::joypad::JoypadState;
 use cartridge::Cartridge;
 use instruction::{CC, Instruction, R8, R16, R16EXT, R16LD, RunInstr, TGT3};
 use memory::Memory;
 use registers::Registers;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
 
 /// # Machine
 /// The machine contains the [Registers], the [Memory], and the [Display], and
@@ -36,15 +44,30 @@ pub struct Machine {
     last_save_cycles: u64,
     /// The debug manager.
     pub debug: DebugManager,
+    /// Cheats in use.
+    cheats: CheatList,
+    /// RAM writes of the enabled GameShark codes and freezes, applied every
+    /// frame.
+    ram_cheats: Vec<(u16, u8)>,
 }
 
 impl Machine {
-    /// Create a new instance of the Game Boy.
+    /// Create a new instance of the Game Boy, with the model that fits
+    /// the cartridge.
     pub fn new(cart: Cartridge, debug: bool) -> Self {
+        let model = Model::for_cartridge(&cart);
+        Self::with_model(cart, model, debug)
+    }
+
+    /// Create a new instance of the given Game Boy model.
+    pub fn with_model(cart: Cartridge, model: Model, debug: bool) -> Self {
+        let colorization = Colorization::for_cartridge(&cart);
+        let mut registers = Registers::new();
+        registers.post_boot(model, &cart);
         // UI state object.
         let mut machine = Machine {
-            registers: Registers::new(),
-            memory: Memory::new(cart),
+            registers,
+            memory: Memory::new(cart, model),
             ime: false,
             ei: 0,
             di: 0,
@@ -53,15 +76,80 @@ impl Machine {
             m_cycles: 0,
             last_save_cycles: 0,
             debug: DebugManager::new(debug),
+            cheats: CheatList::new(),
+            ram_cheats: Vec::new(),
         };
+        if !machine.memory.cgb_mode {
+            machine.memory.ppu.set_colorization(colorization);
+        }
+        if model.is_sgb() && !machine.is_sgb() {
+            println!(
+                "{}: The cartridge does not support the Super Game Boy functions",
+                "WARN".yellow()
+            );
+        }
         machine.init();
 
         machine
     }
 
+    /// Create a new instance of the Game Boy from the raw bytes of a ROM.
+    /// The resulting machine has no SRAM file attached.
+    pub fn from_bytes(data: Vec<u8>, skip_checksum: bool) -> Result<Self> {
+        let cart = Cartridge::from_bytes(data, skip_checksum)?;
+        Ok(Self::new(cart, false))
+    }
+
+    /// Sets the state of the joypad buttons.
+    pub fn set_joypad(&mut self, state: JoypadState) {
+        self.memory.joypad.set_state(state);
+    }
+
+    /// Gets the last complete frame, as RGBA bytes in row-major order,
+    /// of size [constants::DISPLAY_WIDTH] x [constants::DISPLAY_HEIGHT].
+    /// In SGB mode, it has the colors of the Super Game Boy.
+    pub fn framebuffer(&self) -> &[u8] {
+        match &self.memory.sgb {
+            Some(sgb) => sgb.screen(),
+            None => &self.memory.ppu.fb_front,
+        }
+    }
+
+    /// Gets the picture to display: the last complete frame, within the
+    /// border in SGB mode. Returns its width, height, and RGBA bytes.
+    pub fn display(&self) -> (usize, usize, &[u8]) {
+        match &self.memory.sgb {
+            Some(sgb) => (
+                constants::SGB_DISPLAY_WIDTH,
+                constants::SGB_DISPLAY_HEIGHT,
+                sgb.frame(),
+            ),
+            None => (
+                constants::DISPLAY_WIDTH,
+                constants::DISPLAY_HEIGHT,
+                self.framebuffer(),
+            ),
+        }
+    }
+
+    /// Does the machine run in SGB mode?
+    pub fn is_sgb(&self) -> bool {
+        self.memory.sgb.is_some()
+    }
+
+    /// Takes the audio samples generated since the last call. See [crate::apu::Apu::take_samples].
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.memory.apu.take_samples()
+    }
+
     /// Resets the state of the machine and all its components.
     pub fn reset(&mut self) {
-        self.registers.reset();
+        if self.memory.has_boot_rom() {
+            self.registers.power_on();
+        } else {
+            self.registers
+                .post_boot(self.memory.model, &self.memory.cart);
+        }
         self.memory.reset();
         self.ime = false;
         self.ei = 0;
@@ -71,6 +159,14 @@ impl Machine {
         self.m_cycles = 0;
     }
 
+    /// Sets the boot ROM, and resets the machine to run it from power-on.
+    /// The boot ROM stays mapped until the game writes to 0xFF50.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<()> {
+        self.memory.set_boot_rom(boot_rom)?;
+        self.reset();
+        Ok(())
+    }
+
     /// Initialize the Game Boy.
     pub fn init(&mut self) {
         self.memory.initialize_hw_registers();
@@ -78,17 +174,12 @@ impl Machine {
 
     /// Update the state of the machine with a cycle.
     pub fn update(&mut self) {
-        // Check for breakpoints.
-        if self.debug.is_debugging()
-            && !self.debug.is_paused()
-            && self.debug.has_breakpoint(self.registers.pc)
-        {
-            self.debug.set_paused(true);
-        }
-
         if self.debug.is_paused() {
             // Debug branch, we skip HALTED state.
-            if self.debug.take_step_instruction() {
+            if let Some(command) = self.debug.take_run_command() {
+                // Resume until the command target.
+                self.start_run_command(command);
+            } else if self.debug.take_step_instruction() {
                 // Step one instruction.
                 self.machine_cycle();
             } else if self.debug.take_step_line() {
@@ -101,17 +192,12 @@ impl Machine {
             // Present to get visual feedback when debugging.
             self.memory.ppu.present();
         } else {
-            // Normal full-speed execution.
-            let mut cycles_this_frame: usize = 0;
-            while cycles_this_frame < constants::CYCLES_PER_FRAME {
-                let (t, m, r) = self.machine_cycle();
-                if !r {
-                    // Early exit due to joypad.
-                    break;
-                }
-                self.m_cycles += m;
-                self.t_cycles += t;
-                cycles_this_frame += t as usize;
+            if self.debug.is_debugging() {
+                // Debug execution, checking breakpoints and run targets.
+                self.run_frame_debug();
+            } else {
+                // Normal full-speed execution.
+                self.run_frame_until(|_| false);
             }
             // If SRAM is dirty, save it. Check every minute.
             let cycles_since_save = self.t_cycles - self.last_save_cycles;
@@ -123,8 +209,263 @@ impl Machine {
                 self.last_save_cycles = self.t_cycles;
             }
         }
-        // Flush APU.
-        self.memory.apu.flush();
+    }
+
+    /// Resolves a debugger run command into its target, and resumes
+    /// execution until the target is reached.
+    fn start_run_command(&mut self, command: RunCommand) {
+        let pc = self.registers.pc;
+        let sp = self.registers.sp;
+        let target = match command {
+            RunCommand::StepOver => match self.memory.peek8(pc) {
+                // CALL, CALL cc.
+                0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC => RunTarget::Address {
+                    pc: pc.wrapping_add(3),
+                    sp,
+                },
+                // RST.
+                0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => RunTarget::Address {
+                    pc: pc.wrapping_add(1),
+                    sp,
+                },
+                // Any other instruction is just stepped.
+                _ => {
+                    self.machine_cycle();
+                    return;
+                }
+            },
+            RunCommand::StepOut => RunTarget::Return { sp },
+            RunCommand::RunTo(addr) => RunTarget::Address { pc: addr, sp: 0 },
+            RunCommand::StepFrame => RunTarget::VBlank,
+        };
+        self.debug.start_run(target);
+    }
+
+    /// Runs one frame worth of cycles in debug mode. Execution pauses
+    /// after the instruction that hits a breakpoint or the run target.
+    fn run_frame_debug(&mut self) {
+        self.apply_ram_cheats();
+        let mut cycles_this_frame: usize = 0;
+        while cycles_this_frame < constants::CYCLES_PER_FRAME {
+            let opcode = self.memory.peek8(self.registers.pc);
+            let mode = self.memory.ppu().mode();
+            let (t, m, _) = self.machine_cycle();
+            self.m_cycles += m;
+            self.t_cycles += t;
+            cycles_this_frame += self.frame_cycles(t);
+
+            let pc = self.registers.pc;
+            let target_hit = match self.debug.run_target() {
+                Some(RunTarget::Address { pc: addr, sp }) => pc == addr && self.registers.sp >= sp,
+                // RET, RETI, RET cc.
+                Some(RunTarget::Return { sp }) => {
+                    matches!(opcode, 0xC9 | 0xD9 | 0xC0 | 0xC8 | 0xD0 | 0xD8)
+                        && self.registers.sp > sp
+                }
+                Some(RunTarget::VBlank) => mode != 1 && self.memory.ppu().mode() == 1,
+                None => false,
+            };
+            if target_hit || self.debug.has_breakpoint(pc) {
+                self.debug.set_paused(true);
+                self.memory.ppu.present();
+                return;
+            }
+        }
+    }
+
+    /// Converts CPU T-cycles to frame time. In double speed mode, a frame
+    /// takes twice as many CPU cycles.
+    fn frame_cycles(&self, t_cycles: u64) -> usize {
+        if self.memory.double_speed {
+            (t_cycles / 2) as usize
+        } else {
+            t_cycles as usize
+        }
+    }
+
+    /// Runs the machine for one frame worth of cycles, or until the `stop`
+    /// condition, which is checked after every instruction, is met.
+    /// Returns `true` if the frame was interrupted by the condition.
+    pub fn run_frame_until<F>(&mut self, mut stop: F) -> bool
+    where
+        F: FnMut(&Machine) -> bool,
+    {
+        self.apply_ram_cheats();
+        let mut cycles_this_frame: usize = 0;
+        while cycles_this_frame < constants::CYCLES_PER_FRAME {
+            let (t, m, r) = self.machine_cycle();
+            if !r {
+                // Early exit due to joypad.
+                break;
+            }
+            self.m_cycles += m;
+            self.t_cycles += t;
+            cycles_this_frame += self.frame_cycles(t);
+            if stop(self) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Gets the cheats in use.
+    pub fn cheats(&self) -> &CheatList {
+        &self.cheats
+    }
+
+    /// Sets the cheats to use. The Game Genie codes patch the ROM reads
+    /// right away, and the GameShark codes and freezes write to RAM at the
+    /// start of every frame.
+    pub fn set_cheats(&mut self, cheats: CheatList) {
+        self.memory.cart.set_rom_patches(cheats.rom_patches());
+        self.ram_cheats = cheats.ram_writes();
+        self.cheats = cheats;
+    }
+
+    /// Gets the path of the cheat file, next to the ROM file, if the
+    /// cartridge was loaded from disk.
+    pub fn cheats_path(&self) -> Option<PathBuf> {
+        self.memory
+            .cart
+            .get_rom_path()
+            .map(|rom| rom.with_extension("cht"))
+    }
+
+    /// Writes the values of the RAM cheats.
+    fn apply_ram_cheats(&mut self) {
+        for &(address, value) in &self.ram_cheats {
+            self.memory.poke8(address, value);
+        }
+    }
+
+    /// Computes a hash (64-bit FNV-1a) of the last complete frame. Useful to
+    /// compare the output of two runs.
+    pub fn frame_hash(&self) -> u64 {
+        savestate::fnv1a64(self.framebuffer())
+    }
+
+    /// Creates a save state of the whole machine, with a thumbnail of the
+    /// last complete frame. See [StateHeader] for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let header = StateHeader {
+            version: savestate::STATE_VERSION,
+            title: self.memory.cart.title_bytes(),
+            rom_hash: self.memory.cart.rom_hash(),
+            model: self.memory.model,
+            timestamp,
+            thumbnail: self.framebuffer().to_vec(),
+        };
+        let mut w = StateWriter::new();
+        header.write(&mut w);
+        SaveState::save_state(self, &mut w);
+        w.into_bytes()
+    }
+
+    /// Restores a save state created with [Machine::save_state]. The state must
+    /// belong to the currently loaded ROM and hardware model. If the state
+    /// can't be restored, the machine is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let mut r = StateReader::new(data);
+        let header = StateHeader::read(&mut r)?;
+        if header.rom_hash != self.memory.cart.rom_hash() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Save state belongs to a different ROM",
+            ));
+        }
+        if header.model != self.memory.model {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Save state was made on {}, not {}",
+                    header.model.description(),
+                    self.memory.model.description()
+                ),
+            ));
+        }
+        self.load_body(&mut r)
+    }
+
+    /// Reads the state of the whole machine. If the data is corrupt, the
+    /// machine is restored to the state it had before.
+    fn load_body(&mut self, r: &mut StateReader<'_>) -> Result<()> {
+        // Back up the current state in case the body is corrupt.
+        let mut backup = StateWriter::new();
+        SaveState::save_state(self, &mut backup);
+        if let Err(e) = SaveState::load_state(self, r) {
+            let backup = backup.into_bytes();
+            SaveState::load_state(self, &mut StateReader::new(&backup))?;
+            return Err(e);
+        }
+        self.last_save_cycles = self.t_cycles;
+        Ok(())
+    }
+
+    /// Creates a bare snapshot of the machine state, without header. Meant
+    /// for in-memory use, like [crate::rewind::Rewind].
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        SaveState::save_state(self, &mut w);
+        w.into_bytes()
+    }
+
+    /// Restores a snapshot created with [Machine::snapshot]. If the snapshot
+    /// can't be restored, the machine is left untouched.
+    pub fn restore_snapshot(&mut self, data: &[u8]) -> Result<()> {
+        self.load_body(&mut StateReader::new(data))
+    }
+
+    /// Gets the path of the file for the given save state slot, next to the ROM
+    /// file, if the cartridge was loaded from disk.
+    pub fn state_slot_path(&self, slot: u8) -> Option<PathBuf> {
+        self.memory
+            .cart
+            .get_rom_path()
+            .map(|rom| rom.with_extension(format!("ss{}", slot)))
+    }
+
+    /// Saves the state to the file of the given slot, and returns its path.
+    pub fn save_state_slot(&self, slot: u8) -> Result<PathBuf> {
+        let path = self
+            .state_slot_path(slot)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "The ROM was not loaded from a file"))?;
+        std::fs::write(&path, self.save_state())?;
+        Ok(path)
+    }
+
+    /// Loads the state from the file of the given slot.
+    pub fn load_state_slot(&mut self, slot: u8) -> Result<()> {
+        let path = self
+            .state_slot_path(slot)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "The ROM was not loaded from a file"))?;
+        let data = std::fs::read(&path)?;
+        self.load_state(&data)
+    }
+
+    /// Reads the header of the given save state slot, if it exists.
+    pub fn state_slot_header(&self, slot: u8) -> Option<StateHeader> {
+        let data = std::fs::read(self.state_slot_path(slot)?).ok()?;
+        StateHeader::read(&mut StateReader::new(&data)).ok()
+    }
+
+    /// Takes the bytes sent through the serial port since the last call.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.memory.serial.out)
+    }
+
+    /// Plugs in the link cable, or unplugs it with `None`.
+    pub fn set_link(&mut self, link: Option<Box<dyn Link>>) {
+        self.memory.serial.set_link(link);
+    }
+
+    /// Unplugs the link cable, and returns it, so that it can be plugged into
+    /// another machine.
+    pub fn take_link(&mut self) -> Option<Box<dyn Link>> {
+        self.memory.serial.take_link()
     }
 
     /// Updates the IME (Interrupt Master Enable) flag.
@@ -200,22 +541,22 @@ impl Machine {
     fn machine_cycle(&mut self) -> (u64, u64, bool) {
         // CPU instruction.
         // One machine cycle (M-cycle) is 4 clock cycles.
-        let mut m_cycles = if self.halted {
+        let m_cycles = if self.halted {
             // CPU is halted, don't execute instructions but still consume 1 M-cycle.
             1
         } else {
             // Run next CPU instruction.
             self.cycle() as u64
         };
-        let mut t_cycles = m_cycles * 4;
 
-        // Memory cycle.
-        if m_cycles > 0 {
-            // Memory cycle.
-            self.memory.cycle(t_cycles);
-        } else {
-            t_cycles = 0;
-            m_cycles = 0;
+        // Memory cycle. The bus accesses of the instruction already advanced
+        // the hardware, so only the rest of the instruction is left.
+        let mut t_cycles = self.finish_cycles(m_cycles * 4);
+        // VRAM DMA stalls the CPU.
+        let stall = self.memory.take_stall_cycles();
+        if stall > 0 {
+            self.memory.cycle(stall);
+            t_cycles += stall;
         }
 
         // Update IME.
@@ -223,13 +564,22 @@ impl Machine {
 
         // Handle interrupts if necessary.
         let interrupt_m_cycles = self.interrupt_handling();
-
-        // Return.
         if interrupt_m_cycles > 0 {
-            (interrupt_m_cycles * 4, interrupt_m_cycles, true)
-        } else {
-            (t_cycles, m_cycles, true)
+            t_cycles += self.finish_cycles(interrupt_m_cycles * 4);
         }
+
+        (t_cycles, t_cycles / 4, true)
+    }
+
+    /// Advances the hardware to the end of an instruction, or of an
+    /// interrupt dispatch, that takes the given T-cycles. Returns the
+    /// T-cycles it took, which are never fewer than its bus accesses.
+    fn finish_cycles(&mut self, t_cycles: u64) -> u64 {
+        let ticked = self.memory.take_ticked();
+        if t_cycles > ticked {
+            self.memory.cycle(t_cycles - ticked);
+        }
+        t_cycles.max(ticked)
     }
 
     /// Main loop of the machine.
@@ -310,7 +660,7 @@ impl Machine {
                         1
                     }
                     R8::HL => {
-                        self.registers.b = self.memory.read8(self.registers.get_hl());
+                        self.registers.b = self.bus_read8(self.registers.get_hl());
                         2
                     }
                     R8::A => {
@@ -341,7 +691,7 @@ impl Machine {
                         1
                     }
                     R8::HL => {
-                        self.registers.c = self.memory.read8(self.registers.get_hl());
+                        self.registers.c = self.bus_read8(self.registers.get_hl());
                         2
                     }
                     R8::A => {
@@ -372,7 +722,7 @@ impl Machine {
                         1
                     }
                     R8::HL => {
-                        self.registers.d = self.memory.read8(self.registers.get_hl());
+                        self.registers.d = self.bus_read8(self.registers.get_hl());
                         2
                     }
                     R8::A => {
@@ -403,7 +753,7 @@ impl Machine {
                         1
                     }
                     R8::HL => {
-                        self.registers.e = self.memory.read8(self.registers.get_hl());
+                        self.registers.e = self.bus_read8(self.registers.get_hl());
                         2
                     }
                     R8::A => {
@@ -434,7 +784,7 @@ impl Machine {
                         1
                     }
                     R8::HL => {
-                        self.registers.h = self.memory.read8(self.registers.get_hl());
+                        self.registers.h = self.bus_read8(self.registers.get_hl());
                         2
                     }
                     R8::A => {
@@ -465,7 +815,7 @@ impl Machine {
                     }
                     R8::L => 1,
                     R8::HL => {
-                        self.registers.l = self.memory.read8(self.registers.get_hl());
+                        self.registers.l = self.bus_read8(self.registers.get_hl());
                         2
                     }
                     R8::A => {
@@ -475,39 +825,32 @@ impl Machine {
                 },
                 R8::HL => match r8_1 {
                     R8::B => {
-                        self.memory
-                            .write8(self.registers.get_hl(), self.registers.b);
+                        self.bus_write8(self.registers.get_hl(), self.registers.b);
                         2
                     }
                     R8::C => {
-                        self.memory
-                            .write8(self.registers.get_hl(), self.registers.c);
+                        self.bus_write8(self.registers.get_hl(), self.registers.c);
                         2
                     }
                     R8::D => {
-                        self.memory
-                            .write8(self.registers.get_hl(), self.registers.d);
+                        self.bus_write8(self.registers.get_hl(), self.registers.d);
                         2
                     }
                     R8::E => {
-                        self.memory
-                            .write8(self.registers.get_hl(), self.registers.e);
+                        self.bus_write8(self.registers.get_hl(), self.registers.e);
                         2
                     }
                     R8::H => {
-                        self.memory
-                            .write8(self.registers.get_hl(), self.registers.h);
+                        self.bus_write8(self.registers.get_hl(), self.registers.h);
                         2
                     }
                     R8::L => {
-                        self.memory
-                            .write8(self.registers.get_hl(), self.registers.l);
+                        self.bus_write8(self.registers.get_hl(), self.registers.l);
                         2
                     }
                     R8::HL => 1,
                     R8::A => {
-                        self.memory
-                            .write8(self.registers.get_hl(), self.registers.a);
+                        self.bus_write8(self.registers.get_hl(), self.registers.a);
                         2
                     }
                 },
@@ -537,7 +880,7 @@ impl Machine {
                         1
                     }
                     R8::HL => {
-                        self.registers.a = self.memory.read8(self.registers.get_hl());
+                        self.registers.a = self.bus_read8(self.registers.get_hl());
                         2
                     }
                     R8::A => 1,
@@ -571,7 +914,7 @@ impl Machine {
                 }
                 R8::HL => {
                     let val = self.read8();
-                    self.memory.write8(self.registers.get_hl(), val);
+                    self.bus_write8(self.registers.get_hl(), val);
                     3
                 }
                 R8::A => {
@@ -583,71 +926,70 @@ impl Machine {
             // LD x, A
             Instruction::LDfromA(r16ld) => match r16ld {
                 R16LD::BC => {
-                    self.memory
-                        .write8(self.registers.get_bc(), self.registers.a);
+                    self.bus_write8(self.registers.get_bc(), self.registers.a);
                     2
                 }
                 R16LD::DE => {
-                    self.memory
-                        .write8(self.registers.get_de(), self.registers.a);
+                    self.bus_write8(self.registers.get_de(), self.registers.a);
                     2
                 }
                 R16LD::HLp => {
-                    self.memory
-                        .write8(self.registers.get_hl_plus(), self.registers.a);
+                    let hl = self.registers.get_hl_plus();
+                    self.bus_write8(hl, self.registers.a);
                     2
                 }
                 R16LD::HLm => {
-                    self.memory
-                        .write8(self.registers.get_hl_minus(), self.registers.a);
+                    let hl = self.registers.get_hl_minus();
+                    self.bus_write8(hl, self.registers.a);
                     2
                 }
                 R16LD::A8 => {
                     let val = 0xFF00 | (self.read8() as u16);
-                    self.memory.write8(val, self.registers.a);
+                    self.bus_write8(val, self.registers.a);
                     3
                 }
                 R16LD::C => {
-                    self.memory
-                        .write8(0xFF00 | (self.registers.c as u16), self.registers.a);
+                    self.bus_write8(0xFF00 | (self.registers.c as u16), self.registers.a);
                     2
                 }
                 R16LD::A16 => {
                     let addr = self.read16();
-                    self.memory.write8(addr, self.registers.a);
+                    self.bus_write8(addr, self.registers.a);
                     4
                 }
             },
             // LD A, x
             Instruction::LDtoA(r16ld) => match r16ld {
                 R16LD::BC => {
-                    self.registers.a = self.memory.read8(self.registers.get_bc());
+                    self.registers.a = self.bus_read8(self.registers.get_bc());
                     2
                 }
                 R16LD::DE => {
-                    self.registers.a = self.memory.read8(self.registers.get_de());
+                    self.registers.a = self.bus_read8(self.registers.get_de());
                     2
                 }
                 R16LD::HLp => {
-                    self.registers.a = self.memory.read8(self.registers.get_hl_plus());
+                    let hl = self.registers.get_hl_plus();
+                    self.registers.a = self.bus_read8(hl);
                     2
                 }
                 R16LD::HLm => {
-                    self.registers.a = self.memory.read8(self.registers.get_hl_minus());
+                    let hl = self.registers.get_hl_minus();
+                    self.registers.a = self.bus_read8(hl);
                     2
                 }
                 R16LD::A8 => {
                     let val = 0xFF00 | (self.read8() as u16);
-                    self.registers.a = self.memory.read8(val);
+                    self.registers.a = self.bus_read8(val);
                     3
                 }
                 R16LD::C => {
-                    self.registers.a = self.memory.read8(0xFF00 | (self.registers.c as u16));
+                    self.registers.a = self.bus_read8(0xFF00 | (self.registers.c as u16));
                     2
                 }
                 R16LD::A16 => {
                     let val = self.read16();
-                    self.registers.a = self.memory.read8(val);
+                    self.registers.a = self.bus_read8(val);
                     4
                 }
             },
@@ -655,7 +997,7 @@ impl Machine {
             // LD (r16), SP
             Instruction::LD16SP() => {
                 let val = self.read16();
-                self.memory.write16(val, self.registers.sp);
+                self.bus_write16(val, self.registers.sp);
                 5
             }
             // LD x, SP
@@ -726,7 +1068,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.add(val, false);
                     2
                 }
@@ -762,7 +1104,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.add(val, true);
                     2
                 }
@@ -798,7 +1140,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.sub(val, false);
                     2
                 }
@@ -834,7 +1176,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.sub(val, true);
                     2
                 }
@@ -870,7 +1212,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.and(val);
                     2
                 }
@@ -906,7 +1248,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.xor(val);
                     2
                 }
@@ -942,7 +1284,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.or(val);
                     2
                 }
@@ -978,7 +1320,7 @@ impl Machine {
                     1
                 }
                 R8::HL => {
-                    let val = self.memory.read8(self.registers.get_hl());
+                    let val = self.bus_read8(self.registers.get_hl());
                     self.cp(val);
                     2
                 }
@@ -1196,8 +1538,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val_inc = self.inc(self.memory.read8(hl));
-                    self.memory.write8(hl, val_inc);
+                    let val = self.bus_read8(hl);
+                    let val_inc = self.inc(val);
+                    self.bus_write8(hl, val_inc);
                     3
                 }
                 R8::A => {
@@ -1233,8 +1576,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val_dec = self.dec(self.memory.read8(hl));
-                    self.memory.write8(hl, val_dec);
+                    let val = self.bus_read8(hl);
+                    let val_dec = self.dec(val);
+                    self.bus_write8(hl, val_dec);
                     3
                 }
                 R8::A => {
@@ -1368,7 +1712,7 @@ impl Machine {
             },
             // RETI
             Instruction::RETI() => {
-                let val = self.memory.read16(self.registers.sp);
+                let val = self.bus_read16(self.registers.sp);
                 self.registers.sp += 2;
                 self.registers.pc = val;
                 self.ei = 1;
@@ -1565,9 +1909,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.rlc(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1602,9 +1946,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.rrc(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1639,9 +1983,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.rl(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1676,9 +2020,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.rr(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1713,9 +2057,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.sla(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1750,9 +2094,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.sra(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1787,9 +2131,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.swap(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1824,9 +2168,9 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let value = self.memory.read8(hl);
+                    let value = self.bus_read8(hl);
                     let value2 = self.srl(value);
-                    self.memory.write8(hl, value2);
+                    self.bus_write8(hl, value2);
                     4
                 }
                 R8::A => {
@@ -1860,7 +2204,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 0);
                     3
                 }
@@ -1895,7 +2239,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 1);
                     3
                 }
@@ -1930,7 +2274,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 2);
                     3
                 }
@@ -1965,7 +2309,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 3);
                     3
                 }
@@ -2000,7 +2344,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 4);
                     3
                 }
@@ -2035,7 +2379,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 5);
                     3
                 }
@@ -2070,7 +2414,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 6);
                     3
                 }
@@ -2105,7 +2449,7 @@ impl Machine {
                     2
                 }
                 R8::HL => {
-                    let value = self.memory.read8(self.registers.get_hl());
+                    let value = self.bus_read8(self.registers.get_hl());
                     self.bit(value, 7);
                     3
                 }
@@ -2141,8 +2485,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0xFE;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0xFE;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2177,8 +2521,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0xFD;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0xFD;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2213,8 +2557,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0xFB;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0xFB;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2249,8 +2593,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0xF7;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0xF7;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2285,8 +2629,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0xEF;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0xEF;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2321,8 +2665,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0xDF;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0xDF;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2357,8 +2701,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0xBF;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0xBF;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2393,8 +2737,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) & 0x7F;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) & 0x7F;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2429,8 +2773,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x01;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x01;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2465,8 +2809,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x02;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x02;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2501,8 +2845,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x04;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x04;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2537,8 +2881,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x08;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x08;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2573,8 +2917,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x10;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x10;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2609,8 +2953,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x20;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x20;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2645,8 +2989,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x40;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x40;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2681,8 +3025,8 @@ impl Machine {
                 }
                 R8::HL => {
                     let hl = self.registers.get_hl();
-                    let val = self.memory.read8(hl) | 0x80;
-                    self.memory.write8(hl, val);
+                    let val = self.bus_read8(hl) | 0x80;
+                    self.bus_write8(hl, val);
                     4
                 }
                 R8::A => {
@@ -2702,14 +3046,47 @@ impl Machine {
 
     /// Stop the machine.
     fn stop(&mut self) {
+        // Armed speed switch (CGB only).
+        if self.memory.speed_switch {
+            self.memory.double_speed = !self.memory.double_speed;
+            self.memory.speed_switch = false;
+        }
         // Reset DIV register.
         self.memory.write8(0xFF04, 0x00);
     }
 
+    /// Reads a byte on the CPU bus. Every access takes an M-cycle, and the
+    /// hardware is advanced to it first, so that the access sees the PPU,
+    /// the timer, and OAM DMA as they are at that point of the instruction.
+    fn bus_read8(&mut self, address: u16) -> u8 {
+        self.memory.tick();
+        self.memory.read8(address)
+    }
+
+    /// Writes a byte on the CPU bus, in its own M-cycle.
+    fn bus_write8(&mut self, address: u16, value: u8) {
+        self.memory.tick();
+        self.memory.write8(address, value);
+    }
+
+    /// Reads a little-endian word on the CPU bus, in two M-cycles.
+    fn bus_read16(&mut self, address: u16) -> u16 {
+        let low = self.bus_read8(address);
+        let high = self.bus_read8(address.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Writes a little-endian word on the CPU bus, in two M-cycles.
+    fn bus_write16(&mut self, address: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.bus_write8(address, low);
+        self.bus_write8(address.wrapping_add(1), high);
+    }
+
     /// Reads the next byte in memory at the location of `pc`, and
     /// increments `pc`.
     fn read8(&mut self) -> u8 {
-        let result = self.memory.read8(self.registers.pc);
+        let result = self.bus_read8(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
         result
     }
@@ -2717,21 +3094,26 @@ impl Machine {
     /// Reads the next two bytes in memory at the location of `pc`, and
     /// increments `pc` twice.
     fn read16(&mut self) -> u16 {
-        let result = self.memory.read16(self.registers.pc);
+        let result = self.bus_read16(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(2);
         result
     }
 
-    /// Push a word to the stack.
+    /// Push a word to the stack. An internal M-cycle comes first, then the
+    /// high byte is written, and then the low byte.
     fn push_stack(&mut self, value: u16) {
-        self.registers.sp = self.registers.sp.wrapping_sub(2);
-        self.memory.write16(self.registers.sp, value);
+        self.memory.tick();
+        let [low, high] = value.to_le_bytes();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.bus_write8(self.registers.sp, high);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.bus_write8(self.registers.sp, low);
     }
 
     /// Pop a word from the stack.
     fn pop_stack(&mut self) -> u16 {
-        let result = self.memory.read16(self.registers.sp);
-        self.registers.sp += 2;
+        let result = self.bus_read16(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(2);
         result
     }
 
@@ -2972,23 +3354,128 @@ impl Machine {
     }
 }
 
-impl eventhandler::EventHandler for Machine {
-    /// Polls the events in the queue of the event pump and redirects them to the
-    /// interested partners ;).
-    fn handle_event(&mut self, i: &egui::InputState) -> bool {
-        // Reset cycles.
-        let mut handled = false;
+impl SaveState for Machine {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.registers.save_state(w);
+        w.bool(self.ime);
+        w.u8(self.ei);
+        w.u8(self.di);
+        w.bool(self.halted);
+        w.u64(self.t_cycles);
+        w.u64(self.m_cycles);
+        self.memory.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<()> {
+        self.registers.load_state(r)?;
+        self.ime = r.bool()?;
+        self.ei = r.u8()?;
+        self.di = r.u8()?;
+        self.halted = r.bool()?;
+        self.t_cycles = r.u64()?;
+        self.m_cycles = r.u64()?;
+        self.memory.load_state(r)?;
+        Ok(())
+    }
+}
 
-        // Handle general emulator events.
-        if !handled {
-            handled = self.memory.joypad.handle_event(i);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a machine whose program keeps incrementing SCX, with the
+    /// window and a screen full of sprites on, so that every line of mode 3
+    /// depends on the FIFO state.
+    fn raster_machine() -> Machine {
+        let mut m = Machine::from_bytes(raster_rom(), true).unwrap();
+
+        let ppu = &mut m.memory.ppu;
+        for (i, byte) in ppu.vram.iter_mut().enumerate() {
+            *byte = (i * 7 + i / 16) as u8;
         }
+        for (i, byte) in ppu.oam.iter_mut().enumerate() {
+            let sprite = i / 4;
+            *byte = match i % 4 {
+                0 => 16 + sprite * 3,
+                1 => 8 + sprite * 13 % 168,
+                2 => i,
+                _ => sprite << 4,
+            } as u8;
+        }
+        m.memory.write8(0xFF4A, 40);
+        m.memory.write8(0xFF4B, 87);
+        m.memory.write8(0xFF40, 0xE3);
+        m
+    }
+
+    /// Builds the ROM of [raster_machine].
+    fn raster_rom() -> Vec<u8> {
+        let mut rom = vec![0u8; 0x8000];
+        // JP 0150h.
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        // LD HL, FF43h; loop: INC (HL); JR loop.
+        rom[0x150..0x156].copy_from_slice(&[0x21, 0x43, 0xFF, 0x34, 0x18, 0xFD]);
+        rom
+    }
+
+    /// Runs the test in a thread with a large stack, since the machine is
+    /// too big for the default one in debug builds.
+    fn with_big_stack(test: fn()) {
+        std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn save_state_mid_mode3() {
+        with_big_stack(save_state_mid_mode3_body);
+    }
 
-        // Debug events.
-        if !handled {
-            handled = self.debug.handle_event(i);
+    fn save_state_mid_mode3_body() {
+        let mut m = raster_machine();
+        m.update();
+        // Stop a few instructions into mode 3 of a line, so that the FIFOs
+        // and the fetcher are busy.
+        let mut steps = 0;
+        let stopped = m.run_frame_until(|m| {
+            if m.memory.ppu().mode() == 3 && m.memory.peek8(0xFF44) == 60 {
+                steps += 1;
+            }
+            steps == 4
+        });
+        assert!(stopped);
+        let state = m.save_state();
+
+        let mut reloaded = raster_machine();
+        reloaded.load_state(&state).unwrap();
+        for frame in 0..4 {
+            m.update();
+            reloaded.update();
+            assert_eq!(
+                m.frame_hash(),
+                reloaded.frame_hash(),
+                "frame {} differs",
+                frame
+            );
         }
+        assert_eq!(m.snapshot(), reloaded.snapshot());
+    }
+
+    #[test]
+    fn save_state_other_model() {
+        with_big_stack(save_state_other_model_body);
+    }
 
-        handled
+    fn save_state_other_model_body() {
+        let m = raster_machine();
+        let state = m.save_state();
+        let cart = Cartridge::from_bytes(raster_rom(), true).unwrap();
+        let mut cgb = Machine::with_model(cart, Model::Cgb, false);
+        let before = cgb.snapshot();
+        assert!(cgb.load_state(&state).is_err());
+        assert_eq!(cgb.snapshot(), before);
     }
 }
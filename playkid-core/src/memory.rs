@@ -0,0 +1,754 @@
+use crate::apu::Apu;
+use crate::cartridge::Cartridge;
+use crate::constants;
+use crate::joypad::Joypad;
+use crate::model::Model;
+use crate::ppu::Ppu;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use crate::serial::Serial;
+use crate::sgb::Sgb;
+use crate::timer::Timer;
+
+/// M-cycles between the write to DMA (0xFF46) and the first byte of OAM
+/// DMA, besides the cycle of the write itself.
+const OAM_DMA_DELAY: u8 = 1;
+/// Bytes copied by OAM DMA, one per M-cycle.
+const OAM_DMA_LENGTH: u16 = 0xA0;
+
+/// # Memory
+/// The Game Boy uses a 2-byte address space (0x0000 to 0xFFFF) to map the different
+/// types of memory (RAM, VRAM, [Cartridge] memory, etc.)
+///
+/// ## Memory map
+/// 0x0000-0x3FFF: 16 KiB bank #0                 (cartridge)
+/// 0x4000-0x7FFF: 16 KiB switchable ROM bank     (cartridge)
+/// 0x8000-0x9FFF: 8 KiB video RAM                (VRAM)
+/// 0xA000-0xBFFF: 8 KiB switchable RAM bank      (cartridge)
+/// 0xC000-0xCFFF: 4 KiB work RAM bank #0         (WRAM)
+/// 0xD000-0xDFFF: 4 KiB work RAM bank #1-7       (WRAM, switchable in CGB mode)
+/// 0xE000-0xFDFF: Echo RAM                       (mirror of 0xC000-0xDDFF)
+/// 0xFE00-0xFE9F: Object attribute memory        (OAM)
+/// 0xFEA0-0xFEFF: Empty, not usable
+/// 0xFF00-0xFF4B: I/O registers                  (I/O)
+/// 0xFF4C-0xFF7F: Empty (?)
+/// 0xFF80-0xFFFE: High RAM                       (HRAM)
+/// 0xFF80-0xFFFF: Interrupt Enable Register      (IER)
+pub struct Memory {
+    /// Hardware model.
+    pub model: Model,
+    /// Runs in CGB mode. A CGB runs games without CGB support in DMG mode.
+    pub cgb_mode: bool,
+    /// Work RAM.
+    pub wram: [u8; constants::WRAM_SIZE],
+    // High RAM.
+    pub hram: [u8; constants::HRAM_SIZE],
+    // I/O registers.
+    pub io: [u8; constants::IO_SIZE],
+    // IF: interrupt flag.
+    pub iff: u8,
+    // IE flag: interrupt enable.
+    pub ie: u8,
+    // Cartridge reference.
+    pub cart: Cartridge,
+    /// The PPU, Picture Processing Unit.
+    pub ppu: Ppu,
+    /// The timer.
+    pub timer: Timer,
+    /// The serial port.
+    pub serial: Serial,
+    /// The joypad.
+    pub joypad: Joypad,
+    /// The Super Game Boy, on an SGB model with a cartridge that supports it.
+    pub sgb: Option<Box<Sgb>>,
+    // The APU, Audio Processing Unit.
+    pub apu: Apu,
+
+    /// SVBK: WRAM bank mapped at 0xD000-0xDFFF (CGB only). 0 selects bank 1.
+    svbk: u8,
+    /// KEY1 bit 0: speed switch armed, performed by the next STOP (CGB only).
+    pub speed_switch: bool,
+    /// Double speed mode (CGB only).
+    pub double_speed: bool,
+    /// HDMA1-2: VRAM DMA source address.
+    hdma_src: u16,
+    /// HDMA3-4: VRAM DMA destination, as an offset into VRAM.
+    hdma_dst: u16,
+    /// HDMA5 bits 0-6: remaining blocks of 16 bytes, minus one.
+    hdma_len: u8,
+    /// HBlank DMA in progress.
+    hdma_active: bool,
+    /// T-cycles during which the CPU is stalled by VRAM DMA.
+    stall_cycles: u64,
+    /// T-cycles advanced by the CPU bus accesses of the current instruction.
+    ticked: u64,
+    /// DMA: high byte of the source of the last OAM DMA.
+    dma: u8,
+    /// Source of the OAM DMA about to start, and the M-cycles until then.
+    dma_pending: Option<(u16, u8)>,
+    /// OAM DMA in progress. It blocks the bus it reads from.
+    dma_active: bool,
+    /// Source address of the OAM DMA in progress.
+    dma_source: u16,
+    /// Index of the next byte of the OAM DMA in progress.
+    dma_index: u16,
+    /// Last byte read by OAM DMA, which the CPU sees on a blocked bus.
+    dma_byte: u8,
+
+    /// Boot ROM, if any. Without it, the machine starts in the post-boot state.
+    boot_rom: Option<Vec<u8>>,
+    /// The boot ROM is mapped over the cartridge, until a write to 0xFF50.
+    boot_rom_mapped: bool,
+}
+
+impl Memory {
+    /// Create a new memory instance.
+    pub fn new(cart: Cartridge, model: Model) -> Self {
+        let cgb_mode = model.is_cgb() && cart.is_cgb();
+        let sgb = (model.is_sgb() && cart.is_sgb()).then(|| Box::new(Sgb::new()));
+        Memory {
+            model,
+            cgb_mode,
+            wram: [0; constants::WRAM_SIZE],
+            hram: [0; constants::HRAM_SIZE],
+            io: [0; constants::IO_SIZE],
+            iff: 0,
+            ie: 0,
+            cart,
+            ppu: Ppu::new(0x194, cgb_mode),
+            timer: Timer::new(),
+            serial: Serial::new(cgb_mode),
+            joypad: Joypad::new(),
+            sgb,
+            apu: Apu::new(),
+            svbk: 0,
+            speed_switch: false,
+            double_speed: false,
+            hdma_src: 0,
+            hdma_dst: 0,
+            hdma_len: 0x7F,
+            hdma_active: false,
+            stall_cycles: 0,
+            ticked: 0,
+            dma: 0xFF,
+            dma_pending: None,
+            dma_active: false,
+            dma_source: 0,
+            dma_index: 0,
+            dma_byte: 0xFF,
+            boot_rom: None,
+            boot_rom_mapped: false,
+        }
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.timer.reset();
+        self.serial.reset();
+        self.joypad.reset();
+        if let Some(sgb) = &mut self.sgb {
+            sgb.reset();
+        }
+        self.reset_memory();
+        if self.boot_rom.is_some() {
+            // Start from power-on, and let the boot ROM set up the hardware.
+            self.boot_rom_mapped = true;
+            self.write8(0xFF40, 0x00);
+        } else {
+            self.initialize_hw_registers();
+        }
+    }
+
+    /// Sets the boot ROM, which is mapped at the next reset. It must be a
+    /// DMG/MGB boot ROM (256 bytes), or a CGB one (2304 bytes).
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> std::io::Result<()> {
+        if boot_rom.len() != 0x100 && boot_rom.len() != 0x900 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Boot ROM must be 256 or 2304 bytes, got {}", boot_rom.len()),
+            ));
+        }
+        self.boot_rom = Some(boot_rom);
+        Ok(())
+    }
+
+    /// Is there a boot ROM?
+    pub fn has_boot_rom(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    /// Reads the boot ROM, if it is mapped at the given address. The boot ROM
+    /// covers 0x0000-0x00FF, and the CGB one also 0x0200-0x08FF. The
+    /// cartridge header in between is always visible.
+    fn read_boot_rom(&self, address: u16) -> Option<u8> {
+        if !self.boot_rom_mapped || (0x0100..0x0200).contains(&address) {
+            return None;
+        }
+        self.boot_rom.as_ref()?.get(address as usize).copied()
+    }
+
+    /// Resets both WRAM and HRAM.
+    pub fn reset_memory(&mut self) {
+        self.wram.fill(0);
+        self.hram.fill(0);
+        self.io.fill(0);
+        self.iff = 0;
+        self.ie = 0;
+        self.svbk = 0;
+        self.speed_switch = false;
+        self.double_speed = false;
+        self.hdma_src = 0;
+        self.hdma_dst = 0;
+        self.hdma_len = 0x7F;
+        self.hdma_active = false;
+        self.stall_cycles = 0;
+        self.ticked = 0;
+        self.dma = 0xFF;
+        self.dma_pending = None;
+        self.dma_active = false;
+        self.dma_source = 0;
+        self.dma_index = 0;
+        self.dma_byte = 0xFF;
+    }
+
+    /// Initializes the HW registers.
+    pub fn initialize_hw_registers(&mut self) {
+        // Initialize hardware registers in the I/O ports region.
+        // P1/JOYPAD
+        self.write8(0xFF00, 0xCF);
+        // SB
+        self.write8(0xFF01, 0x00);
+        // SC. The CGB has the clock speed bit.
+        self.write8(0xFF02, if self.model.is_cgb() { 0x7F } else { 0x7E });
+        // DIV
+        // Do not use memory interface, for it sets DIV to 0!
+        self.timer
+            .set_initial_div(self.model.post_boot_div(self.cgb_mode));
+        // TIMA
+        self.write8(0xFF05, 0x00);
+        // TMA
+        self.write8(0xFF06, 0x00);
+        // TAC
+        self.write8(0xFF07, 0xF8);
+        // IF
+        self.write8(0xFF0F, 0xE1);
+        // NR10
+        self.write8(0xFF10, 0x80);
+        // NR11
+        self.write8(0xFF12, 0xBF);
+        // NR12
+        self.write8(0xFF13, 0xF3);
+        // NR13
+        self.write8(0xFF14, 0xFF);
+        // NR14
+        self.write8(0xFF15, 0xBF);
+        // NR21
+        self.write8(0xFF16, 0x3F);
+        // NR22
+        self.write8(0xFF17, 0x00);
+        // NR23
+        self.write8(0xFF18, 0xFF);
+        // NR24
+        self.write8(0xFF19, 0xBF);
+        // NR30
+        self.write8(0xFF1A, 0x7F);
+        // NR31
+        self.write8(0xFF1B, 0xFF);
+        // NR32
+        self.write8(0xFF1C, 0x9F);
+        // NR33
+        self.write8(0xFF1D, 0xFF);
+        // NR34
+        self.write8(0xFF1E, 0xBF);
+        // NR41
+        self.write8(0xFF20, 0xFF);
+        // NR42
+        self.write8(0xFF21, 0x00);
+        // NR43
+        self.write8(0xFF22, 0x00);
+        // NR44
+        self.write8(0xFF23, 0xBF);
+        // NR50
+        self.write8(0xFF24, 0x77);
+        // NR51
+        self.write8(0xFF25, 0xF3);
+        // NR52
+        self.write8(0xFF26, 0xF1);
+        // LCDC
+        self.write8(0xFF40, 0x91);
+        // STAT
+        self.write8(0xFF41, 0x85);
+        // SCY
+        self.write8(0xFF42, 0x00);
+        // SCX
+        self.write8(0xFF43, 0x00);
+        // LY. The DMG0 boot ROM hands over in VBlank.
+        self.write8(0xFF44, 0x00);
+        if self.model == Model::Dmg0 {
+            self.ppu.set_phase(0x91, 0x194);
+        }
+        // LYC
+        self.write8(0xFF45, 0x00);
+        // DMA. Writing it would start a transfer.
+        self.dma = 0xFF;
+        // BGP
+        self.write8(0xFF47, 0xFC);
+        // OBP0
+        self.write8(0xFF48, 0x00);
+        // OBP1
+        self.write8(0xFF49, 0x00);
+        // WY
+        self.write8(0xFF4A, 0x00);
+        // WX
+        self.write8(0xFF4B, 0x00);
+        // KEY1
+        self.write8(0xFF4D, 0x7E);
+        // VBK
+        self.write8(0xFF4F, 0xFE);
+        // HDMA1-HDMA5 are reset in `reset_memory()`. Writing to HDMA5
+        // would start a transfer.
+        // RP
+        self.write8(0xFF56, 0x3E);
+        // BCPS
+        self.write8(0xFF68, 0x00);
+        // OCPS
+        self.write8(0xFF6A, 0x00);
+        // SVBK
+        self.write8(0xFF70, 0xF8);
+        // IE
+        self.write8(0xFFFF, 0x00);
+    }
+
+    /// Read a byte of memory at the given `address`, as the CPU sees it.
+    /// While OAM DMA runs, the bus it reads from is blocked.
+    pub fn read8(&self, address: u16) -> u8 {
+        if let Some(value) = self.dma_conflict(address) {
+            return value;
+        }
+        if (0xFEA0..=0xFEFF).contains(&address) {
+            println!("Forbidden read ($FEA0-$FEFE): ${:04x}", address);
+        }
+        self.peek8(address)
+    }
+
+    /// Read a byte of memory at the given `address`, ignoring OAM DMA. Used
+    /// by the debugger and the tools, which are not on the CPU bus.
+    pub fn peek8(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => {
+                // 16kB bank #0 (cartridge), or the boot ROM.
+                self.read_boot_rom(address)
+                    .unwrap_or_else(|| self.cart.read(address))
+            }
+            0x4000..=0x7FFF => {
+                // 16kB switchable ROM bank (cartridge).
+                self.cart.read(address)
+            }
+            0x8000..=0x9FFF => {
+                // VRAM.
+                self.ppu.read(address)
+            }
+            0xA000..=0xBFFF => {
+                // 8kB switchable RAM bank (cartridge).
+                self.cart.read_ram(address)
+            }
+            0xC000..=0xFDFF => {
+                // 8kB WRAM, and its echo.
+                self.wram[self.wram_index(address)]
+            }
+            0xFE00..=0xFE9F => {
+                // OAM.
+                self.ppu.read(address)
+            }
+            0xFEA0..=0xFEFF => {
+                // Empty, unusable.
+                // Return 0xFF, the default value in the Game Boy main data bus.
+                0xFF
+            }
+            // Joypad. The SGB reports the joypad being read.
+            0xFF00 => match &self.sgb {
+                Some(sgb) => sgb.read_p1(self.joypad.read(address)),
+                None => self.joypad.read(address),
+            },
+            // SB, SC: serial port.
+            0xFF01..=0xFF02 => self.serial.read(address),
+            // Timer registers.
+            0xFF04..=0xFF07 => self.timer.read(address),
+            // Interrupt flag.
+            0xFF0F => self.iff | 0b1110_0000,
+
+            // Audio.
+            0xFF10..=0xFF3F => self.apu.read(address),
+
+            // DMA.
+            0xFF46 => self.dma,
+            // KEY1: speed switch.
+            0xFF4D if self.cgb_mode => {
+                ((self.double_speed as u8) << 7) | 0x7E | self.speed_switch as u8
+            }
+            // VRAM registers.
+            0xFF40..=0xFF4F => self.ppu.read(address),
+            // HDMA5: remaining length, and bit 7 clear while HBlank DMA is active.
+            0xFF55 if self.cgb_mode => ((!self.hdma_active as u8) << 7) | self.hdma_len,
+            // BANK (boot ROM unmap), and HDMA1-4, are write-only.
+            0xFF50..=0xFF54 => 0xFF,
+            // CGB palettes.
+            0xFF68..=0xFF6B => self.ppu.read(address),
+            // SVBK.
+            0xFF70 if self.cgb_mode => 0xF8 | self.svbk,
+            0xFF55..=0xFF7F => {
+                // I/O registers.
+                self.io[(address - 0xFF00) as usize]
+            }
+            0xFF80..=0xFFFE => {
+                // High RAM.
+                self.hram[(address - 0xFF80) as usize]
+            }
+            // IE
+            0xFFFF => self.ie,
+            _ => 0xFF,
+        }
+    }
+    /// Read two bytes of memory at the given `address`.
+    pub fn read16(&self, address: u16) -> u16 {
+        (self.read8(address) as u16) | ((self.read8(address.wrapping_add(1)) as u16) << 8)
+    }
+    /// Read two bytes of memory at the given `address`, ignoring OAM DMA.
+    pub fn peek16(&self, address: u16) -> u16 {
+        (self.peek8(address) as u16) | ((self.peek8(address.wrapping_add(1)) as u16) << 8)
+    }
+    /// Write the given byte `value` at the given `address`, as the CPU does.
+    /// Writes to the bus blocked by OAM DMA are lost.
+    pub fn write8(&mut self, address: u16, value: u8) {
+        if self.dma_conflict(address).is_none() {
+            self.poke8(address, value);
+        }
+    }
+    /// Write the given byte `value` at the given `address`, ignoring OAM DMA.
+    /// Used by the cheats, which are not on the CPU bus.
+    pub fn poke8(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                // Cartridge (ROM + switchable banks).
+                self.cart.write(address, value);
+            }
+            0x8000..=0x9FFF => {
+                // VRAM.
+                self.ppu.write(address, value);
+            }
+            0xA000..=0xBFFF => {
+                // 8kB switchable RAM bank (cartridge).
+                self.cart.write_ram(address, value)
+            }
+            0xC000..=0xFDFF => {
+                // 8kB WRAM, and its echo.
+                self.wram[self.wram_index(address)] = value;
+            }
+            0xFE00..=0xFE9F => {
+                // OAM.
+                self.ppu.write(address, value);
+            }
+            0xFEA0..=0xFEFF => {
+                // Empty, unusable.
+                // println!("Forbidden write ($FEA0-$FEFE): ${:04x}", address)
+            }
+            // Joypad. Also carries the SGB command packets.
+            0xFF00 => {
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_p1(value);
+                }
+                self.joypad.write(address, value)
+            }
+            // SB, SC: serial port.
+            0xFF01..=0xFF02 => self.serial.write(address, value),
+            // Timer registers.
+            0xFF04..=0xFF07 => self.timer.write(address, value),
+            // IF: interrupt flag.
+            0xFF0F => self.iff = value,
+
+            // Audio.
+            0xFF10..=0xFF3F => self.apu.write(address, value),
+
+            // DMA: starts OAM DMA from $XX00-$XX9F to OAM. Sources above
+            // 0xDFFF read WRAM, like the echo RAM. A running transfer goes on
+            // until the new one starts.
+            0xFF46 => {
+                self.dma = value;
+                let high = if value > 0xDF { value - 0x20 } else { value };
+                let source = (high as u16) << 8;
+                self.dma_pending = Some((source, OAM_DMA_DELAY));
+            }
+            // KEY1: speed switch.
+            0xFF4D if self.cgb_mode => self.speed_switch = value & 0x01 != 0,
+            // VRAM registers.
+            0xFF40..=0xFF4F => self.ppu.write(address, value),
+            // BANK: unmaps the boot ROM for good.
+            0xFF50 => self.boot_rom_mapped = false,
+            // HDMA1-5: VRAM DMA.
+            0xFF51..=0xFF55 if self.cgb_mode => self.write_hdma(address, value),
+            // CGB palettes.
+            0xFF68..=0xFF6B => self.ppu.write(address, value),
+            // SVBK.
+            0xFF70 if self.cgb_mode => self.svbk = value & 0x07,
+            0xFF51..=0xFF7F => {
+                // I/O registers.
+                self.io[(address - 0xFF00) as usize] = value;
+            }
+            0xFF80..=0xFFFE => {
+                // High RAM.
+                self.hram[(address - 0xFF80) as usize] = value;
+            }
+            // IE: interrupt enable.
+            0xFFFF => self.ie = value,
+            // Default.
+            _ => (),
+        }
+    }
+    /// Write the given word `value` at the given `address`.
+    pub fn write16(&mut self, address: u16, value: u16) {
+        self.write8(address, (value & 0xFF) as u8);
+        self.write8(address.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    /// Gets the index into [Memory::wram] of a WRAM or echo RAM address.
+    fn wram_index(&self, address: u16) -> usize {
+        let offset = (address & 0x0FFF) as usize;
+        if address & 0x1000 == 0 {
+            offset
+        } else if self.cgb_mode {
+            (self.svbk.max(1) as usize) * 0x1000 + offset
+        } else {
+            0x1000 + offset
+        }
+    }
+
+    /// Gets what the CPU reads at the given `address` while OAM DMA runs, if
+    /// the address is on the bus the DMA reads from: VRAM, or the external
+    /// bus (ROM, cartridge RAM, and WRAM). That is the byte the DMA is
+    /// reading, and 0xFF for OAM. I/O and HRAM are always accessible, which
+    /// is why games run the DMA routine from HRAM.
+    fn dma_conflict(&self, address: u16) -> Option<u8> {
+        if !self.dma_active {
+            return None;
+        }
+        let is_vram = |a: u16| (0x8000..=0x9FFF).contains(&a);
+        match address {
+            0xFE00..=0xFEFF => Some(0xFF),
+            0xFF00..=0xFFFF => None,
+            _ if is_vram(address) == is_vram(self.dma_source) => Some(self.dma_byte),
+            _ => None,
+        }
+    }
+
+    /// Advances OAM DMA by one M-cycle. A transfer starts after the delay,
+    /// then copies one byte per M-cycle, regardless of the PPU mode. The bus
+    /// is released in the M-cycle after the last byte.
+    fn dma_cycle(&mut self) {
+        if self.dma_active && self.dma_index == OAM_DMA_LENGTH {
+            self.dma_active = false;
+        }
+        if let Some((source, delay)) = self.dma_pending {
+            if delay == 0 {
+                self.dma_pending = None;
+                self.dma_active = true;
+                self.dma_source = source;
+                self.dma_index = 0;
+            } else {
+                self.dma_pending = Some((source, delay - 1));
+            }
+        }
+        if self.dma_active {
+            self.dma_byte = self.peek8(self.dma_source + self.dma_index);
+            self.ppu.write_oam(self.dma_index, self.dma_byte);
+            self.dma_index += 1;
+        }
+    }
+
+    /// Writes to the HDMA1-5 registers.
+    fn write_hdma(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF51 => self.hdma_src = (self.hdma_src & 0x00F0) | ((value as u16) << 8),
+            0xFF52 => self.hdma_src = (self.hdma_src & 0xFF00) | (value & 0xF0) as u16,
+            0xFF53 => self.hdma_dst = (self.hdma_dst & 0x00F0) | (((value & 0x1F) as u16) << 8),
+            0xFF54 => self.hdma_dst = (self.hdma_dst & 0x1F00) | (value & 0xF0) as u16,
+            _ => {
+                if self.hdma_active && value & 0x80 == 0 {
+                    // Stop the HBlank DMA. HDMA5 keeps the remaining length.
+                    self.hdma_active = false;
+                } else if value & 0x80 == 0 {
+                    // General purpose DMA: copy everything right away.
+                    for _ in 0..=(value & 0x7F) {
+                        self.hdma_block();
+                    }
+                    self.hdma_len = 0x7F;
+                } else {
+                    // HBlank DMA: copy one block per HBlank.
+                    self.hdma_len = value & 0x7F;
+                    self.hdma_active = true;
+                }
+            }
+        }
+    }
+
+    /// Copies a block of 16 bytes of VRAM DMA, and stalls the CPU for the
+    /// duration of the copy (8 M-cycles, at single speed).
+    fn hdma_block(&mut self) {
+        for _ in 0..16 {
+            let byte = self.peek8(self.hdma_src);
+            self.ppu.write_vram(0x8000 | self.hdma_dst, byte);
+            self.hdma_src = self.hdma_src.wrapping_add(1);
+            self.hdma_dst = (self.hdma_dst + 1) & 0x1FFF;
+        }
+        self.stall_cycles += if self.double_speed { 64 } else { 32 };
+    }
+
+    /// Takes the T-cycles the CPU must stall for VRAM DMA.
+    pub fn take_stall_cycles(&mut self) -> u64 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    /// Advances the hardware by one M-cycle, for a CPU bus access in the
+    /// middle of an instruction.
+    pub fn tick(&mut self) {
+        self.cycle(4);
+        self.ticked += 4;
+    }
+
+    /// Takes the T-cycles advanced with [Memory::tick] since the last call.
+    /// The CPU advances the rest of the instruction with [Memory::cycle].
+    pub fn take_ticked(&mut self) -> u64 {
+        std::mem::take(&mut self.ticked)
+    }
+
+    /// Advances the hardware by the given CPU T-cycles. In double speed mode,
+    /// the PPU and the APU run at half the CPU rate.
+    pub fn cycle(&mut self, t_cycles: u64) {
+        // OAM DMA, at the CPU rate.
+        for _ in 0..t_cycles / 4 {
+            self.dma_cycle();
+        }
+
+        // Joypad.
+        self.joypad.cycle();
+        self.iff |= self.joypad.i_mask;
+        self.joypad.i_mask = 0;
+
+        // Timer and serial port, both clocked by the divider.
+        let div = self.timer.div16();
+        self.timer.cycle(t_cycles);
+        self.iff |= self.timer.i_mask;
+        self.timer.i_mask = 0;
+        self.serial.cycle(div, t_cycles);
+        self.iff |= self.serial.i_mask;
+        self.serial.i_mask = 0;
+
+        let t_cycles = if self.double_speed {
+            t_cycles / 2
+        } else {
+            t_cycles
+        };
+
+        // Cartridge clock.
+        self.cart.cycle(t_cycles);
+
+        // PPU
+        self.ppu.cycle(t_cycles);
+        if self.ppu.i_mask & 0x01 != 0
+            && let Some(sgb) = &mut self.sgb
+        {
+            sgb.vblank(&self.ppu);
+        }
+        self.iff |= self.ppu.i_mask;
+        self.ppu.i_mask = 0;
+
+        // HBlank DMA.
+        if self.hdma_active && self.ppu.hblank {
+            self.hdma_block();
+            if self.hdma_len == 0 {
+                self.hdma_active = false;
+                self.hdma_len = 0x7F;
+            } else {
+                self.hdma_len -= 1;
+            }
+        }
+
+        // APU
+        self.apu.cycle(t_cycles);
+        self.iff |= self.apu.i_mask;
+        self.apu.i_mask = 0;
+    }
+}
+
+impl SaveState for Memory {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.wram);
+        w.bytes(&self.hram);
+        w.bytes(&self.io);
+        w.u8(self.iff);
+        w.u8(self.ie);
+        w.u8(self.svbk);
+        w.bool(self.speed_switch);
+        w.bool(self.double_speed);
+        w.u16(self.hdma_src);
+        w.u16(self.hdma_dst);
+        w.u8(self.hdma_len);
+        w.bool(self.hdma_active);
+        w.u8(self.dma);
+        w.bool(self.dma_pending.is_some());
+        let (pending_source, pending_delay) = self.dma_pending.unwrap_or_default();
+        w.u16(pending_source);
+        w.u8(pending_delay);
+        w.bool(self.dma_active);
+        w.u16(self.dma_source);
+        w.u16(self.dma_index);
+        w.u8(self.dma_byte);
+        w.bool(self.boot_rom_mapped);
+        self.cart.save_state(w);
+        self.ppu.save_state(w);
+        self.timer.save_state(w);
+        self.serial.save_state(w);
+        self.joypad.save_state(w);
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(w);
+        }
+        self.apu.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.wram)?;
+        r.bytes_into(&mut self.hram)?;
+        r.bytes_into(&mut self.io)?;
+        self.iff = r.u8()?;
+        self.ie = r.u8()?;
+        self.svbk = r.u8()?;
+        self.speed_switch = r.bool()?;
+        self.double_speed = r.bool()?;
+        self.hdma_src = r.u16()?;
+        self.hdma_dst = r.u16()?;
+        self.hdma_len = r.u8()?;
+        self.hdma_active = r.bool()?;
+        self.dma = r.u8()?;
+        let pending = r.bool()?;
+        let pending_source = r.u16()?;
+        let pending_delay = r.u8()?;
+        self.dma_pending = pending.then_some((pending_source, pending_delay));
+        self.dma_active = r.bool()?;
+        self.dma_source = r.u16()?;
+        self.dma_index = r.u16()?;
+        self.dma_byte = r.u8()?;
+        self.boot_rom_mapped = r.bool()?;
+        self.cart.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.timer.load_state(r)?;
+        self.serial.load_state(r)?;
+        self.joypad.load_state(r)?;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.load_state(r)?;
+        }
+        self.apu.load_state(r)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use crate::cartridge::Cartridge;
+use std::fmt;
+use std::str::FromStr;
+
+/// # Model
+/// The Game Boy hardware model being emulated. Games tell them apart by
+/// the register values the boot ROM leaves behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Model {
+    /// Early original Game Boy, with the first boot ROM revision.
+    Dmg0,
+    /// Original Game Boy.
+    #[default]
+    Dmg,
+    /// Game Boy Pocket.
+    Mgb,
+    /// Super Game Boy.
+    Sgb,
+    /// Super Game Boy 2.
+    Sgb2,
+    /// Game Boy Color.
+    Cgb,
+}
+
+impl Model {
+    /// All the models, in release order.
+    pub const ALL: [Model; 6] = [
+        Model::Dmg0,
+        Model::Dmg,
+        Model::Mgb,
+        Model::Sgb,
+        Model::Sgb2,
+        Model::Cgb,
+    ];
+
+    /// Index of the model in [Model::ALL], as stored in save states and
+    /// movies.
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Gets the model with the given index in [Model::ALL].
+    pub fn from_id(id: u8) -> Option<Self> {
+        Model::ALL.get(id as usize).copied()
+    }
+
+    /// Picks the model for the given cartridge: CGB for cartridges flagged
+    /// as CGB-enhanced or CGB-only, and DMG for the rest.
+    pub fn for_cartridge(cart: &Cartridge) -> Self {
+        if cart.is_cgb() {
+            Model::Cgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    /// Is this a Game Boy Color?
+    pub fn is_cgb(&self) -> bool {
+        *self == Model::Cgb
+    }
+
+    /// Is this a Super Game Boy?
+    pub fn is_sgb(&self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    /// Short name, as used in the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0",
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Sgb2 => "sgb2",
+            Model::Cgb => "cgb",
+        }
+    }
+
+    /// Human-readable name.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Model::Dmg0 => "Game Boy (DMG0)",
+            Model::Dmg => "Game Boy (DMG)",
+            Model::Mgb => "Game Boy Pocket (MGB)",
+            Model::Sgb => "Super Game Boy (SGB)",
+            Model::Sgb2 => "Super Game Boy 2 (SGB2)",
+            Model::Cgb => "Game Boy Color (CGB)",
+        }
+    }
+
+    /// Value of DIV when the boot ROM hands over to the game. The SGB and
+    /// CGB boot ROMs do not take a fixed time, so these are typical values.
+    pub fn post_boot_div(&self, cgb_mode: bool) -> u8 {
+        match self {
+            Model::Dmg0 => 0x18,
+            Model::Dmg | Model::Mgb => 0xAB,
+            Model::Sgb | Model::Sgb2 => 0xD8,
+            Model::Cgb if cgb_mode => 0x1E,
+            Model::Cgb => 0x26,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Model::ALL
+            .into_iter()
+            .find(|m| m.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Model::ALL.iter().map(|m| m.name()).collect();
+                format!(
+                    "unknown model '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
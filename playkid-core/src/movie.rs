@@ -0,0 +1,397 @@
+use crate::cheats::CheatList;
+use crate::joypad::JoypadState;
+use crate::machine::Machine;
+use crate::model::Model;
+use crate::savestate::{STATE_VERSION, StateReader, StateWriter};
+
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// Magic bytes at the start of every movie file.
+pub const MOVIE_MAGIC: [u8; 4] = *b"PKMV";
+/// Current version of the movie format.
+pub const MOVIE_VERSION: u16 = 2;
+/// Number of frames between two frame hashes.
+pub const MOVIE_HASH_INTERVAL: u32 = 60;
+
+/// Point a movie starts from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieAnchor {
+    /// The machine is reset when the recording starts.
+    PowerOn,
+    /// The recording starts from the current state of the machine.
+    SaveState,
+}
+
+/// # Movie
+/// Input recording of a run. Contains the joypad state of every frame,
+/// and the state of the machine at the start (the anchor). Both power-on
+/// and save state anchors store a snapshot of the machine, so that
+/// battery RAM and mapper state are reproduced too. The hardware model and
+/// the cheats in use are stored as well, since they change the run. Every
+/// [MOVIE_HASH_INTERVAL] frames, the hash of the frame is stored, so that
+/// playback can detect desyncs.
+///
+/// ## Layout
+/// All values are little-endian, blocks are prefixed with their length.
+/// - magic: `PKMV` (4 bytes)
+/// - version (u16)
+/// - save state version of the anchor (u16)
+/// - ROM title (16 bytes, from 0x134-0x143)
+/// - ROM hash (u64, FNV-1a of the whole ROM)
+/// - hardware model (u8, index in [Model::ALL])
+/// - cheats (block, in the format of the cheat files)
+/// - anchor (u8, 0: power-on, 1: save state)
+/// - anchor snapshot (block)
+/// - hash interval (u32)
+/// - inputs (block, one byte per frame, see [JoypadState::to_bits])
+/// - number of frame hashes (u32), followed by the hashes (u64)
+pub struct Movie {
+    pub title: [u8; 16],
+    pub rom_hash: u64,
+    pub model: Model,
+    pub cheats: CheatList,
+    pub anchor: MovieAnchor,
+    /// Snapshot of the machine at the start, see [Machine::snapshot].
+    snapshot: Vec<u8>,
+    hash_interval: u32,
+    /// Joypad state of every frame.
+    inputs: Vec<u8>,
+    /// Frame hash after every `hash_interval` frames.
+    hashes: Vec<u64>,
+}
+
+impl Movie {
+    /// Number of frames in the movie.
+    pub fn frames(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Gets the ROM title as a string.
+    pub fn title(&self) -> String {
+        String::from_utf8_lossy(&self.title)
+            .trim_end_matches('\0')
+            .to_string()
+    }
+
+    /// Serializes the movie.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(&MOVIE_MAGIC);
+        w.u16(MOVIE_VERSION);
+        w.u16(STATE_VERSION);
+        w.bytes(&self.title);
+        w.u64(self.rom_hash);
+        w.u8(self.model.id());
+        w.bytes(self.cheats.to_text().as_bytes());
+        w.u8(match self.anchor {
+            MovieAnchor::PowerOn => 0,
+            MovieAnchor::SaveState => 1,
+        });
+        w.bytes(&self.snapshot);
+        w.u32(self.hash_interval);
+        w.bytes(&self.inputs);
+        w.u32(self.hashes.len() as u32);
+        for hash in &self.hashes {
+            w.u64(*hash);
+        }
+        w.into_bytes()
+    }
+
+    /// Deserializes a movie created with [Movie::to_bytes].
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut r = StateReader::new(data);
+        let mut magic = [0u8; 4];
+        r.bytes_into(&mut magic)?;
+        if magic != MOVIE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a movie file"));
+        }
+        let version = r.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Unsupported movie version {} (expected {})",
+                    version, MOVIE_VERSION
+                ),
+            ));
+        }
+        let state_version = r.u16()?;
+        if state_version != STATE_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Unsupported movie anchor version {} (expected {})",
+                    state_version, STATE_VERSION
+                ),
+            ));
+        }
+        let mut title = [0u8; 16];
+        r.bytes_into(&mut title)?;
+        let rom_hash = r.u64()?;
+        let model = Model::from_id(r.u8()?)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Unknown hardware model"))?;
+        let cheats = CheatList::parse(&String::from_utf8_lossy(&r.bytes()?));
+        let anchor = match r.u8()? {
+            0 => MovieAnchor::PowerOn,
+            1 => MovieAnchor::SaveState,
+            a => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown movie anchor {}", a),
+                ));
+            }
+        };
+        let snapshot = r.bytes()?;
+        let hash_interval = r.u32()?.max(1);
+        let inputs = r.bytes()?;
+        let hashes = (0..r.u32()?).map(|_| r.u64()).collect::<Result<_>>()?;
+        Ok(Self {
+            title,
+            rom_hash,
+            model,
+            cheats,
+            anchor,
+            snapshot,
+            hash_interval,
+            inputs,
+            hashes,
+        })
+    }
+
+    /// Writes the movie to a file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    /// Reads a movie from a file.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+/// # Movie recorder
+/// Records the joypad state of every frame into a [Movie].
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Starts a recording. With [MovieAnchor::PowerOn], the machine is
+    /// reset first.
+    pub fn new(machine: &mut Machine, anchor: MovieAnchor) -> Self {
+        if anchor == MovieAnchor::PowerOn {
+            machine.reset();
+        }
+        Self {
+            movie: Movie {
+                title: machine.memory.cart.title_bytes(),
+                rom_hash: machine.memory.cart.rom_hash(),
+                model: machine.memory.model,
+                cheats: machine.cheats().clone(),
+                anchor,
+                snapshot: machine.snapshot(),
+                hash_interval: MOVIE_HASH_INTERVAL,
+                inputs: Vec::new(),
+                hashes: Vec::new(),
+            },
+        }
+    }
+
+    /// Number of frames recorded so far.
+    pub fn frames(&self) -> usize {
+        self.movie.frames()
+    }
+
+    /// Records a frame. Call it after running the frame.
+    pub fn end_frame(&mut self, machine: &Machine) {
+        self.movie
+            .inputs
+            .push(machine.memory.joypad.state().to_bits());
+        if self
+            .movie
+            .inputs
+            .len()
+            .is_multiple_of(self.movie.hash_interval as usize)
+        {
+            self.movie.hashes.push(machine.frame_hash());
+        }
+    }
+
+    /// Stops the recording and returns the movie.
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// # Movie player
+/// Feeds the inputs of a [Movie] into the machine, one frame at a time,
+/// and checks the frame hashes to detect desyncs.
+pub struct MoviePlayer {
+    movie: Movie,
+    /// Next frame to play.
+    frame: usize,
+    /// First frame whose hash did not match, if any.
+    desync: Option<usize>,
+    /// Cheats in use before the playback, restored by [MoviePlayer::finish].
+    cheats: CheatList,
+}
+
+impl MoviePlayer {
+    /// Starts the playback. The movie must belong to the ROM and the
+    /// hardware model of the machine. The machine is restored to the anchor
+    /// of the movie, and uses the cheats of the movie. If the anchor can't
+    /// be restored, the machine is left untouched.
+    pub fn new(movie: Movie, machine: &mut Machine) -> Result<Self> {
+        if movie.rom_hash != machine.memory.cart.rom_hash() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Movie belongs to a different ROM ({})", movie.title()),
+            ));
+        }
+        if movie.model != machine.memory.model {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Movie was recorded on {}, not {}",
+                    movie.model.description(),
+                    machine.memory.model.description()
+                ),
+            ));
+        }
+        machine.restore_snapshot(&movie.snapshot)?;
+        let cheats = machine.cheats().clone();
+        machine.set_cheats(movie.cheats.clone());
+        Ok(Self {
+            movie,
+            frame: 0,
+            desync: None,
+            cheats,
+        })
+    }
+
+    /// Stops the playback, and gives the machine back the cheats it had
+    /// before.
+    pub fn finish(self, machine: &mut Machine) {
+        machine.set_cheats(self.cheats);
+    }
+
+    /// Gets the movie being played.
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Number of frames played so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Whether all the frames have been played.
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames()
+    }
+
+    /// First frame (1-based) whose hash did not match the recording, if any.
+    pub fn desync(&self) -> Option<usize> {
+        self.desync
+    }
+
+    /// Sets the joypad state of the next frame. Call it before running the
+    /// frame. Returns `false` if the movie is finished.
+    pub fn begin_frame(&mut self, machine: &mut Machine) -> bool {
+        match self.movie.inputs.get(self.frame) {
+            Some(&bits) => {
+                machine.set_joypad(JoypadState::from_bits(bits));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks the frame hash, if due, after running the frame. Returns the
+    /// frame number if this is the first frame that desyncs.
+    pub fn end_frame(&mut self, machine: &Machine) -> Option<usize> {
+        if self.is_finished() {
+            return None;
+        }
+        self.frame += 1;
+        if self.desync.is_some() || !self.frame.is_multiple_of(self.movie.hash_interval as usize) {
+            return None;
+        }
+        let index = self.frame / self.movie.hash_interval as usize - 1;
+        match self.movie.hashes.get(index) {
+            Some(&hash) if hash != machine.frame_hash() => {
+                self.desync = Some(self.frame);
+                self.desync
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+
+    /// Creates a machine with an empty ROM, of the given model.
+    fn machine(model: Model) -> Machine {
+        let cart = Cartridge::from_bytes(vec![0u8; 0x8000], true).unwrap();
+        Machine::with_model(cart, model, false)
+    }
+
+    /// Runs the test in a thread with a large stack, since the machine is
+    /// too big for the default one in debug builds.
+    fn with_big_stack(test: fn()) {
+        std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn model_and_cheats() {
+        with_big_stack(model_and_cheats_body);
+    }
+
+    fn model_and_cheats_body() {
+        let mut m = machine(Model::Mgb);
+        let mut cheats = CheatList::new();
+        cheats.add("Lives", "C0A5=09").unwrap();
+        m.set_cheats(cheats.clone());
+        let mut recorder = MovieRecorder::new(&mut m, MovieAnchor::SaveState);
+        m.update();
+        recorder.end_frame(&m);
+        let movie = Movie::from_bytes(&recorder.finish().to_bytes()).unwrap();
+        assert_eq!(movie.model, Model::Mgb);
+        assert_eq!(movie.cheats, cheats);
+
+        // Other models are rejected.
+        let mut dmg = machine(Model::Dmg);
+        assert!(MoviePlayer::new(Movie::from_bytes(&movie.to_bytes()).unwrap(), &mut dmg).is_err());
+
+        // The cheats of the movie are used during playback only.
+        let mut m = machine(Model::Mgb);
+        let player = MoviePlayer::new(movie, &mut m).unwrap();
+        assert_eq!(m.cheats(), &cheats);
+        player.finish(&mut m);
+        assert!(m.cheats().is_empty());
+    }
+
+    #[test]
+    fn corrupt_anchor() {
+        with_big_stack(corrupt_anchor_body);
+    }
+
+    fn corrupt_anchor_body() {
+        let mut m = machine(Model::Dmg);
+        let mut movie = MovieRecorder::new(&mut m, MovieAnchor::SaveState).finish();
+        movie.snapshot.truncate(movie.snapshot.len() / 2);
+        m.update();
+        let before = m.snapshot();
+        assert!(MoviePlayer::new(movie, &mut m).is_err());
+        assert_eq!(m.snapshot(), before);
+    }
+}
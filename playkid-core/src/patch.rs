@@ -0,0 +1,474 @@
+use std::fmt;
+
+/// Magic number of IPS patches.
+const IPS_MAGIC: &[u8] = b"PATCH";
+/// End of file marker of IPS patches, in place of an offset.
+const IPS_EOF: usize = 0x454F46;
+/// Magic number of UPS patches.
+const UPS_MAGIC: &[u8] = b"UPS1";
+/// Magic number of BPS patches.
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Size of the UPS and BPS footer: source, target, and patch CRC32.
+const FOOTER_SIZE: usize = 12;
+/// Largest patched ROM, the size of the largest Game Boy ROM (8 MiB).
+pub const MAX_ROM_SIZE: usize = 0x80_0000;
+
+/// # Patch format
+/// The supported ROM patch formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    /// International Patching System. No checksums.
+    Ips,
+    /// Universal Patching System, with CRC32 of the source, target, and
+    /// patch.
+    Ups,
+    /// Beat Patching System, with CRC32 of the source, target, and patch.
+    Bps,
+}
+
+impl PatchFormat {
+    /// Detects the format from the magic number of the patch.
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        if patch.starts_with(IPS_MAGIC) {
+            Some(PatchFormat::Ips)
+        } else if patch.starts_with(UPS_MAGIC) {
+            Some(PatchFormat::Ups)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Some(PatchFormat::Bps)
+        } else {
+            None
+        }
+    }
+}
+
+/// # Patch error
+/// Everything that can go wrong when applying a patch.
+#[derive(Debug)]
+pub enum PatchError {
+    /// The patch is not IPS, UPS, or BPS.
+    UnknownFormat,
+    /// The patch ends in the middle of a record.
+    Truncated,
+    /// The patch CRC32 in the footer does not match the patch.
+    BadPatchCrc { stored: u32, computed: u32 },
+    /// The ROM is not the one the patch was made for.
+    BadSourceCrc { expected: u32, computed: u32 },
+    /// The ROM size is not the one the patch was made for.
+    BadSourceSize { expected: usize, size: usize },
+    /// The patched ROM is not the one the patch should produce.
+    BadTargetCrc { expected: u32, computed: u32 },
+    /// A record reads or writes out of bounds.
+    OutOfBounds,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => {
+                write!(f, "Unknown patch format, expected IPS, UPS or BPS")
+            }
+            PatchError::Truncated => write!(f, "Patch file is truncated"),
+            PatchError::BadPatchCrc { stored, computed } => write!(
+                f,
+                "Patch file is corrupt, CRC32 {:08x} != {:08x}",
+                stored, computed
+            ),
+            PatchError::BadSourceCrc { expected, computed } => write!(
+                f,
+                "Patch is for another ROM, CRC32 {:08x} != {:08x}",
+                computed, expected
+            ),
+            PatchError::BadSourceSize { expected, size } => {
+                write!(f, "Patch is for another ROM, size {} != {}", size, expected)
+            }
+            PatchError::BadTargetCrc { expected, computed } => write!(
+                f,
+                "Patched ROM is wrong, CRC32 {:08x} != {:08x}",
+                computed, expected
+            ),
+            PatchError::OutOfBounds => write!(f, "Patch reads or writes out of bounds"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Applies the given IPS, UPS, or BPS patch to the ROM data, and returns
+/// the patched ROM. The ROM data is left untouched. For UPS and BPS, the
+/// CRC32 of the ROM, of the result, and of the patch itself are verified.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    match PatchFormat::detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(rom, patch),
+        Some(PatchFormat::Ups) => apply_ups(rom, patch),
+        Some(PatchFormat::Bps) => apply_bps(rom, patch),
+        None => Err(PatchError::UnknownFormat),
+    }
+}
+
+/// Computes the CRC32 (IEEE 802.3) of the data, as used by UPS and BPS.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// Reads the patch sequentially.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        let byte = *self.data.get(self.pos).ok_or(PatchError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(n).ok_or(PatchError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(PatchError::Truncated)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    /// Reads a big-endian number of `n` bytes, as used by IPS.
+    fn be(&mut self, n: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .bytes(n)?
+            .iter()
+            .fold(0, |value, &b| (value << 8) | b as usize))
+    }
+
+    /// Reads a variable-length number, as used by UPS and BPS. Every byte
+    /// holds 7 bits, and the last one has bit 7 set.
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.u8()?;
+            value = value
+                .checked_add((byte & 0x7F) as usize * shift)
+                .ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::OutOfBounds)?;
+            value = value.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+}
+
+/// Applies an IPS patch. Records are a 3-byte offset, a 2-byte size, and
+/// the data. A zero size means a run: a 2-byte count and the byte to
+/// repeat. The patch ends with `EOF`, optionally followed by the 3-byte size
+/// to truncate the ROM to.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut r = Reader::new(patch, IPS_MAGIC.len());
+    loop {
+        let offset = r.be(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let size = r.be(2)?;
+        let (count, data) = if size == 0 {
+            let count = r.be(2)?;
+            (count, None)
+        } else {
+            (size, Some(r.bytes(size)?))
+        };
+        if out.len() < offset + count {
+            if offset + count > MAX_ROM_SIZE {
+                return Err(PatchError::OutOfBounds);
+            }
+            out.resize(offset + count, 0);
+        }
+        match data {
+            Some(data) => out[offset..offset + count].copy_from_slice(data),
+            None => out[offset..offset + count].fill(r.u8()?),
+        }
+    }
+    if let Ok(size) = r.be(3) {
+        out.truncate(size);
+    }
+    Ok(out)
+}
+
+/// Checks the footer of a UPS or BPS patch, and returns the source and
+/// target CRC32.
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<(u32, u32), PatchError> {
+    if patch.len() < 4 + FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+    let footer = &patch[patch.len() - FOOTER_SIZE..];
+    let crc =
+        |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+    let (source, target, stored) = (crc(0), crc(4), crc(8));
+    let computed = crc32(&patch[..patch.len() - 4]);
+    if computed != stored {
+        return Err(PatchError::BadPatchCrc { stored, computed });
+    }
+    let computed = crc32(rom);
+    if computed != source {
+        return Err(PatchError::BadSourceCrc {
+            expected: source,
+            computed,
+        });
+    }
+    Ok((source, target))
+}
+
+/// Checks the CRC32 of the patched ROM.
+fn check_target(out: &[u8], expected: u32) -> Result<(), PatchError> {
+    let computed = crc32(out);
+    if computed != expected {
+        return Err(PatchError::BadTargetCrc { expected, computed });
+    }
+    Ok(())
+}
+
+/// Applies a UPS patch. After the source and target sizes, every hunk skips
+/// a number of bytes, then XORs bytes into the ROM up to a zero byte.
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (_, target_crc) = check_footer(rom, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut r = Reader::new(&patch[..end], UPS_MAGIC.len());
+    let source_size = r.varint()?;
+    let target_size = r.varint()?;
+    if source_size != rom.len() {
+        return Err(PatchError::BadSourceSize {
+            expected: source_size,
+            size: rom.len(),
+        });
+    }
+    if target_size > MAX_ROM_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+    let mut pos = 0usize;
+    while r.pos < end {
+        pos = pos
+            .checked_add(r.varint()?)
+            .ok_or(PatchError::OutOfBounds)?;
+        loop {
+            let byte = r.u8()?;
+            if byte == 0 {
+                break;
+            }
+            *out.get_mut(pos).ok_or(PatchError::OutOfBounds)? ^= byte;
+            pos += 1;
+        }
+        pos += 1;
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+/// Applies a BPS patch. After the sizes and the metadata, every action
+/// copies bytes to the target from the ROM at the same offset, from the
+/// patch, or from a relative offset in the ROM or in the target itself.
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (_, target_crc) = check_footer(rom, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut r = Reader::new(&patch[..end], BPS_MAGIC.len());
+    let source_size = r.varint()?;
+    let target_size = r.varint()?;
+    let metadata_size = r.varint()?;
+    r.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(PatchError::BadSourceSize {
+            expected: source_size,
+            size: rom.len(),
+        });
+    }
+    if target_size > MAX_ROM_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+    // Reads a signed relative offset: bit 0 is the sign.
+    let relative = |r: &mut Reader<'_>| -> Result<isize, PatchError> {
+        let value = r.varint()?;
+        let magnitude = (value >> 1) as isize;
+        Ok(if value & 1 != 0 {
+            -magnitude
+        } else {
+            magnitude
+        })
+    };
+    while r.pos < end {
+        let action = r.varint()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - out.len() {
+            return Err(PatchError::OutOfBounds);
+        }
+        match action & 0x03 {
+            // Source read.
+            0 => {
+                let start = out.len();
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::OutOfBounds)?;
+                out.extend_from_slice(bytes);
+            }
+            // Target read.
+            1 => out.extend_from_slice(r.bytes(length)?),
+            // Source copy.
+            2 => {
+                source_offset = source_offset
+                    .checked_add(relative(&mut r)?)
+                    .ok_or(PatchError::OutOfBounds)?;
+                let start = usize::try_from(source_offset).map_err(|_| PatchError::OutOfBounds)?;
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::OutOfBounds)?;
+                out.extend_from_slice(bytes);
+                source_offset += length as isize;
+            }
+            // Target copy. The ranges can overlap, so byte by byte.
+            _ => {
+                target_offset = target_offset
+                    .checked_add(relative(&mut r)?)
+                    .ok_or(PatchError::OutOfBounds)?;
+                for _ in 0..length {
+                    let byte = usize::try_from(target_offset)
+                        .ok()
+                        .and_then(|i| out.get(i).copied())
+                        .ok_or(PatchError::OutOfBounds)?;
+                    out.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a UPS and BPS variable-length number.
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    /// Appends the UPS and BPS footer to the patch body.
+    fn with_footer(mut patch: Vec<u8>, rom: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32(rom).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips_rle_record() {
+        let rom = [0u8; 8];
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend([0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x7F]);
+        patch.extend(b"EOF");
+        let out = apply(&rom, &patch).unwrap();
+        assert_eq!(out, [0, 0, 0x7F, 0x7F, 0x7F, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ips_truncate() {
+        let rom = [0u8; 8];
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend([0x00, 0x00, 0x00, 0x00, 0x01, 0xAA]);
+        patch.extend(b"EOF");
+        patch.extend([0x00, 0x00, 0x04]);
+        let out = apply(&rom, &patch).unwrap();
+        assert_eq!(out, [0xAA, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ups_round_trip() {
+        let rom = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let target = [1u8, 2, 0xFF, 4, 5, 6, 7, 8, 9, 10];
+        let mut patch = UPS_MAGIC.to_vec();
+        patch.extend(varint(rom.len()));
+        patch.extend(varint(target.len()));
+        // Skip 2 bytes, XOR one, then skip to the end and append two.
+        patch.extend(varint(2));
+        patch.extend([3 ^ 0xFF, 0x00]);
+        patch.extend(varint(4));
+        patch.extend([9, 10, 0x00]);
+        let patch = with_footer(patch, &rom, &target);
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+        assert!(matches!(
+            apply(&target, &patch),
+            Err(PatchError::BadSourceCrc { .. })
+        ));
+    }
+
+    #[test]
+    fn bps_overlapping_target_copy() {
+        let rom = [0u8; 4];
+        let target = [0xA, 0xB, 0xA, 0xB, 0xA, 0xB];
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(varint(rom.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        // Target read of 2 bytes.
+        patch.extend(varint((1 << 2) | 1));
+        patch.extend([0xA, 0xB]);
+        // Target copy of 4 bytes from offset 0, which overlaps the output.
+        patch.extend(varint((3 << 2) | 3));
+        patch.extend(varint(0));
+        let patch = with_footer(patch, &rom, &target);
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn oversized_target() {
+        let rom = [0u8; 4];
+        let mut patch = UPS_MAGIC.to_vec();
+        patch.extend(varint(rom.len()));
+        patch.extend(varint(usize::MAX >> 8));
+        let patch = with_footer(patch, &rom, &[]);
+        assert!(matches!(apply(&rom, &patch), Err(PatchError::OutOfBounds)));
+    }
+
+    #[test]
+    fn bps_offset_overflow() {
+        let rom = [0u8; 4];
+        // Source copy and target copy.
+        for kind in [2, 3] {
+            let mut patch = BPS_MAGIC.to_vec();
+            patch.extend(varint(rom.len()));
+            patch.extend(varint(3));
+            patch.extend(varint(0));
+            // Source read of 1 byte, then a copy of 1 byte from offset 0,
+            // which moves the offset past 0.
+            patch.extend(varint(0));
+            patch.extend(varint(kind));
+            patch.extend(varint(0));
+            // A copy from the largest positive relative offset.
+            patch.extend(varint(kind));
+            patch.extend(varint((isize::MAX as usize) << 1));
+            let patch = with_footer(patch, &rom, &[0, 0, 0]);
+            assert!(matches!(apply(&rom, &patch), Err(PatchError::OutOfBounds)));
+        }
+    }
+}
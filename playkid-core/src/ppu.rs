@@ -1,10 +1,90 @@
+use crate::colorization::Colorization;
 use crate::constants;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 use colored::Colorize;
+use fifo::Fifo;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+mod fifo;
 
 /// Key for sprite tile row cache.
-type SpriteTileKey = (u8, u8, bool, bool);
+type SpriteTileKey = (u8, u8, bool, bool, u8);
+
+/// # Renderer
+/// How the PPU draws the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Renderer {
+    /// Pixel FIFO and fetcher, clocked every dot like the hardware. Mid-line
+    /// register writes show up, and the length of mode 3 depends on the
+    /// scroll, the window, and the sprites.
+    #[default]
+    Fifo,
+    /// Whole lines at once, when entering HBlank, with a fixed mode 3
+    /// length. Faster, but raster effects within a line are lost.
+    Scanline,
+}
+
+impl Renderer {
+    /// All the renderers.
+    pub const ALL: [Renderer; 2] = [Renderer::Fifo, Renderer::Scanline];
+
+    /// Index in [Renderer::ALL], as stored in save states.
+    fn index(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Renderer at the given index in [Renderer::ALL]. Out of range indices
+    /// fall back to the default.
+    fn from_index(index: u8) -> Renderer {
+        Renderer::ALL
+            .get(index as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Short name, as used in the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Renderer::Fifo => "fifo",
+            Renderer::Scanline => "scanline",
+        }
+    }
+
+    /// Human-readable name.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Renderer::Fifo => "Pixel FIFO (accurate)",
+            Renderer::Scanline => "Scanline (fast)",
+        }
+    }
+}
+
+impl fmt::Display for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Renderer::ALL
+            .into_iter()
+            .find(|r| r.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Renderer::ALL.iter().map(|r| r.name()).collect();
+                format!(
+                    "unknown renderer '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
 
 /// # PPU
 /// The PPU is the Picture Processing Unit of our machine.
@@ -23,11 +103,31 @@ type SpriteTileKey = (u8, u8, bool, bool);
 ///   Tiles in a bank are typically grouped into blocks.
 /// - A **tile block** contains 128 tiles of 16 bytes each, so 2048 bytes.
 /// - A **map** contains 32x32=1024 bytes.
+///
+/// ## Game Boy Color
+/// The CGB has a second VRAM bank, selected with VBK. In bank 1, the maps
+/// hold the BG attributes of each tile (palette, tile bank, flips, and
+/// priority). Colors come from 8 BG and 8 OBJ palettes of 4 RGB555 colors,
+/// accessed through BCPS/BCPD and OCPS/OCPD.
 pub struct Ppu {
+    /// Runs in CGB mode, with the CGB registers, VRAM bank, and palettes.
+    cgb_mode: bool,
     /// Object Attribute Memory.
     pub oam: [u8; constants::OAM_SIZE],
     /// Video RAM.
     pub vram: [u8; constants::VRAM_SIZE],
+    /// Video RAM bank 1 (CGB only).
+    pub vram1: [u8; constants::VRAM_SIZE],
+    /// VBK: VRAM bank select (CGB only).
+    vbk: u8,
+    /// BG palette RAM: 8 palettes of 4 colors, 2 bytes each (CGB only).
+    bg_cram: [u8; 64],
+    /// OBJ palette RAM: 8 palettes of 4 colors, 2 bytes each (CGB only).
+    obj_cram: [u8; 64],
+    /// BCPS: BG palette index, with auto-increment in bit 7.
+    bcps: u8,
+    /// OCPS: OBJ palette index, with auto-increment in bit 7.
+    ocps: u8,
 
     /// There are four modes:
     /// - 0: HBlank
@@ -111,14 +211,33 @@ pub struct Ppu {
     palette: [u8; 4 * 3],
     /// Index of the current palette.
     current_palette: u8,
+    /// Separate BG, OBJ0, and OBJ1 palettes picked for the cartridge.
+    colorization: Option<Colorization>,
+    /// Use the colorization instead of the palette.
+    auto_palette: bool,
     /// The buffer currently being drawn to by the PPU (Back Buffer)
     fb_back: [u8; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH * 4],
     /// The buffer ready to be displayed (Front Buffer)
     pub fb_front: [u8; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH * 4],
-    /// Color ID buffer for priorities.
+    /// Shades (0-3) of the pixels of the back buffer, after BGP/OBP (DMG only).
+    shades_back: [u8; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH],
+    /// Shades (0-3) of the pixels of the front buffer. The Super Game Boy
+    /// colors these.
+    pub shades: [u8; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH],
+    /// Color ID buffer for priorities. In CGB mode, bit 7 holds the BG
+    /// priority attribute of the tile.
     pub priorities: [u8; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH],
+
+    /// The renderer in use.
+    active_renderer: Renderer,
+    /// The renderer to switch to, outside of mode 3.
+    next_renderer: Renderer,
+    /// State of the pixel FIFO renderer within the current line.
+    fifo: Fifo,
 }
 
+/// Name of the automatic CGB-style colorization.
+pub const AUTO_PALETTE_NAME: &str = "Auto (CGB)";
 /// Palette names.
 pub const PALETTE_NAMES: [&str; 18] = [
     "Game Boy",
@@ -163,13 +282,20 @@ pub const PALETTES: [[u8; 12]; 18] = [
 ];
 
 impl Ppu {
-    pub fn new(start_dot: u64) -> Self {
+    pub fn new(start_dot: u64, cgb_mode: bool) -> Self {
         // Default palette.
         let palette = PALETTES[0];
 
         Ppu {
+            cgb_mode,
             oam: [0xFF; constants::OAM_SIZE],
             vram: [0; constants::VRAM_SIZE],
+            vram1: [0; constants::VRAM_SIZE],
+            vbk: 0,
+            bg_cram: [0xFF; 64],
+            obj_cram: [0xFF; 64],
+            bcps: 0,
+            ocps: 0,
             mode: 0,
             lcdc: 0,
             lcdc7: true,
@@ -206,17 +332,32 @@ impl Ppu {
 
             palette,
             current_palette: 0,
+            colorization: None,
+            auto_palette: false,
             fb_front: [0xff; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH * 4],
             fb_back: [0xff; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH * 4],
+            shades_back: [0; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH],
+            shades: [0; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH],
             priorities: [0x01; constants::DISPLAY_HEIGHT * constants::DISPLAY_WIDTH],
+            active_renderer: Renderer::default(),
+            next_renderer: Renderer::default(),
+            fifo: Fifo::new(),
         }
     }
 
     pub fn reset(&mut self) {
         self.oam.fill(0xff);
         self.vram.fill(0x00);
+        self.vram1.fill(0x00);
+        self.vbk = 0;
+        self.bg_cram.fill(0xFF);
+        self.obj_cram.fill(0xFF);
+        self.bcps = 0;
+        self.ocps = 0;
         self.fb_front.fill(0xff);
         self.fb_back.fill(0xff);
+        self.shades_back.fill(0);
+        self.shades.fill(0);
         self.priorities.fill(0x00);
         self.mode = 0;
         self.lcdc = 0;
@@ -238,29 +379,36 @@ impl Ppu {
         self.i_mask = 0;
         self.hblank = false;
         self.last_ly_eq_lyc = false;
+        self.fifo = Fifo::new();
+    }
+
+    /// Gets the renderer in use, or about to be.
+    pub fn renderer(&self) -> Renderer {
+        self.next_renderer
+    }
+
+    /// Sets the renderer. It takes over outside of mode 3, so that a line is
+    /// never drawn half by each.
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.next_renderer = renderer;
+        if self.mode != 3 {
+            self.active_renderer = renderer;
+        }
     }
 
     /// Read a byte from a PPU.
     pub fn read(&self, address: u16) -> u8 {
         match address {
-            // VRAM.
-            0x8000..=0x9FFF => {
-                if self.mode != 3 {
-                    self.vram[(address - 0x8000) as usize]
-                } else {
-                    // During mode 3 VRAM is inaccessible.
-                    0xFF
-                }
-            }
-            // OAM.
-            0xFE00..=0xFE9F => {
-                if self.mode & 0x02 == 0 {
-                    self.oam[(address - 0xfe00) as usize]
-                } else {
-                    // During modes 2 and 3 OAM is inaccessible.
-                    0xFF
-                }
-            }
+            // VRAM. During mode 3 VRAM is inaccessible.
+            0x8000..=0x9FFF => match self.mode {
+                3 => 0xFF,
+                _ => self.vram_bank(self.vbk)[(address - 0x8000) as usize],
+            },
+            // OAM. During modes 2 and 3 OAM is inaccessible.
+            0xFE00..=0xFE9F => match self.mode & 0x02 {
+                0 => self.oam[(address - 0xfe00) as usize],
+                _ => 0xFF,
+            },
             // LCDC.
             0xFF40 => self.lcdc,
             // STAT - bit 7 is always 1 when STAT is read, hence the OR.
@@ -294,24 +442,72 @@ impl Ppu {
             // WX.
             0xFF4B => self.wx,
 
+            // VBK.
+            0xFF4F if self.cgb_mode => 0xFE | self.vbk,
+            // BCPS/OCPS. Bit 6 is unused.
+            0xFF68 if self.cgb_mode => self.bcps | 0x40,
+            0xFF6A if self.cgb_mode => self.ocps | 0x40,
+            // BCPD/OCPD. Palette RAM is inaccessible during mode 3.
+            0xFF69 if self.cgb_mode && self.mode != 3 => self.bg_cram[(self.bcps & 0x3F) as usize],
+            0xFF6B if self.cgb_mode && self.mode != 3 => self.obj_cram[(self.ocps & 0x3F) as usize],
+
             _ => 0xFF,
         }
     }
 
+    /// Gets the given VRAM bank. Bank 1 only exists in CGB mode.
+    fn vram_bank(&self, bank: u8) -> &[u8; constants::VRAM_SIZE] {
+        if bank & 0x01 != 0 && self.cgb_mode {
+            &self.vram1
+        } else {
+            &self.vram
+        }
+    }
+
+    /// Gets the data of the first 256 BG tiles on screen, in rows of 20
+    /// tiles, as the Super Game Boy reads VRAM transfers. Games display
+    /// tiles 0-255 in order for those.
+    pub fn screen_tile_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(256 * 16);
+        for n in 0..256u16 {
+            let map_index = (self.lcdc3 + (n / 20) * 32 + n % 20 - 0x8000) as usize;
+            let tile_id = self.vram[map_index];
+            let tile = if self.lcdc & 0x10 != 0 {
+                tile_id as usize * 16
+            } else {
+                (0x1000 + tile_id as i8 as isize * 16) as usize
+            };
+            data.extend_from_slice(&self.vram[tile..tile + 16]);
+        }
+        data
+    }
+
+    /// Writes a byte to the current VRAM bank, regardless of the mode. Used by
+    /// VRAM DMA (HDMA).
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        let index = (address & 0x1FFF) as usize;
+        if self.vbk & 0x01 != 0 && self.cgb_mode {
+            self.vram1[index] = value;
+        } else {
+            self.vram[index] = value;
+        }
+    }
+
+    /// Writes a byte to OAM, regardless of the mode. Used by OAM DMA.
+    pub fn write_oam(&mut self, index: u16, value: u8) {
+        self.oam[index as usize] = value;
+    }
+
     /// Write a byte to a PPU address.
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
-            0x8000..=0x9FFF => {
-                // VRAM only accessible when mode != 3.
-                if self.mode != 3 {
-                    self.vram[(address - 0x8000) as usize] = value;
-                }
+            // VRAM only accessible when mode != 3.
+            0x8000..=0x9FFF if self.mode != 3 => {
+                self.write_vram(address, value);
             }
-            0xFE00..=0xFE9F => {
-                // OAM inaccessible in modes 2 and 3.
-                if self.mode & 0x02 == 0 {
-                    self.oam[(address - 0xFE00) as usize] = value;
-                }
+            // OAM inaccessible in modes 2 and 3.
+            0xFE00..=0xFE9F if self.mode & 0x02 == 0 => {
+                self.oam[(address - 0xFE00) as usize] = value;
             }
             // LCDC.
             0xFF40 => {
@@ -339,7 +535,7 @@ impl Ppu {
                 // The written value specifies the transfer source address divided by $100.
                 // Source: $XX00-$XX9F (where XX is the written value).
                 // Dest:   $FE00-FE9F
-                // This is implemented in `memory.rs`.
+                // This is implemented in `memory.rs`, which also reads it.
             }
             // BGP.
             0xFF47 => {
@@ -354,10 +550,39 @@ impl Ppu {
             0xFF4A => self.wy = value,
             // WX.
             0xFF4B => self.wx = value,
+
+            // VBK.
+            0xFF4F if self.cgb_mode => self.vbk = value & 0x01,
+            // BCPS/OCPS.
+            0xFF68 if self.cgb_mode => self.bcps = value & 0xBF,
+            0xFF6A if self.cgb_mode => self.ocps = value & 0xBF,
+            // BCPD/OCPD. Writes during mode 3 are ignored, but still
+            // increment the index.
+            0xFF69 if self.cgb_mode => {
+                if self.mode != 3 {
+                    self.bg_cram[(self.bcps & 0x3F) as usize] = value;
+                }
+                self.bcps = Self::increment_palette_index(self.bcps);
+            }
+            0xFF6B if self.cgb_mode => {
+                if self.mode != 3 {
+                    self.obj_cram[(self.ocps & 0x3F) as usize] = value;
+                }
+                self.ocps = Self::increment_palette_index(self.ocps);
+            }
             _ => {}
         }
     }
 
+    /// Increments the index of a BCPS/OCPS value if auto-increment is on.
+    fn increment_palette_index(spec: u8) -> u8 {
+        if spec & 0x80 != 0 {
+            0x80 | (spec.wrapping_add(1) & 0x3F)
+        } else {
+            spec
+        }
+    }
+
     /// Performs a GPU cycle with the given number of t-cycles, or dots.
     /// 1 m-cycle has 4 dots, or t-cycles.
     /// Timing is divided between 154 lines, 144 during VDraw (modes 0, 2, 3),
@@ -368,7 +593,22 @@ impl Ppu {
             return;
         }
         self.hblank = false;
+        if self.mode != 3 {
+            self.active_renderer = self.next_renderer;
+        }
+        match self.active_renderer {
+            Renderer::Fifo => {
+                for _ in 0..t_cycles {
+                    self.dot();
+                }
+            }
+            Renderer::Scanline => self.cycle_scanline(t_cycles),
+        }
+    }
 
+    /// Advances the scanline renderer. The modes change at fixed dots, and
+    /// the line is drawn when entering HBlank.
+    fn cycle_scanline(&mut self, t_cycles: u64) {
         self.fdot += t_cycles;
 
         // Determine the mode based on the CURRENT scanline (ly) and dots (fdot).
@@ -394,6 +634,26 @@ impl Ppu {
         }
     }
 
+    /// Moves the PPU to the given line and dot, to match the state the boot
+    /// ROM leaves behind.
+    pub fn set_phase(&mut self, ly: u8, dot: u64) {
+        self.ly = ly;
+        self.fdot = dot;
+        self.mode = if ly >= 144 {
+            1
+        } else {
+            match dot {
+                0..=80 => 2,
+                81..=252 => 3,
+                _ => 0,
+            }
+        };
+        if self.mode == 3 && self.active_renderer == Renderer::Fifo {
+            self.fifo_start_line();
+        }
+        self.update_stat_ly_lyc();
+    }
+
     fn update_stat_ly_lyc(&mut self) {
         // Update mode bits in STAT (bits 0–1).
         self.stat = (self.stat & 0b1111_1100) | (self.mode & 0b0000_0011);
@@ -409,6 +669,7 @@ impl Ppu {
     /// Present the back buffer for rendering.
     pub fn present(&mut self) {
         self.fb_front.copy_from_slice(&self.fb_back);
+        self.shades.copy_from_slice(&self.shades_back);
     }
 
     /// Updates the PPU mode and triggers the necessary actions.
@@ -418,9 +679,12 @@ impl Ppu {
         self.update_stat_ly_lyc();
 
         if match self.mode {
-            // HBlank.
+            // HBlank. The pixel FIFO has drawn the line already.
             0 => {
-                self.render_scanline();
+                match self.active_renderer {
+                    Renderer::Scanline => self.render_scanline(),
+                    Renderer::Fifo => self.fifo_end_line(),
+                }
                 self.hblank = true;
                 self.stat3
             }
@@ -470,8 +734,9 @@ impl Ppu {
     }
 
     /// Fetches and combines the two bytes of pixel data (low/high) for a tile row,
-    /// for the background/window.
-    fn get_bgwin_tile_data(&mut self, tile_id: u8, line: u16, use_unsigned: bool) -> [u8; 8] {
+    /// for the background/window. The attributes are only used in CGB mode, and
+    /// select the VRAM bank and the flips.
+    fn get_bgwin_tile_data(&self, tile_id: u8, line: u16, use_unsigned: bool, attr: u8) -> [u8; 8] {
         let tile_addr_base = if use_unsigned {
             // Unsigned mode: tile_id directly indexes from 0x8000.
             // 0x8000 + (tile_id * 16)
@@ -485,10 +750,13 @@ impl Ppu {
             (self.lcdc4 as i16 + (signed_tile_id + 128) * 16) as u16
         };
 
-        let tile_addr = tile_addr_base + (line * 2);
+        // Vertical flip.
+        let line = if attr & 0x40 != 0 { 7 - line } else { line };
+        let tile_addr = (tile_addr_base + (line * 2) - 0x8000) as usize;
 
-        let low_byte = self.read(tile_addr);
-        let high_byte = self.read(tile_addr + 1);
+        let bank = self.vram_bank(attr >> 3);
+        let low_byte = bank[tile_addr];
+        let high_byte = bank[tile_addr + 1];
         let mut pixels = [0u8; 8];
 
         // Each bit pair in the bytes represents a pixel color ID.
@@ -496,6 +764,10 @@ impl Ppu {
             let color_id = ((high_byte >> (7 - i)) & 0x1) << 1 | ((low_byte >> (7 - i)) & 0x1);
             *item = color_id;
         }
+        // Horizontal flip.
+        if attr & 0x20 != 0 {
+            pixels.reverse();
+        }
         pixels
     }
 
@@ -506,9 +778,10 @@ impl Ppu {
         // Tracking variable for window rendering.
         let mut win_was_rendered = false;
 
-        // Use 0xFF as sentinel since valid color IDs are 0-3.
-        let mut bg_cache = [[0xffu8; 8]; 32];
-        let mut win_cache = [[0xffu8; 8]; 32];
+        // Decoded tile rows and their attributes.
+        let mut bg_cache: [Option<([u8; 8], u8)>; 32] = [None; 32];
+        let mut win_cache: [Option<([u8; 8], u8)>; 32] = [None; 32];
+        let cgb = self.cgb_mode;
 
         for x in 0..constants::DISPLAY_WIDTH {
             let win_active_now = self.lcdc5 && self.wly_flag;
@@ -532,8 +805,9 @@ impl Ppu {
                     win_tile_y,
                     &mut win_cache,
                 )
-            } else if self.lcdc0 {
-                // Background pixel.
+            } else if self.lcdc0 || cgb {
+                // Background pixel. In CGB mode, LCDC0 is the BG priority
+                // switch instead.
                 let bg_x = self.scx as u32 + x as u32;
                 let bg_y = self.scy.wrapping_add(self.ly);
                 let bg_tile_x = (bg_x as u16 / 8) & 0x1f;
@@ -551,28 +825,39 @@ impl Ppu {
                 // LCDC0 disabled: render white background (color 0).
                 // Still need to set priorities for sprite rendering!
                 self.priorities[self.ly as usize * constants::DISPLAY_WIDTH + x] = 0;
-                self.color(x, self.ly, 0);
+                self.color(x, self.ly, 0, Layer::Bg);
                 continue;
             };
 
             // Fetch the tile data if not already cached.
             let tile_index = tile_y * 32 + tile_x;
 
-            if cache[tile_x as usize][0] == 0xff {
-                let tile_id = self.read(tile_map_addr + tile_index);
-                let tile_data = self.get_bgwin_tile_data(tile_id, px_y, use_unsigned);
-                cache[tile_x as usize] = tile_data;
-            }
+            let (tile_pixels, attr) = match cache[tile_x as usize] {
+                Some(entry) => entry,
+                None => {
+                    let map_index = (tile_map_addr + tile_index - 0x8000) as usize;
+                    let tile_id = self.vram[map_index];
+                    let attr = if cgb { self.vram1[map_index] } else { 0 };
+                    let tile_data = self.get_bgwin_tile_data(tile_id, px_y, use_unsigned, attr);
+                    cache[tile_x as usize] = Some((tile_data, attr));
+                    (tile_data, attr)
+                }
+            };
 
             // Get the color index from the tile data.
-            let tile_pixels = cache[tile_x as usize];
             let color_idx = tile_pixels[px_x as usize];
-            let color = (self.bgp >> (color_idx * 2)) & 0x03;
 
-            // Store the raw ID for sprite priority checks.
-            self.priorities[self.ly as usize * constants::DISPLAY_WIDTH + x] = color_idx;
+            // Store the raw ID (and the CGB priority attribute) for sprite
+            // priority checks.
+            self.priorities[self.ly as usize * constants::DISPLAY_WIDTH + x] =
+                color_idx | (attr & 0x80);
             // Render the pixel
-            self.color(x, self.ly, color);
+            if cgb {
+                self.color_cgb(x, self.ly, false, attr & 0x07, color_idx);
+            } else {
+                let color = (self.bgp >> (color_idx * 2)) & 0x03;
+                self.color(x, self.ly, color, Layer::Bg);
+            }
         }
 
         // Increment WLY only if the window was rendered in this scanline.
@@ -649,15 +934,21 @@ impl Ppu {
             (tile_id, effective_line)
         };
 
-        let key = (tile_id, effective_line, hflip, vflip);
+        // Attribute bit 3 selects the VRAM bank in CGB mode.
+        let bank = if self.cgb_mode {
+            (attributes >> 3) & 0x01
+        } else {
+            0
+        };
+        let key = (tile_id, effective_line, hflip, vflip, bank);
 
         if let Some(cached) = cache.get(&key) {
             return *cached;
         }
 
-        let tile_addr = 0x8000 + (tile_id as u16) * 16;
-        let low_byte = self.read(tile_addr + (effective_line as u16) * 2);
-        let high_byte = self.read(tile_addr + (effective_line as u16) * 2 + 1);
+        let tile_addr = (tile_id as usize) * 16 + (effective_line as usize) * 2;
+        let low_byte = self.vram_bank(bank)[tile_addr];
+        let high_byte = self.vram_bank(bank)[tile_addr + 1];
 
         let mut pixels = [0u8; 8];
         // Horizontal flip.
@@ -683,16 +974,20 @@ impl Ppu {
         let mut sprites = sprites;
 
         // Game Boy priority: X asc, then OAM index asc.
-        sprites.sort_by(|a, b| {
-            if a.x != b.x {
-                a.x.cmp(&b.x)
-            } else {
-                a.oam_index.cmp(&b.oam_index)
-            }
-        });
+        // In CGB mode, only the OAM index counts, and sprites are already
+        // in that order.
+        if !self.cgb_mode {
+            sprites.sort_by(|a, b| {
+                if a.x != b.x {
+                    a.x.cmp(&b.x)
+                } else {
+                    a.oam_index.cmp(&b.oam_index)
+                }
+            });
+        }
 
         // Cache for decoded sprite rows.
-        // - Key: (tile_id, line, hflip, vflip)
+        // - Key: (tile_id, line, hflip, vflip, bank)
         // - Value: [u8; 8] for the  pixels
         let mut tile_row_cache = HashMap::new();
 
@@ -726,48 +1021,96 @@ impl Ppu {
             // Fetch BG color ID from priorities cache.
             let bg_color_id = self.priorities[self.ly as usize * constants::DISPLAY_WIDTH + x_pos];
 
-            // Priority logic:
-            // Bit 7 (OBJ-to-BG Priority):
-            //   0 = OBJ above BG (always draw sprite if not transparent)
-            //   1 = OBJ behind BG colors 1-3 (only draw if BG is color 0)
-            let obj_behind_bg = (sprite.attributes & 0x80) != 0;
-            let sprite_has_priority = if obj_behind_bg {
-                // Priority bit set: only draw sprite over BG color 0.
-                bg_color_id == 0
-            } else {
-                // Priority bit clear: always draw sprite (over any BG color).
-                true
-            };
+            let sprite_has_priority = self.obj_has_priority(sprite.attributes, bg_color_id);
 
-            if sprite_has_priority {
-                let palette = if sprite.attributes & 0x10 != 0 {
-                    self.obp1
+            if sprite_has_priority && self.cgb_mode {
+                self.color_cgb(x_pos, self.ly, true, sprite.attributes & 0x07, *color_idx);
+            } else if sprite_has_priority {
+                let (palette, layer) = if sprite.attributes & 0x10 != 0 {
+                    (self.obp1, Layer::Obj1)
                 } else {
-                    self.obp0
+                    (self.obp0, Layer::Obj0)
                 };
                 let color = (palette >> (color_idx * 2)) & 0x03;
 
-                self.color(x_pos, self.ly, color);
+                self.color(x_pos, self.ly, color, layer);
             }
         }
     }
 
+    /// Whether a sprite pixel with the given attributes is drawn over the BG
+    /// pixel with the given entry of the priorities buffer.
+    fn obj_has_priority(&self, attributes: u8, bg_color_id: u8) -> bool {
+        // Priority logic:
+        // Bit 7 (OBJ-to-BG Priority):
+        //   0 = OBJ above BG (always draw sprite if not transparent)
+        //   1 = OBJ behind BG colors 1-3 (only draw if BG is color 0)
+        let obj_behind_bg = (attributes & 0x80) != 0;
+        let bg_behind_obj = bg_color_id & 0x03 == 0;
+        if self.cgb_mode {
+            // In CGB mode, LCDC0 clear puts all sprites on top, and the BG
+            // attribute priority bit also puts the BG above the sprite.
+            !self.lcdc0 || bg_behind_obj || (!obj_behind_bg && bg_color_id & 0x80 == 0)
+        } else if obj_behind_bg {
+            // Priority bit set: only draw sprite over BG color 0.
+            bg_behind_obj
+        } else {
+            // Priority bit clear: always draw sprite (over any BG color).
+            true
+        }
+    }
+
     /// Sets the pixel at the given position to the given color id.
     // In PPU struct, ensure priorities stores the raw Color ID (0-3)
-    fn color(&mut self, x: usize, y: u8, paletted_color: u8) {
+    fn color(&mut self, x: usize, y: u8, paletted_color: u8, layer: Layer) {
         let pos = y as usize * constants::DISPLAY_WIDTH + x;
         let base = paletted_color as usize * 3;
+        let palette = *self.layer_palette(layer);
 
+        self.shades_back[pos] = paletted_color;
         // RGBA, in order.
-        self.fb_back[pos * 4] = self.palette[base];
-        self.fb_back[pos * 4 + 1] = self.palette[base + 1];
-        self.fb_back[pos * 4 + 2] = self.palette[base + 2];
+        self.fb_back[pos * 4] = palette[base];
+        self.fb_back[pos * 4 + 1] = palette[base + 1];
+        self.fb_back[pos * 4 + 2] = palette[base + 2];
+        self.fb_back[pos * 4 + 3] = 0xff;
+    }
+
+    /// Gets the colors of the given layer: the colorization, if active, or
+    /// the palette.
+    fn layer_palette(&self, layer: Layer) -> &[u8; 12] {
+        match &self.colorization {
+            Some(c) if self.auto_palette => match layer {
+                Layer::Bg => &c.bg,
+                Layer::Obj0 => &c.obj0,
+                Layer::Obj1 => &c.obj1,
+            },
+            _ => &self.palette,
+        }
+    }
+
+    /// Sets the pixel at the given position to a color of the CGB palette RAM.
+    fn color_cgb(&mut self, x: usize, y: u8, obj: bool, palette: u8, color_idx: u8) {
+        let pos = y as usize * constants::DISPLAY_WIDTH + x;
+        let cram = if obj { &self.obj_cram } else { &self.bg_cram };
+        let i = palette as usize * 8 + color_idx as usize * 2;
+        let rgb555 = u16::from_le_bytes([cram[i], cram[i + 1]]);
+
+        // RGB555 to RGB888.
+        let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+        self.fb_back[pos * 4] = expand(rgb555 & 0x1F);
+        self.fb_back[pos * 4 + 1] = expand((rgb555 >> 5) & 0x1F);
+        self.fb_back[pos * 4 + 2] = expand((rgb555 >> 10) & 0x1F);
         self.fb_back[pos * 4 + 3] = 0xff;
     }
 
     fn clear_screen(&mut self) {
-        // Get the first palette color (RGB888 format).
-        let (r, g, b) = (self.palette[0], self.palette[1], self.palette[2]);
+        // Get the first palette color (RGB888 format). The CGB shows white.
+        let (r, g, b) = if self.cgb_mode {
+            (0xff, 0xff, 0xff)
+        } else {
+            let palette = self.layer_palette(Layer::Bg);
+            (palette[0], palette[1], palette[2])
+        };
 
         self.fb_front.chunks_exact_mut(4).for_each(|chunk| {
             chunk[0] = r;
@@ -782,6 +1125,8 @@ impl Ppu {
             chunk[3] = 0xff;
         });
 
+        self.shades_back.fill(0);
+        self.shades.fill(0);
         // Set the priorities to all ones.
         self.priorities.fill(0xff);
     }
@@ -856,22 +1201,72 @@ impl Ppu {
         self.stat3 = self.stat & 0b0000_1000 != 0;
     }
 
+    /// Gets the current PPU mode (0: HBlank, 1: VBlank, 2: OAM scan, 3: drawing).
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+
     /// Are the LCD and the PPU enabled?
     pub fn is_ppu_enabled(&self) -> bool {
         self.lcdc7
     }
 
-    /// Cycles through the palettes in direct order.
+    /// Cycles through the palettes in direct order. The colorization, if
+    /// any, comes after the last palette.
     pub fn cycle_palette(&mut self) {
-        self.set_palette((self.current_palette + 1) % PALETTES.len() as u8);
+        let last = PALETTES.len() as u8 - 1;
+        if self.auto_palette {
+            self.set_palette(0);
+        } else if self.current_palette == last && self.colorization.is_some() {
+            self.set_auto_palette();
+        } else {
+            self.set_palette((self.current_palette + 1) % PALETTES.len() as u8);
+        }
     }
     /// Cycles through the palettes in reverse order.
     pub fn cycle_palette_rev(&mut self) {
-        self.set_palette((self.current_palette + PALETTES.len() as u8 - 1) % PALETTES.len() as u8);
+        let last = PALETTES.len() as u8 - 1;
+        if self.auto_palette {
+            self.set_palette(last);
+        } else if self.current_palette == 0 && self.colorization.is_some() {
+            self.set_auto_palette();
+        } else {
+            self.set_palette((self.current_palette + last) % PALETTES.len() as u8);
+        }
+    }
+
+    /// Sets the colorization of the cartridge, and uses it.
+    pub fn set_colorization(&mut self, colorization: Colorization) {
+        self.colorization = Some(colorization);
+        self.auto_palette = true;
+    }
+
+    /// Uses the colorization of the cartridge instead of the palette. Does
+    /// nothing if there is no colorization.
+    pub fn set_auto_palette(&mut self) {
+        if self.colorization.is_some() {
+            self.auto_palette = true;
+            println!(
+                "{}: Palette changed to {}",
+                "OK".green(),
+                AUTO_PALETTE_NAME.yellow()
+            );
+        }
+    }
+
+    /// Is the colorization of the cartridge in use?
+    pub fn is_auto_palette(&self) -> bool {
+        self.auto_palette
+    }
+
+    /// Is there a colorization for the cartridge?
+    pub fn has_colorization(&self) -> bool {
+        self.colorization.is_some()
     }
 
     /// Set the palette index.
     pub fn set_palette(&mut self, index: u8) {
+        self.auto_palette = false;
         self.current_palette = index;
         self.palette = PALETTES[self.current_palette as usize];
         println!(
@@ -888,10 +1283,107 @@ impl Ppu {
 
     /// Get the name of the current palette in use.
     pub fn get_palette_name(&self) -> &str {
+        if self.auto_palette {
+            return AUTO_PALETTE_NAME;
+        }
         PALETTE_NAMES[self.current_palette as usize]
     }
 }
 
+impl SaveState for Ppu {
+    /// The palette is a user setting, so it is not part of the state.
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.oam);
+        w.bytes(&self.vram);
+        w.bytes(&self.vram1);
+        w.u8(self.vbk);
+        w.bytes(&self.bg_cram);
+        w.bytes(&self.obj_cram);
+        w.u8(self.bcps);
+        w.u8(self.ocps);
+        w.u8(self.mode);
+        w.u64(self.fdot);
+        w.u64(self.ldot);
+        w.u8(self.lcdc);
+        w.u8(self.lx);
+        w.u8(self.ly);
+        w.u8(self.lyc);
+        w.u8(self.stat);
+        w.u8(self.scy);
+        w.u8(self.scx);
+        w.u8(self.wy);
+        w.u8(self.wx);
+        w.u16(self.wly);
+        w.bool(self.wly_flag);
+        w.u8(self.bgp);
+        w.u8(self.obp0);
+        w.u8(self.obp1);
+        w.u8(self.i_mask);
+        w.bool(self.hblank);
+        w.bool(self.last_ly_eq_lyc);
+        w.bytes(&self.fb_back);
+        w.bytes(&self.fb_front);
+        w.bytes(&self.shades_back);
+        w.bytes(&self.shades);
+        w.bytes(&self.priorities);
+        w.u8(self.active_renderer.index());
+        w.u8(self.next_renderer.index());
+        self.fifo.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.oam)?;
+        r.bytes_into(&mut self.vram)?;
+        r.bytes_into(&mut self.vram1)?;
+        self.vbk = r.u8()?;
+        r.bytes_into(&mut self.bg_cram)?;
+        r.bytes_into(&mut self.obj_cram)?;
+        self.bcps = r.u8()?;
+        self.ocps = r.u8()?;
+        self.mode = r.u8()?;
+        self.fdot = r.u64()?;
+        self.ldot = r.u64()?;
+        self.lcdc = r.u8()?;
+        // Derive the LCDC flags without triggering the LCD off transition.
+        self.lcdc7 = false;
+        self.update_lcdc_flags();
+        self.lx = r.u8()?;
+        self.ly = r.u8()?;
+        self.lyc = r.u8()?;
+        self.stat = r.u8()?;
+        self.update_stat_flags();
+        self.scy = r.u8()?;
+        self.scx = r.u8()?;
+        self.wy = r.u8()?;
+        self.wx = r.u8()?;
+        self.wly = r.u16()?;
+        self.wly_flag = r.bool()?;
+        self.bgp = r.u8()?;
+        self.obp0 = r.u8()?;
+        self.obp1 = r.u8()?;
+        self.i_mask = r.u8()?;
+        self.hblank = r.bool()?;
+        self.last_ly_eq_lyc = r.bool()?;
+        r.bytes_into(&mut self.fb_back)?;
+        r.bytes_into(&mut self.fb_front)?;
+        r.bytes_into(&mut self.shades_back)?;
+        r.bytes_into(&mut self.shades)?;
+        r.bytes_into(&mut self.priorities)?;
+        self.active_renderer = Renderer::from_index(r.u8()?);
+        self.next_renderer = Renderer::from_index(r.u8()?);
+        self.fifo.load_state(r)?;
+        Ok(())
+    }
+}
+
+/// Layer a DMG pixel belongs to, to pick its colors.
+#[derive(Debug, Clone, Copy)]
+enum Layer {
+    Bg,
+    Obj0,
+    Obj1,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Sprite {
     y: u8,
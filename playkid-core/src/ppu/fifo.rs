@@ -0,0 +1,460 @@
+use super::{Layer, Ppu, Sprite, SpriteTileKey};
+use crate::constants;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind};
+
+/// Dot of the line at which mode 3 starts, after the OAM scan.
+const MODE3_START: u64 = 80;
+/// Dots in a line.
+const LINE_DOTS: u64 = 456;
+/// Dots of the first tile fetch of a line, which is thrown away.
+const DUMMY_FETCH_DOTS: i8 = 6;
+/// Dots of a tile fetch: tile number, low byte, and high byte, 2 dots each.
+/// After that, the fetcher waits for the BG FIFO to be empty to push.
+const FETCH_DOTS: i8 = 6;
+/// Dot of the tile fetch from which a sprite fetch can take over.
+const SPRITE_FETCH_READY: i8 = 4;
+/// Dots of a sprite fetch.
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+/// A pixel of the BG FIFO, from the background or the window.
+#[derive(Debug, Clone, Copy)]
+struct BgPixel {
+    /// Color ID, 0-3.
+    color: u8,
+    /// BG attributes of the tile (CGB only).
+    attr: u8,
+}
+
+/// A pixel of the OBJ FIFO.
+#[derive(Debug, Clone, Copy)]
+struct ObjPixel {
+    /// Color ID, 0-3. 0 is transparent.
+    color: u8,
+    /// OAM attributes of the sprite.
+    attr: u8,
+    /// Index of the sprite in OAM, for the CGB priority.
+    oam_index: u8,
+}
+
+/// # Pixel FIFO
+/// State of the pixel FIFO renderer within a line. The fetcher reads the BG
+/// and window tiles 8 pixels at a time, and pushes them to the BG FIFO when
+/// it is empty. Every dot, a pixel is shifted out of the BG FIFO, mixed with
+/// the one of the OBJ FIFO, and drawn. Sprites pause the shifting while
+/// they are fetched.
+pub(super) struct Fifo {
+    bg: VecDeque<BgPixel>,
+    obj: VecDeque<ObjPixel>,
+    /// Dots into the current tile fetch. Negative during the first fetch.
+    fetch_dot: i8,
+    /// Tile column of the next fetch, relative to the left of the BG map
+    /// (plus SCX) or of the window.
+    fetch_x: u8,
+    /// Tile number of the current fetch.
+    tile_id: u8,
+    /// BG attributes of the current fetch (CGB only).
+    tile_attr: u8,
+    /// Decoded tile row of the current fetch.
+    tile_row: [u8; 8],
+    /// Pixels to throw away before drawing: SCX % 8 at the start of the
+    /// line, or the part of the window left of the screen.
+    discard: u8,
+    /// The fetcher reads window tiles.
+    window: bool,
+    /// The window was drawn in this line.
+    window_drawn: bool,
+    /// Sprites on this line, in X order.
+    sprites: Vec<Sprite>,
+    /// Next sprite to fetch.
+    next_sprite: usize,
+    /// Dots into the current sprite fetch.
+    sprite_dots: u8,
+    /// Decoded sprite rows of this line.
+    sprite_cache: HashMap<SpriteTileKey, [u8; 8]>,
+}
+
+impl Fifo {
+    pub(super) fn new() -> Self {
+        Self {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+            fetch_dot: 0,
+            fetch_x: 0,
+            tile_id: 0,
+            tile_attr: 0,
+            tile_row: [0; 8],
+            discard: 0,
+            window: false,
+            window_drawn: false,
+            sprites: Vec::new(),
+            next_sprite: 0,
+            sprite_dots: 0,
+            sprite_cache: HashMap::new(),
+        }
+    }
+}
+
+/// Reads a length stored in a save state, which must not exceed `max`.
+fn read_len(r: &mut StateReader<'_>, max: usize) -> std::io::Result<usize> {
+    let len = r.usize()?;
+    if len > max {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Save state FIFO length out of range: {} > {}", len, max),
+        ));
+    }
+    Ok(len)
+}
+
+impl SaveState for Fifo {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.bg.len());
+        for p in &self.bg {
+            w.u8(p.color);
+            w.u8(p.attr);
+        }
+        w.usize(self.obj.len());
+        for p in &self.obj {
+            w.u8(p.color);
+            w.u8(p.attr);
+            w.u8(p.oam_index);
+        }
+        w.u8(self.fetch_dot as u8);
+        w.u8(self.fetch_x);
+        w.u8(self.tile_id);
+        w.u8(self.tile_attr);
+        w.bytes(&self.tile_row);
+        w.u8(self.discard);
+        w.bool(self.window);
+        w.bool(self.window_drawn);
+        w.usize(self.sprites.len());
+        for s in &self.sprites {
+            w.u8(s.y);
+            w.u8(s.x);
+            w.u8(s.tile_id);
+            w.u8(s.attributes);
+            w.u8(s.oam_index);
+        }
+        w.usize(self.next_sprite);
+        w.u8(self.sprite_dots);
+        // The rows were decoded from VRAM as it was when first fetched, so
+        // they are kept too. Sorted, so that equal states give equal bytes.
+        let mut rows: Vec<_> = self.sprite_cache.iter().collect();
+        rows.sort_by_key(|(key, _)| **key);
+        w.usize(rows.len());
+        for (&(tile_id, line, hflip, vflip, bank), row) in rows {
+            w.u8(tile_id);
+            w.u8(line);
+            w.bool(hflip);
+            w.bool(vflip);
+            w.u8(bank);
+            w.bytes(row);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        self.bg.clear();
+        for _ in 0..read_len(r, 16)? {
+            let color = r.u8()?;
+            let attr = r.u8()?;
+            self.bg.push_back(BgPixel { color, attr });
+        }
+        self.obj.clear();
+        for _ in 0..read_len(r, 8)? {
+            let color = r.u8()?;
+            let attr = r.u8()?;
+            let oam_index = r.u8()?;
+            self.obj.push_back(ObjPixel {
+                color,
+                attr,
+                oam_index,
+            });
+        }
+        self.fetch_dot = r.u8()? as i8;
+        self.fetch_x = r.u8()?;
+        self.tile_id = r.u8()?;
+        self.tile_attr = r.u8()?;
+        r.bytes_into(&mut self.tile_row)?;
+        self.discard = r.u8()?;
+        self.window = r.bool()?;
+        self.window_drawn = r.bool()?;
+        self.sprites.clear();
+        for _ in 0..read_len(r, 10)? {
+            self.sprites.push(Sprite {
+                y: r.u8()?,
+                x: r.u8()?,
+                tile_id: r.u8()?,
+                attributes: r.u8()?,
+                oam_index: r.u8()?,
+            });
+        }
+        self.next_sprite = r.usize()?.min(self.sprites.len());
+        self.sprite_dots = r.u8()?;
+        self.sprite_cache.clear();
+        for _ in 0..read_len(r, 10)? {
+            let key = (r.u8()?, r.u8()?, r.bool()?, r.bool()?, r.u8()?);
+            let mut row = [0; 8];
+            r.bytes_into(&mut row)?;
+            self.sprite_cache.insert(key, row);
+        }
+        Ok(())
+    }
+}
+
+impl Ppu {
+    /// Runs a single dot with the pixel FIFO renderer. Mode 2 takes 80 dots,
+    /// mode 3 at least 172, and HBlank the rest of the 456 dots of the line.
+    pub(super) fn dot(&mut self) {
+        if self.ly < 144 {
+            if self.fdot == 0 {
+                self.update_mode(2);
+            } else if self.fdot == MODE3_START {
+                self.update_mode(3);
+                self.fifo_start_line();
+            }
+            if self.mode == 3 && self.fdot >= MODE3_START && self.fifo_dot() {
+                self.update_mode(0);
+            }
+        } else if self.mode != 1 {
+            self.update_mode(1);
+        }
+
+        self.fdot += 1;
+        if self.fdot >= LINE_DOTS {
+            self.fdot = 0;
+            self.ly = (self.ly + 1) % 154;
+            self.check_interrupt_lyc();
+        }
+    }
+
+    /// Starts mode 3: picks the sprites of the line and resets the FIFOs and
+    /// the fetcher. The fine scroll is latched here.
+    pub(super) fn fifo_start_line(&mut self) {
+        let mut sprites = self.get_sprites_on_scanline(self.lcdc2);
+        // Fetched from left to right. The sort is stable, so OAM order
+        // breaks ties.
+        sprites.sort_by_key(|s| s.x);
+
+        let fifo = &mut self.fifo;
+        fifo.bg.clear();
+        fifo.obj.clear();
+        fifo.fetch_dot = -DUMMY_FETCH_DOTS;
+        fifo.fetch_x = 0;
+        fifo.discard = self.scx & 0x07;
+        fifo.window = false;
+        fifo.window_drawn = false;
+        fifo.sprites = sprites;
+        fifo.next_sprite = 0;
+        fifo.sprite_dots = 0;
+        fifo.sprite_cache.clear();
+        self.lx = 0;
+
+        // Clear priorities for this scanline before rendering.
+        let line_start = self.ly as usize * constants::DISPLAY_WIDTH;
+        self.priorities[line_start..line_start + constants::DISPLAY_WIDTH].fill(0);
+    }
+
+    /// Ends mode 3. The window line counter only advances on lines that
+    /// drew the window.
+    pub(super) fn fifo_end_line(&mut self) {
+        if self.fifo.window_drawn {
+            self.wly += 1;
+        }
+    }
+
+    /// Runs a dot of mode 3. Returns true on the dot after the last of the
+    /// 160 pixels of the line.
+    fn fifo_dot(&mut self) -> bool {
+        if self.lx as usize >= constants::DISPLAY_WIDTH {
+            return true;
+        }
+
+        // The window starts at WX - 7, and replaces the BG for the rest of
+        // the line. The fetcher starts over with the first window tile.
+        if !self.fifo.window && self.lcdc5 && self.wly_flag && self.lx as u16 + 7 >= self.wx as u16
+        {
+            let fifo = &mut self.fifo;
+            fifo.window = true;
+            fifo.window_drawn = true;
+            fifo.bg.clear();
+            fifo.fetch_x = 0;
+            // A window at the start of the line waits for the first fetch.
+            if fifo.fetch_dot < 0 {
+                fifo.fetch_dot -= FETCH_DOTS;
+            } else {
+                fifo.fetch_dot = 0;
+            }
+            if self.lx == 0 {
+                // The part of the window left of the screen is shifted out.
+                fifo.discard = 7u8.saturating_sub(self.wx);
+            }
+        }
+
+        // A sprite at this position pauses the shifter. It waits for the
+        // fetcher to be far enough into its current tile, and then takes 6
+        // dots to fetch.
+        if self.fifo.discard == 0
+            && self.lcdc1
+            && let Some(sprite) = self.fifo.sprites.get(self.fifo.next_sprite).copied()
+            && sprite.x <= self.lx + 8
+        {
+            if self.fifo.fetch_dot < SPRITE_FETCH_READY || self.fifo.bg.is_empty() {
+                self.fetcher_dot();
+            } else {
+                self.fifo.sprite_dots += 1;
+                if self.fifo.sprite_dots == SPRITE_FETCH_DOTS {
+                    self.fetch_sprite(sprite);
+                    self.fifo.next_sprite += 1;
+                    self.fifo.sprite_dots = 0;
+                }
+            }
+            return false;
+        }
+
+        self.fetcher_dot();
+        let Some(bg) = self.fifo.bg.pop_front() else {
+            return false;
+        };
+        let obj = self.fifo.obj.pop_front();
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return false;
+        }
+        self.draw_pixel(bg, obj);
+        self.lx += 1;
+        false
+    }
+
+    /// Runs a dot of the BG fetcher. The registers are read when each step
+    /// happens, so writes in the middle of the line take effect from the
+    /// next tile on.
+    fn fetcher_dot(&mut self) {
+        match self.fifo.fetch_dot {
+            // Tile number.
+            1 => self.fetch_tile_id(),
+            // Tile data, low and high bytes. Both are read with the high
+            // byte.
+            5 => {
+                let line = if self.fifo.window {
+                    self.wly & 0x07
+                } else {
+                    self.ly.wrapping_add(self.scy) as u16 & 0x07
+                };
+                let use_unsigned = self.lcdc & 0x10 != 0;
+                self.fifo.tile_row = self.get_bgwin_tile_data(
+                    self.fifo.tile_id,
+                    line,
+                    use_unsigned,
+                    self.fifo.tile_attr,
+                );
+            }
+            // Push, once the BG FIFO is empty.
+            d if d >= FETCH_DOTS => {
+                if self.fifo.bg.is_empty() {
+                    let attr = self.fifo.tile_attr;
+                    for color in self.fifo.tile_row {
+                        self.fifo.bg.push_back(BgPixel { color, attr });
+                    }
+                    self.fifo.fetch_x = self.fifo.fetch_x.wrapping_add(1);
+                    self.fifo.fetch_dot = 0;
+                }
+                return;
+            }
+            _ => {}
+        }
+        self.fifo.fetch_dot += 1;
+    }
+
+    /// Reads the tile number, and the attributes in CGB mode, of the next
+    /// BG or window tile.
+    fn fetch_tile_id(&mut self) {
+        let (map, x, y) = if self.fifo.window {
+            (self.lcdc6, self.fifo.fetch_x as u16, self.wly)
+        } else {
+            (
+                self.lcdc3,
+                ((self.scx >> 3) as u16 + self.fifo.fetch_x as u16),
+                self.ly.wrapping_add(self.scy) as u16,
+            )
+        };
+        let index = (map - 0x8000 + ((y / 8) & 0x1F) * 32 + (x & 0x1F)) as usize;
+        self.fifo.tile_id = self.vram[index];
+        self.fifo.tile_attr = if self.cgb_mode { self.vram1[index] } else { 0 };
+    }
+
+    /// Fetches the row of the sprite on this line, and merges it into the
+    /// OBJ FIFO. Pixels already left of the current position are dropped.
+    fn fetch_sprite(&mut self, sprite: Sprite) {
+        let sprite_size = self.lcdc2;
+        let tile_line = self.ly.wrapping_add(16).wrapping_sub(sprite.y) % sprite_size;
+        let mut cache = std::mem::take(&mut self.fifo.sprite_cache);
+        let pixels = self.get_sprite_tile_pixels(
+            sprite.tile_id,
+            tile_line,
+            sprite.attributes,
+            sprite_size == 16,
+            &mut cache,
+        );
+        self.fifo.sprite_cache = cache;
+
+        let skip = (self.lx + 8 - sprite.x) as usize;
+        for (slot, &color) in pixels.iter().skip(skip).enumerate() {
+            let pixel = ObjPixel {
+                color,
+                attr: sprite.attributes,
+                oam_index: sprite.oam_index,
+            };
+            match self.fifo.obj.get_mut(slot) {
+                // On the DMG, the sprite fetched first wins, which is the
+                // leftmost one. On the CGB, the one first in OAM wins.
+                Some(old) => {
+                    if old.color == 0
+                        || (self.cgb_mode && color != 0 && pixel.oam_index < old.oam_index)
+                    {
+                        *old = pixel;
+                    }
+                }
+                None => self.fifo.obj.push_back(pixel),
+            }
+        }
+    }
+
+    /// Mixes a BG pixel with a sprite pixel, and draws the result at the
+    /// current position.
+    fn draw_pixel(&mut self, bg: BgPixel, obj: Option<ObjPixel>) {
+        let x = self.lx as usize;
+        // On the DMG, LCDC0 clear blanks the BG and the window.
+        let bg_color = if self.lcdc0 || self.cgb_mode {
+            bg.color
+        } else {
+            0
+        };
+        let bg_priority = bg_color | (bg.attr & 0x80);
+        self.priorities[self.ly as usize * constants::DISPLAY_WIDTH + x] = bg_priority;
+
+        let obj = obj
+            .filter(|o| o.color != 0 && self.lcdc1 && self.obj_has_priority(o.attr, bg_priority));
+        match obj {
+            Some(o) if self.cgb_mode => {
+                self.color_cgb(x, self.ly, true, o.attr & 0x07, o.color);
+            }
+            Some(o) => {
+                let (palette, layer) = if o.attr & 0x10 != 0 {
+                    (self.obp1, Layer::Obj1)
+                } else {
+                    (self.obp0, Layer::Obj0)
+                };
+                self.color(x, self.ly, (palette >> (o.color * 2)) & 0x03, layer);
+            }
+            None if self.cgb_mode => {
+                self.color_cgb(x, self.ly, false, bg.attr & 0x07, bg.color);
+            }
+            None if self.lcdc0 => {
+                self.color(x, self.ly, (self.bgp >> (bg.color * 2)) & 0x03, Layer::Bg);
+            }
+            None => self.color(x, self.ly, 0, Layer::Bg),
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use crate::serial::{Link, Reply};
+use std::sync::{Arc, Mutex};
+
+/// First magic byte of a packet.
+const MAGIC1: u8 = 0x88;
+/// Second magic byte of a packet.
+const MAGIC2: u8 = 0x33;
+/// Clears the buffer and the status.
+const CMD_INIT: u8 = 0x01;
+/// Prints the buffer.
+const CMD_PRINT: u8 = 0x02;
+/// Appends image data to the buffer.
+const CMD_DATA: u8 = 0x04;
+/// Only asks for the status.
+const CMD_STATUS: u8 = 0x0F;
+/// Answer to the first byte after the checksum: the printer is there.
+const ALIVE: u8 = 0x81;
+
+/// Status bit: the checksum of the last packet did not match.
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+/// Status bit: printing.
+const STATUS_PRINTING: u8 = 0x02;
+/// Status bit: the buffer holds an image that was printed, or is full.
+const STATUS_FULL: u8 = 0x04;
+/// Status bit: the buffer holds data that was not printed yet.
+const STATUS_UNPROCESSED: u8 = 0x08;
+
+/// Size of the image buffer: 9 DATA packets of 2 tile rows each.
+const BUFFER_SIZE: usize = 9 * 0x280;
+/// Width of the paper, in pixels.
+pub const PRINTER_WIDTH: usize = 160;
+/// Number of STATUS packets that report the printer as busy after a PRINT.
+const PRINTING_STATUS_COUNT: u8 = 4;
+
+/// # Printed strip
+/// An image printed by the Game Boy Printer, with one shade (0: white to
+/// 3: black) per pixel, in row-major order, [PRINTER_WIDTH] pixels wide.
+#[derive(Debug, Clone)]
+pub struct PrintedStrip {
+    pub height: usize,
+    pub shades: Vec<u8>,
+    /// Paper feeds before the image.
+    pub margin_before: u8,
+    /// Paper feeds after the image.
+    pub margin_after: u8,
+}
+
+impl PrintedStrip {
+    /// Converts the image to RGBA bytes, with the given RGB color per shade.
+    pub fn to_rgba(&self, colors: &[[u8; 3]; 4]) -> Vec<u8> {
+        self.shades
+            .iter()
+            .flat_map(|s| {
+                let [r, g, b] = colors[*s as usize & 0x03];
+                [r, g, b, 0xFF]
+            })
+            .collect()
+    }
+}
+
+/// Part of the packet being received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+struct Inner {
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    /// Checksum received in the packet.
+    received_checksum: u16,
+    status: u8,
+    /// STATUS packets left while printing.
+    printing: u8,
+    /// Decompressed image data, in tiles.
+    buffer: Vec<u8>,
+    /// Strips printed and not yet taken.
+    printed: Vec<PrintedStrip>,
+    /// Answer to the last byte received.
+    answer: u8,
+}
+
+/// # Game Boy Printer
+/// Thermal printer that plugs into the serial port, in place of a link
+/// cable. The game clocks the transfers, and sends packets of the form
+/// `0x88 0x33 command compression length(2) data checksum(2) 0x00 0x00`.
+/// The printer answers the last two bytes with 0x81 and its status.
+///
+/// It is cheap to clone, and all clones share the same printer, so a
+/// frontend can keep one to collect the printed strips.
+#[derive(Clone)]
+pub struct Printer {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                state: State::Magic1,
+                command: 0,
+                compressed: false,
+                length: 0,
+                data: Vec::new(),
+                checksum: 0,
+                received_checksum: 0,
+                status: 0,
+                printing: 0,
+                buffer: Vec::new(),
+                printed: Vec::new(),
+                answer: 0x00,
+            })),
+        }
+    }
+
+    /// Takes the strips printed since the last call.
+    pub fn take_printed(&self) -> Vec<PrintedStrip> {
+        std::mem::take(&mut self.inner.lock().unwrap().printed)
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Link for Printer {
+    fn send(&mut self, data: u8) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.answer = inner.receive(data);
+    }
+
+    fn receive(&mut self) -> Reply {
+        Reply::Data(self.inner.lock().unwrap().answer)
+    }
+
+    fn poll(&mut self) -> Option<u8> {
+        // The printer never clocks a transfer.
+        None
+    }
+
+    fn reply(&mut self, _data: u8) {}
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+impl Inner {
+    /// Receives a byte of a packet, and returns the answer.
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut answer = 0x00;
+        self.state = match self.state {
+            State::Magic1 if byte == MAGIC1 => State::Magic2,
+            State::Magic1 => State::Magic1,
+            State::Magic2 if byte == MAGIC2 => State::Command,
+            State::Magic2 => State::Magic1,
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                self.data.clear();
+                State::Compression
+            }
+            State::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthLow
+            }
+            State::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.length == 0 {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() >= self.length as usize {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                State::Alive
+            }
+            State::Alive => {
+                answer = ALIVE;
+                State::Status
+            }
+            State::Status => {
+                self.execute();
+                answer = self.status;
+                State::Magic1
+            }
+        };
+        answer
+    }
+
+    /// Runs the command of the packet that was just received.
+    fn execute(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+        match self.command {
+            CMD_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.printing = 0;
+            }
+            CMD_DATA => {
+                let data = std::mem::take(&mut self.data);
+                if self.compressed {
+                    self.decompress(&data);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_FULL;
+                }
+            }
+            CMD_PRINT if self.data.len() >= 4 => {
+                self.print();
+                self.buffer.clear();
+                self.printing = PRINTING_STATUS_COUNT;
+                self.status = (self.status & !STATUS_UNPROCESSED) | STATUS_PRINTING | STATUS_FULL;
+            }
+            CMD_STATUS if self.printing > 0 => {
+                self.printing -= 1;
+                if self.printing == 0 {
+                    self.status &= !STATUS_PRINTING;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Decompresses RLE image data into the buffer. A control byte with bit
+    /// 7 set repeats the next byte `(control & 0x7F) + 2` times. Otherwise,
+    /// `control + 1` literal bytes follow.
+    fn decompress(&mut self, data: &[u8]) {
+        let mut i = 0;
+        while i < data.len() {
+            let control = data[i];
+            i += 1;
+            if control & 0x80 != 0 {
+                let Some(&value) = data.get(i) else {
+                    break;
+                };
+                let count = (control & 0x7F) as usize + 2;
+                self.buffer.extend(std::iter::repeat_n(value, count));
+                i += 1;
+            } else {
+                let end = (i + control as usize + 1).min(data.len());
+                self.buffer.extend_from_slice(&data[i..end]);
+                i = end;
+            }
+        }
+    }
+
+    /// Prints the buffer, with the palette and margins of the PRINT packet.
+    /// Incomplete tile rows are dropped.
+    /// The buffer holds rows of 20 tiles, in the 2bpp tile format.
+    fn print(&mut self) {
+        let margins = self.data[1];
+        // A zero palette means the default one.
+        let palette = match self.data[2] {
+            0 => 0xE4,
+            p => p,
+        };
+        let tile_rows = self.buffer.len() / (20 * 16);
+        let height = tile_rows * 8;
+        let mut shades = vec![0u8; PRINTER_WIDTH * height];
+        for (tile, bytes) in self.buffer[..tile_rows * 20 * 16]
+            .chunks_exact(16)
+            .enumerate()
+        {
+            let tx = (tile % 20) * 8;
+            let ty = (tile / 20) * 8;
+            for (row, pair) in bytes.chunks_exact(2).enumerate() {
+                for bit in 0..8 {
+                    let lo = (pair[0] >> (7 - bit)) & 1;
+                    let hi = (pair[1] >> (7 - bit)) & 1;
+                    let index = (hi << 1) | lo;
+                    let shade = (palette >> (index * 2)) & 0x03;
+                    shades[(ty + row) * PRINTER_WIDTH + tx + bit] = shade;
+                }
+            }
+        }
+        if height > 0 {
+            self.printed.push(PrintedStrip {
+                height,
+                shades,
+                margin_before: margins >> 4,
+                margin_after: margins & 0x0F,
+            });
+        }
+    }
+}
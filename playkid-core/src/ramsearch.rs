@@ -0,0 +1,197 @@
+use crate::memory::Memory;
+
+/// Size of the whole address space, which the snapshots cover.
+const ADDRESS_SPACE: usize = 0x10000;
+
+/// # Value size
+/// How the bytes of the RAM are read as values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSize {
+    /// A single byte.
+    Byte,
+    /// Two bytes, little-endian, like the CPU reads them.
+    Word,
+}
+
+impl ValueSize {
+    /// Number of bytes of a value.
+    pub fn bytes(self) -> usize {
+        match self {
+            ValueSize::Byte => 1,
+            ValueSize::Word => 2,
+        }
+    }
+
+    /// Reinterprets the difference of two values as a signed number.
+    fn signed(self, value: u16) -> i32 {
+        match self {
+            ValueSize::Byte => value as u8 as i8 as i32,
+            ValueSize::Word => value as i16 as i32,
+        }
+    }
+}
+
+/// # Operand
+/// What the current values are compared against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// The value in the previous snapshot.
+    Previous,
+    /// A constant value.
+    Value(u16),
+}
+
+/// # Search filter
+/// The comparisons that narrow the candidates down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Equal(Operand),
+    NotEqual(Operand),
+    Greater(Operand),
+    Less(Operand),
+    /// The value is not the one in the previous snapshot.
+    Changed,
+    /// The value is the one in the previous snapshot.
+    Unchanged,
+    /// The value is the previous one plus the given amount, which can be
+    /// negative. Wraps around like the game's own arithmetic.
+    ChangedBy(i32),
+}
+
+impl Filter {
+    /// Whether the current value passes the filter.
+    fn matches(self, size: ValueSize, current: u16, previous: u16) -> bool {
+        let operand = |o: Operand| match o {
+            Operand::Previous => previous,
+            Operand::Value(v) => v,
+        };
+        match self {
+            Filter::Equal(o) => current == operand(o),
+            Filter::NotEqual(o) => current != operand(o),
+            Filter::Greater(o) => current > operand(o),
+            Filter::Less(o) => current < operand(o),
+            Filter::Changed => current != previous,
+            Filter::Unchanged => current == previous,
+            Filter::ChangedBy(n) => size.signed(current.wrapping_sub(previous)) == n,
+        }
+    }
+}
+
+/// # RAM search
+/// Finds the addresses of game variables, like the lives or HP counters,
+/// by taking snapshots of the WRAM, the HRAM, and the cartridge RAM, and
+/// keeping the candidates whose values pass the successive filters.
+pub struct RamSearch {
+    /// How the values are read.
+    size: ValueSize,
+    /// Addresses that passed all the filters so far.
+    candidates: Vec<u16>,
+    /// The memory at the last snapshot, indexed by address.
+    snapshot: Vec<u8>,
+}
+
+impl RamSearch {
+    pub fn new() -> Self {
+        Self {
+            size: ValueSize::Byte,
+            candidates: Vec::new(),
+            snapshot: vec![0; ADDRESS_SPACE],
+        }
+    }
+
+    /// Starts a new search, with every searchable address as a candidate
+    /// and a fresh snapshot.
+    pub fn start(&mut self, memory: &Memory, size: ValueSize) {
+        self.size = size;
+        let ranges = Self::ranges(memory);
+        // Words must fit in the range, so that both bytes are RAM.
+        self.candidates = ranges
+            .into_iter()
+            .flat_map(|(start, end)| start..=end + 1 - size.bytes() as u16)
+            .collect();
+        self.take_snapshot(memory);
+    }
+
+    /// Clears the candidates.
+    pub fn clear(&mut self) {
+        self.candidates.clear();
+    }
+
+    /// Keeps the candidates that pass the filter, and takes a new snapshot.
+    pub fn filter(&mut self, memory: &Memory, filter: Filter) {
+        let size = self.size;
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&address| {
+            let current = Self::read(memory, size, address);
+            let previous = Self::stored(snapshot, size, address);
+            filter.matches(size, current, previous)
+        });
+        self.take_snapshot(memory);
+    }
+
+    /// Takes a new snapshot of the candidates, without filtering them.
+    pub fn take_snapshot(&mut self, memory: &Memory) {
+        for &address in &self.candidates {
+            for i in 0..self.size.bytes() as u16 {
+                let a = address + i;
+                self.snapshot[a as usize] = memory.peek8(a);
+            }
+        }
+    }
+
+    pub fn size(&self) -> ValueSize {
+        self.size
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// Gets the current value at the given address.
+    pub fn value(&self, memory: &Memory, address: u16) -> u16 {
+        Self::read(memory, self.size, address)
+    }
+
+    /// Gets the value at the given address in the last snapshot.
+    pub fn previous(&self, address: u16) -> u16 {
+        Self::stored(&self.snapshot, self.size, address)
+    }
+
+    /// Reads a value of the given size from a snapshot.
+    fn stored(snapshot: &[u8], size: ValueSize, address: u16) -> u16 {
+        let a = address as usize;
+        match size {
+            ValueSize::Byte => snapshot[a] as u16,
+            ValueSize::Word => u16::from_le_bytes([snapshot[a], snapshot[a + 1]]),
+        }
+    }
+
+    /// Reads a value of the given size.
+    fn read(memory: &Memory, size: ValueSize, address: u16) -> u16 {
+        match size {
+            ValueSize::Byte => memory.peek8(address) as u16,
+            ValueSize::Word => memory.peek16(address),
+        }
+    }
+
+    /// Gets the searchable address ranges, inclusive: the cartridge RAM, if
+    /// the cartridge has some, the WRAM, and the HRAM. Only the banks that
+    /// are mapped in are seen, and the cartridge RAM reads 0xFF while the
+    /// game keeps it disabled.
+    fn ranges(memory: &Memory) -> Vec<(u16, u16)> {
+        let mut ranges = Vec::with_capacity(3);
+        let sram = memory.cart.header().ram_bytes().min(0x2000);
+        if sram > 0 {
+            ranges.push((0xA000, 0xA000 + sram as u16 - 1));
+        }
+        ranges.push((0xC000, 0xDFFF));
+        ranges.push((0xFF80, 0xFFFE));
+        ranges
+    }
+}
+
+impl Default for RamSearch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,3 +1,6 @@
+use crate::cartridge::Cartridge;
+use crate::model::Model;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 /// # Registers
 /// We have 7 1-bit registers (`a`, `b`, `c`, `d`, `e`, `h`, `l`) which can be accessed individually,
@@ -56,16 +59,53 @@ impl Registers {
         }
     }
 
-    /// Reset the state of the registers.
-    pub fn reset(&mut self) {
-        self.a = 0x01;
-        self.b = 0x00;
-        self.c = 0x13;
-        self.d = 0x00;
-        self.e = 0xD8;
-        self.f = 0xB0;
-        self.h = 0x01;
-        self.l = 0x4D;
+    /// Sets all the registers to zero, like at power-on, before the boot ROM
+    /// runs.
+    pub fn power_on(&mut self) {
+        *self = Registers {
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            f: 0,
+            h: 0,
+            l: 0,
+            sp: 0,
+            pc: 0,
+        };
+    }
+
+    /// Sets the registers to the values the boot ROM of the given model
+    /// leaves them at. Some of them depend on the cartridge header.
+    pub fn post_boot(&mut self, model: Model, cart: &Cartridge) {
+        // The DMG and MGB boot ROMs leave H and C set, unless the header
+        // checksum is zero.
+        let hc = if cart.read(0x014D) != 0 { 0x30 } else { 0x00 };
+        let (af, bc, de, hl) = match model {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x0180 | hc, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF80 | hc, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Sgb2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+            Model::Cgb if cart.is_cgb() => (0x1180, 0x0000, 0xFF56, 0x000D),
+            Model::Cgb => {
+                // DMG mode: B holds the title checksum used to pick the
+                // colorization, for Nintendo games.
+                let b = if cart.is_nintendo_licensee() {
+                    cart.title_bytes()
+                        .iter()
+                        .fold(0u8, |acc, b| acc.wrapping_add(*b))
+                } else {
+                    0x00
+                };
+                (0x1180, (b as u16) << 8, 0x0008, 0x007C)
+            }
+        };
+        self.set_af(af);
+        self.set_bc(bc);
+        self.set_de(de);
+        self.set_hl(hl);
         self.sp = 0xFFFE;
         self.pc = 0x100;
     }
@@ -153,3 +193,35 @@ impl Registers {
         self.get_flag(Flag::C)
     }
 }
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Registers {
+    fn save_state(&self, w: &mut StateWriter) {
+        for r in [
+            self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l,
+        ] {
+            w.u8(r);
+        }
+        w.u16(self.sp);
+        w.u16(self.pc);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        self.a = r.u8()?;
+        self.b = r.u8()?;
+        self.c = r.u8()?;
+        self.d = r.u8()?;
+        self.e = r.u8()?;
+        self.f = r.u8()?;
+        self.h = r.u8()?;
+        self.l = r.u8()?;
+        self.sp = r.u16()?;
+        self.pc = r.u16()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+
+/// Default number of frames between two rewind snapshots.
+pub const REWIND_INTERVAL: u32 = 4;
+/// Default maximum memory used by the rewind buffer, in bytes.
+pub const REWIND_MAX_BYTES: usize = 128 * 1024 * 1024;
+
+/// # Rewind buffer
+/// Ring buffer of machine snapshots, taken every few frames. Only the newest
+/// snapshot is kept whole. Every older snapshot is stored as the XOR against
+/// the snapshot that follows it, run-length encoded. Consecutive snapshots
+/// are mostly identical, so deltas are small. Since deltas point forward,
+/// the oldest snapshot can always be dropped when the buffer is full.
+pub struct Rewind {
+    /// Number of frames between snapshots.
+    interval: u32,
+    /// Maximum number of snapshots.
+    capacity: usize,
+    /// Maximum number of bytes used by the deltas.
+    max_bytes: usize,
+    /// Frames since the last snapshot.
+    frames: u32,
+    /// The newest snapshot, whole.
+    newest: Option<Vec<u8>>,
+    /// Encoded deltas, oldest first. The delta at `i` restores snapshot `i`
+    /// from snapshot `i + 1`.
+    deltas: VecDeque<Vec<u8>>,
+    /// Bytes used by the deltas.
+    bytes: usize,
+}
+
+impl Rewind {
+    /// Creates a rewind buffer that covers the given number of seconds,
+    /// taking a snapshot every `interval` frames.
+    pub fn new(seconds: u32, interval: u32) -> Self {
+        let interval = interval.max(1);
+        Self {
+            interval,
+            capacity: (seconds as usize * crate::constants::TARGET_FPS as usize
+                / interval as usize)
+                .max(1),
+            max_bytes: REWIND_MAX_BYTES,
+            frames: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Number of frames between snapshots.
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Number of snapshots in the buffer.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Approximate memory used by the buffer, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes + self.newest.as_ref().map_or(0, |s| s.len())
+    }
+
+    /// Drops all snapshots.
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.bytes = 0;
+        self.frames = 0;
+    }
+
+    /// Counts one emulated frame. Returns `true` if a snapshot is due.
+    pub fn tick(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Adds a snapshot as the newest one, dropping the oldest ones if the
+    /// buffer is full.
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(prev) = self.newest.take() {
+            let delta = encode_delta(&prev, &snapshot);
+            self.bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(snapshot);
+        while self.len() > self.capacity || (self.bytes > self.max_bytes && !self.deltas.is_empty())
+        {
+            if let Some(d) = self.deltas.pop_front() {
+                self.bytes -= d.len();
+            }
+        }
+    }
+
+    /// Removes the newest snapshot and returns it. The oldest snapshot is
+    /// never removed, it is returned again on every call.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        match self.deltas.pop_back() {
+            Some(delta) => {
+                self.bytes -= delta.len();
+                self.newest = Some(decode_delta(&newest, &delta));
+            }
+            None => self.newest = Some(newest.clone()),
+        }
+        self.frames = 0;
+        Some(newest)
+    }
+
+    /// Reconstructs the snapshot at `index`, where 0 is the oldest one,
+    /// without removing anything from the buffer.
+    pub fn get(&self, index: usize) -> Option<Vec<u8>> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut snapshot = self.newest.clone()?;
+        for delta in self.deltas.iter().skip(index).rev() {
+            snapshot = decode_delta(&snapshot, delta);
+        }
+        Some(snapshot)
+    }
+
+    /// Drops all snapshots newer than `index`, so that the snapshot at
+    /// `index` becomes the newest one.
+    pub fn truncate(&mut self, index: usize) {
+        if index + 1 >= self.len() {
+            return;
+        }
+        if let Some(snapshot) = self.get(index) {
+            for delta in self.deltas.drain(index..) {
+                self.bytes -= delta.len();
+            }
+            self.newest = Some(snapshot);
+            self.frames = 0;
+        }
+    }
+}
+
+/// Encodes `old` relative to `new`. The XOR of both is written as a
+/// sequence of (zero run, literal length, literal bytes) records, with
+/// lengths as LEB128 varints. If the lengths differ, `old` is stored whole.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if old.len() != new.len() {
+        out.push(1);
+        out.extend_from_slice(old);
+        return out;
+    }
+    out.push(0);
+    let mut i = 0;
+    while i < old.len() {
+        let start = i;
+        while i < old.len() && old[i] == new[i] {
+            i += 1;
+        }
+        let zeros = i - start;
+        let lit_start = i;
+        // Literal runs end at the first pair of equal bytes, so that single
+        // equal bytes don't split them.
+        while i < old.len() && (old[i] != new[i] || (i + 1 < old.len() && old[i + 1] != new[i + 1]))
+        {
+            i += 1;
+        }
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, i - lit_start);
+        out.extend(
+            old[lit_start..i]
+                .iter()
+                .zip(&new[lit_start..i])
+                .map(|(a, b)| a ^ b),
+        );
+    }
+    out
+}
+
+/// Restores the old snapshot from `new` and a delta created by [encode_delta].
+fn decode_delta(new: &[u8], delta: &[u8]) -> Vec<u8> {
+    if delta.first() == Some(&1) {
+        return delta[1..].to_vec();
+    }
+    let mut out = new.to_vec();
+    let mut pos = 1;
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let len = read_varint(delta, &mut pos);
+        for (o, d) in out[i..i + len].iter_mut().zip(&delta[pos..pos + len]) {
+            *o ^= d;
+        }
+        pos += len;
+        i += len;
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    while let Some(&b) = data.get(*pos) {
+        *pos += 1;
+        v |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a 300-byte snapshot, different for every `n`, with changes
+    /// far apart so that zero runs need multi-byte varints.
+    fn snapshot(n: u8) -> Vec<u8> {
+        let mut s = vec![0x55u8; 300];
+        s[3] = n;
+        s[4] = n.wrapping_mul(3);
+        s[200] = !n;
+        s
+    }
+
+    /// Creates a buffer that holds at most `capacity` snapshots.
+    fn with_capacity(capacity: usize) -> Rewind {
+        Rewind {
+            capacity,
+            ..Rewind::new(1, 1)
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let new = snapshot(1);
+        let mut old = new.clone();
+        // A single changed byte, a pair split by one equal byte, and a
+        // trailing run of equal bytes.
+        old[0] ^= 0xFF;
+        old[10] = 1;
+        old[12] = 2;
+        old[150] = 3;
+        let delta = encode_delta(&old, &new);
+        assert_eq!(decode_delta(&new, &delta), old);
+        // Identical snapshots.
+        assert_eq!(decode_delta(&new, &encode_delta(&new, &new)), new);
+        // Changes up to the last byte.
+        let mut old = new.clone();
+        *old.last_mut().unwrap() = 0;
+        assert_eq!(decode_delta(&new, &encode_delta(&old, &new)), old);
+    }
+
+    #[test]
+    fn delta_different_lengths() {
+        let old = vec![1u8, 2, 3];
+        let new = snapshot(0);
+        let delta = encode_delta(&old, &new);
+        assert_eq!(delta[0], 1);
+        assert_eq!(decode_delta(&new, &delta), old);
+    }
+
+    #[test]
+    fn get_every_index() {
+        let mut rewind = with_capacity(16);
+        for n in 0..8 {
+            rewind.push(snapshot(n));
+        }
+        assert_eq!(rewind.len(), 8);
+        for n in 0..8 {
+            assert_eq!(rewind.get(n as usize), Some(snapshot(n)));
+        }
+        assert_eq!(rewind.get(8), None);
+    }
+
+    #[test]
+    fn truncate_then_push() {
+        let mut rewind = with_capacity(16);
+        for n in 0..6 {
+            rewind.push(snapshot(n));
+        }
+        rewind.truncate(2);
+        assert_eq!(rewind.len(), 3);
+        rewind.push(snapshot(100));
+        assert_eq!(rewind.len(), 4);
+        for n in 0..3 {
+            assert_eq!(rewind.get(n as usize), Some(snapshot(n)));
+        }
+        assert_eq!(rewind.get(3), Some(snapshot(100)));
+    }
+
+    #[test]
+    fn pop_to_oldest() {
+        let mut rewind = with_capacity(16);
+        for n in 0..4 {
+            rewind.push(snapshot(n));
+        }
+        for n in (0..4).rev() {
+            assert_eq!(rewind.pop(), Some(snapshot(n)));
+        }
+        // The oldest snapshot stays.
+        assert_eq!(rewind.len(), 1);
+        assert_eq!(rewind.pop(), Some(snapshot(0)));
+        assert_eq!(rewind.memory_usage(), snapshot(0).len());
+    }
+
+    #[test]
+    fn capacity_eviction() {
+        let mut rewind = with_capacity(3);
+        for n in 0..10 {
+            rewind.push(snapshot(n));
+        }
+        assert_eq!(rewind.len(), 3);
+        for n in 0..3 {
+            assert_eq!(rewind.get(n), Some(snapshot(n as u8 + 7)));
+        }
+        let bytes: usize = rewind.deltas.iter().map(Vec::len).sum();
+        assert_eq!(rewind.bytes, bytes);
+    }
+}
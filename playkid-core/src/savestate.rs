@@ -0,0 +1,238 @@
+use crate::constants;
+use crate::model::Model;
+
+use std::io::{Error, ErrorKind, Result};
+
+/// Magic bytes at the start of every save state.
+pub const STATE_MAGIC: [u8; 4] = *b"PKST";
+/// Current version of the save state format. Bump it whenever the layout of
+/// any component changes.
+pub const STATE_VERSION: u16 = 9;
+/// Number of save state slots.
+pub const STATE_SLOTS: u8 = 9;
+
+/// # Save state
+/// Implemented by every component whose state goes into a save state.
+/// Components write and read their fields in the same order.
+pub trait SaveState {
+    /// Writes the state of the component.
+    fn save_state(&self, w: &mut StateWriter);
+    /// Reads the state of the component, as written by [SaveState::save_state].
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> Result<()>;
+}
+
+/// # State header
+/// The header of a save state. It identifies the ROM the state belongs to,
+/// and contains a thumbnail of the screen at the time of saving.
+///
+/// ## Layout
+/// All values are little-endian.
+/// - magic: `PKST` (4 bytes)
+/// - version (u16)
+/// - ROM title (16 bytes, from 0x134-0x143)
+/// - ROM hash (u64, FNV-1a of the whole ROM)
+/// - hardware model (u8, index in [Model::ALL])
+/// - timestamp (u64, seconds since the UNIX epoch)
+/// - thumbnail ([constants::DISPLAY_WIDTH] x [constants::DISPLAY_HEIGHT] RGBA)
+pub struct StateHeader {
+    pub version: u16,
+    pub title: [u8; 16],
+    pub rom_hash: u64,
+    pub model: Model,
+    pub timestamp: u64,
+    pub thumbnail: Vec<u8>,
+}
+
+impl StateHeader {
+    /// Writes the header.
+    pub fn write(&self, w: &mut StateWriter) {
+        w.bytes(&STATE_MAGIC);
+        w.u16(self.version);
+        w.bytes(&self.title);
+        w.u64(self.rom_hash);
+        w.u8(self.model.id());
+        w.u64(self.timestamp);
+        w.bytes(&self.thumbnail);
+    }
+
+    /// Reads and validates the header at the start of a save state.
+    pub fn read(r: &mut StateReader<'_>) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        r.bytes_into(&mut magic)?;
+        if magic != STATE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a save state"));
+        }
+        let version = r.u16()?;
+        if version != STATE_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Unsupported save state version {} (expected {})",
+                    version, STATE_VERSION
+                ),
+            ));
+        }
+        let mut title = [0u8; 16];
+        r.bytes_into(&mut title)?;
+        let rom_hash = r.u64()?;
+        let model = Model::from_id(r.u8()?)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Unknown hardware model"))?;
+        let timestamp = r.u64()?;
+        let mut thumbnail = vec![0u8; constants::DISPLAY_WIDTH * constants::DISPLAY_HEIGHT * 4];
+        r.bytes_into(&mut thumbnail)?;
+        Ok(Self {
+            version,
+            title,
+            rom_hash,
+            model,
+            timestamp,
+            thumbnail,
+        })
+    }
+}
+
+/// # State writer
+/// Serializes values into a little-endian byte buffer.
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    /// Consumes the writer and returns the bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn i32(&mut self, v: i32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, v: usize) {
+        self.u64(v as u64);
+    }
+
+    pub fn f32(&mut self, v: f32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// Writes a block of bytes, prefixed with its length.
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// # State reader
+/// Deserializes values written by a [StateWriter].
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Takes the next `n` bytes.
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.pos + n > self.data.len() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Save state is truncated",
+            ));
+        }
+        let slice = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn usize(&mut self) -> Result<usize> {
+        Ok(self.u64()? as usize)
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a length-prefixed block of bytes.
+    pub fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    /// Reads a length-prefixed block of bytes into `dst`, whose length
+    /// must match the stored one.
+    pub fn bytes_into(&mut self, dst: &mut [u8]) -> Result<()> {
+        let len = self.u32()? as usize;
+        if len != dst.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Save state block size mismatch: {} != {}", len, dst.len()),
+            ));
+        }
+        dst.copy_from_slice(self.take(len)?);
+        Ok(())
+    }
+}
+
+/// Computes the 64-bit FNV-1a hash of the given bytes.
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
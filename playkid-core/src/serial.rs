@@ -0,0 +1,222 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+/// # Reply
+/// Answer of the other end to a transfer clocked by this Game Boy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    /// The answer has not arrived yet.
+    Pending,
+    /// The byte of the other end.
+    Data(u8),
+    /// Nothing came back. The transfer shifts in 0xFF, like with no cable.
+    Lost,
+}
+
+/// # Link
+/// The other end of the link cable. Frontends implement it with whatever
+/// transport they have at hand.
+pub trait Link: Send {
+    /// Sends the byte of a transfer clocked by this Game Boy (internal
+    /// clock). It must not block: the answer is fetched with
+    /// [Link::receive].
+    fn send(&mut self, data: u8);
+    /// Gets the answer of the other end to the last transfer sent.
+    fn receive(&mut self) -> Reply;
+    /// Gets the byte of a pending transfer clocked by the other end, if any.
+    /// It must be answered with [Link::reply].
+    fn poll(&mut self) -> Option<u8>;
+    /// Answers a transfer clocked by the other end with the byte of this
+    /// Game Boy.
+    fn reply(&mut self, data: u8);
+    /// Is the other end connected?
+    fn is_connected(&self) -> bool;
+}
+
+/// # Serial
+/// The serial port, with the SB and SC registers. With the internal clock,
+/// a bit is shifted out on every falling edge of the divider bit that drives
+/// the serial clock (8192 Hz, or 262144 Hz in CGB fast mode), and the serial
+/// interrupt is requested after the eighth bit. With the external clock,
+/// the transfer waits for the other end of the link cable.
+pub struct Serial {
+    /// SB: serial transfer data.
+    sb: u8,
+    /// SC: serial transfer control.
+    sc: u8,
+    /// Byte shifted in during the current transfer.
+    incoming: u8,
+    /// Bits left in the current transfer with the internal clock.
+    bits_left: u8,
+    /// The transfer with the internal clock waits for the answer of the
+    /// other end. The serial clock is held meanwhile.
+    waiting: bool,
+    /// Runs in CGB mode, with the fast clock bit.
+    cgb_mode: bool,
+    /// Link cable, if connected.
+    link: Option<Box<dyn Link>>,
+    /// Bytes sent through the serial port, not yet consumed.
+    pub out: Vec<u8>,
+    /// Serial interrupt mask for registers IE and IF.
+    pub i_mask: u8,
+}
+
+impl Serial {
+    pub fn new(cgb_mode: bool) -> Self {
+        Self {
+            sb: 0,
+            sc: 0,
+            incoming: 0xFF,
+            bits_left: 0,
+            waiting: false,
+            cgb_mode,
+            link: None,
+            out: Vec::new(),
+            i_mask: 0,
+        }
+    }
+
+    /// Resets the state of the serial port. The link cable stays plugged in.
+    pub fn reset(&mut self) {
+        self.sb = 0;
+        self.sc = 0;
+        self.incoming = 0xFF;
+        self.bits_left = 0;
+        self.waiting = false;
+        self.i_mask = 0;
+    }
+
+    /// Plugs in the link cable, or unplugs it with `None`.
+    pub fn set_link(&mut self, link: Option<Box<dyn Link>>) {
+        self.link = link;
+    }
+
+    /// Unplugs the link cable, and returns it.
+    pub fn take_link(&mut self) -> Option<Box<dyn Link>> {
+        self.link.take()
+    }
+
+    /// Gets the link cable, if plugged in.
+    pub fn link(&self) -> Option<&dyn Link> {
+        self.link.as_deref()
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.sb,
+            // Unused bits read as 1. The fast clock bit only exists on CGB.
+            0xFF02 if self.cgb_mode => self.sc | 0x7C,
+            0xFF02 => self.sc | 0x7E,
+            _ => panic!("Serial does not know address: {:#04X}", address),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.sb = value,
+            0xFF02 => {
+                self.sc = if self.cgb_mode {
+                    value & 0x83
+                } else {
+                    value & 0x81
+                };
+                if self.sc & 0x81 == 0x81 {
+                    self.start_internal();
+                } else {
+                    self.bits_left = 0;
+                    self.waiting = false;
+                }
+            }
+            _ => panic!("Serial does not know address: {:#04X}", address),
+        }
+    }
+
+    /// Starts a transfer with the internal clock. The byte is sent to the
+    /// other end right away, and its answer is shifted in bit by bit once it
+    /// arrives. Without a link cable, 0xFF is shifted in.
+    fn start_internal(&mut self) {
+        self.out.push(self.sb);
+        self.incoming = 0xFF;
+        self.waiting = false;
+        if let Some(link) = self.link.as_mut().filter(|l| l.is_connected()) {
+            link.send(self.sb);
+            self.waiting = true;
+        }
+        self.bits_left = 8;
+    }
+
+    /// Checks whether the answer of the other end has arrived. Returns
+    /// `true` while the transfer must keep waiting.
+    fn wait_reply(&mut self) -> bool {
+        let reply = self.link.as_mut().map_or(Reply::Lost, |l| l.receive());
+        match reply {
+            Reply::Pending => return true,
+            Reply::Data(data) => self.incoming = data,
+            Reply::Lost => self.incoming = 0xFF,
+        }
+        self.waiting = false;
+        false
+    }
+
+    /// Advances the serial port by the given amount of T-cycles. `div` is
+    /// the value of the internal divider before them.
+    pub fn cycle(&mut self, div: u16, t_cycles: u64) {
+        if self.sc & 0x80 == 0 {
+            return;
+        }
+        if self.sc & 0x01 == 0 {
+            self.cycle_external();
+            return;
+        }
+        if self.waiting && self.wait_reply() {
+            return;
+        }
+        // Count the falling edges of the serial clock bit of the divider.
+        let shift = if self.sc & 0x02 != 0 { 4 } else { 9 };
+        let start = div as u64;
+        let edges = ((start + t_cycles) >> shift) - (start >> shift);
+        for _ in 0..edges.min(self.bits_left as u64) {
+            self.sb = (self.sb << 1) | (self.incoming >> 7);
+            self.incoming <<= 1;
+            self.bits_left -= 1;
+        }
+        if self.bits_left == 0 {
+            self.finish();
+        }
+    }
+
+    /// Waits for a transfer clocked by the other end.
+    fn cycle_external(&mut self) {
+        let Some(link) = self.link.as_mut() else {
+            return;
+        };
+        if let Some(data) = link.poll() {
+            link.reply(self.sb);
+            self.sb = data;
+            self.finish();
+        }
+    }
+
+    /// Ends the current transfer, and requests the serial interrupt.
+    fn finish(&mut self) {
+        self.sc &= 0x7F;
+        self.bits_left = 0;
+        self.i_mask |= 0x08;
+    }
+}
+
+impl SaveState for Serial {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.sb);
+        w.u8(self.sc);
+        w.u8(self.incoming);
+        w.u8(self.bits_left);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        self.sb = r.u8()?;
+        self.sc = r.u8()?;
+        self.incoming = r.u8()?;
+        self.bits_left = r.u8()?.min(8);
+        Ok(())
+    }
+}
@@ -0,0 +1,655 @@
+use crate::constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH, SGB_DISPLAY_HEIGHT, SGB_DISPLAY_WIDTH};
+use crate::ppu::Ppu;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+/// Bytes in a command packet.
+const PACKET_SIZE: usize = 16;
+/// Maximum number of packets of a command.
+const MAX_PACKETS: usize = 7;
+/// Width of the attribute map, in cells of 8x8 pixels.
+const ATTR_WIDTH: usize = DISPLAY_WIDTH / 8;
+/// Height of the attribute map, in cells of 8x8 pixels.
+const ATTR_HEIGHT: usize = DISPLAY_HEIGHT / 8;
+/// Size of an attribute file: one 2-bit palette number per cell.
+const ATF_SIZE: usize = ATTR_WIDTH * ATTR_HEIGHT / 4;
+/// Number of attribute files, set with ATTR_TRN.
+const ATF_COUNT: usize = 45;
+/// Number of system palettes, set with PAL_TRN.
+const SYSTEM_PALETTES: usize = 512;
+/// Size of a VRAM transfer.
+const TRANSFER_SIZE: usize = 4096;
+/// Size of the border tiles: 256 tiles in the SNES 4bpp format.
+const BORDER_TILES_SIZE: usize = 256 * 32;
+/// Size of the border map: 32x32 entries of 2 bytes.
+const BORDER_MAP_SIZE: usize = 32 * 32 * 2;
+/// Left edge of the game screen within the border.
+const SCREEN_X: usize = 48;
+/// Top edge of the game screen within the border.
+const SCREEN_Y: usize = 40;
+/// Colors of all palettes until the game sets them, in RGB555.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+/// Sets palettes 0 and 1.
+const PAL01: u8 = 0x00;
+/// Sets palettes 2 and 3.
+const PAL23: u8 = 0x01;
+/// Sets palettes 0 and 3.
+const PAL03: u8 = 0x02;
+/// Sets palettes 1 and 2.
+const PAL12: u8 = 0x03;
+/// Colors rectangles of the attribute map.
+const ATTR_BLK: u8 = 0x04;
+/// Colors rows and columns of the attribute map.
+const ATTR_LIN: u8 = 0x05;
+/// Splits the attribute map in two by a row or column.
+const ATTR_DIV: u8 = 0x06;
+/// Colors individual cells of the attribute map.
+const ATTR_CHR: u8 = 0x07;
+/// Sets the palettes from the system palettes, and maybe an attribute file.
+const PAL_SET: u8 = 0x0A;
+/// Transfers the system palettes.
+const PAL_TRN: u8 = 0x0B;
+/// Enables multiplayer joypads.
+const MLT_REQ: u8 = 0x11;
+/// Transfers half of the border tiles.
+const CHR_TRN: u8 = 0x13;
+/// Transfers the border map and palettes.
+const PCT_TRN: u8 = 0x14;
+/// Transfers the attribute files.
+const ATTR_TRN: u8 = 0x15;
+/// Applies an attribute file.
+const ATTR_SET: u8 = 0x16;
+/// Masks the game screen.
+const MASK_EN: u8 = 0x17;
+
+/// Mask: the game screen is shown.
+const MASK_OFF: u8 = 0;
+/// Mask: the game screen freezes on the current frame.
+const MASK_FREEZE: u8 = 1;
+/// Mask: the game screen is black.
+const MASK_BLACK: u8 = 2;
+/// Mask: the game screen has color 0.
+const MASK_COLOR0: u8 = 3;
+
+/// # Super Game Boy
+/// The SNES side of the Super Game Boy. Games talk to it with command
+/// packets, sent bit by bit through writes to P1: a pulse with P14 low is a
+/// 0, a pulse with P15 low is a 1, and both low resets the transfer. Every
+/// packet has 16 bytes, plus a stop bit. Bulk data (palettes, border, and
+/// attribute files) is sent by displaying it, and is read from the BG tiles
+/// on screen in the next frame.
+///
+/// It colors the four shades of the game screen with four palettes,
+/// picked per 8x8 cell by the attribute map, and draws the game screen
+/// in the middle of a 256x224 border.
+pub struct Sgb {
+    /// Bits of the command being received.
+    command: [u8; PACKET_SIZE * MAX_PACKETS],
+    /// Number of bits received.
+    bit_index: usize,
+    /// Both lines went high since the last bit.
+    ready_for_pulse: bool,
+    /// A reset pulse started the packet.
+    ready_for_write: bool,
+    /// A full packet was received, and the stop bit is next.
+    ready_for_stop: bool,
+    /// Last value written to P1.
+    last_p1: u8,
+
+    /// The four palettes, in RGB555. Color 0 of palette 0 is shared by all.
+    palettes: [[u16; 4]; 4],
+    /// System palettes, in RGB555.
+    system_palettes: Vec<u16>,
+    /// Palette number of every cell of the game screen.
+    attr_map: [u8; ATTR_WIDTH * ATTR_HEIGHT],
+    /// Attribute files.
+    atfs: Vec<u8>,
+    /// Border tiles, in the SNES 4bpp format.
+    border_tiles: Vec<u8>,
+    /// Border map entries: tile, palette, and flips.
+    border_map: Vec<u8>,
+    /// Border palettes 4-7, of 16 colors each, in RGB555.
+    border_palettes: [u16; 64],
+    /// Screen mask.
+    mask: u8,
+    /// Number of joypads: 1, 2, or 4.
+    players: u8,
+    /// Joypad being read.
+    player: u8,
+    /// VRAM transfer to perform in the next frame: command, and argument.
+    transfer: Option<(u8, u8)>,
+
+    /// Colored game screen, as RGBA bytes.
+    screen: Vec<u8>,
+    /// Border, as RGBA bytes. Transparent pixels have alpha 0.
+    border: Vec<u8>,
+    /// Game screen within the border, as RGBA bytes.
+    frame: Vec<u8>,
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        let mut sgb = Self {
+            command: [0; PACKET_SIZE * MAX_PACKETS],
+            bit_index: 0,
+            ready_for_pulse: false,
+            ready_for_write: false,
+            ready_for_stop: false,
+            last_p1: 0xFF,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![0; SYSTEM_PALETTES * 4],
+            attr_map: [0; ATTR_WIDTH * ATTR_HEIGHT],
+            atfs: vec![0; ATF_COUNT * ATF_SIZE],
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: [0; 64],
+            mask: MASK_OFF,
+            players: 1,
+            player: 0,
+            transfer: None,
+            screen: vec![0xFF; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4],
+            border: vec![0; SGB_DISPLAY_WIDTH * SGB_DISPLAY_HEIGHT * 4],
+            frame: vec![0xFF; SGB_DISPLAY_WIDTH * SGB_DISPLAY_HEIGHT * 4],
+        };
+        sgb.compose_frame();
+        sgb
+    }
+
+    /// Resets the state of the Super Game Boy.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Gets the colored game screen, as RGBA bytes, of size
+    /// [DISPLAY_WIDTH] x [DISPLAY_HEIGHT].
+    pub fn screen(&self) -> &[u8] {
+        &self.screen
+    }
+
+    /// Gets the game screen within the border, as RGBA bytes, of size
+    /// [SGB_DISPLAY_WIDTH] x [SGB_DISPLAY_HEIGHT].
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Receives a write to P1, which may carry a bit of a command packet, or
+    /// select the next joypad.
+    pub fn write_p1(&mut self, value: u8) {
+        // The next joypad is selected when P15 goes high.
+        if value & 0x20 != 0 && self.last_p1 & 0x20 == 0 && self.players > 1 {
+            self.player = (self.player + 1) % self.players;
+        }
+        self.last_p1 = value;
+
+        let size = self.command_size();
+        match (value >> 4) & 0x03 {
+            // Both high: end of a pulse.
+            3 => self.ready_for_pulse = true,
+            // P14 low: a 0, or the stop bit.
+            2 => {
+                if !self.ready_for_pulse || !self.ready_for_write {
+                    return;
+                }
+                self.ready_for_pulse = false;
+                if self.ready_for_stop {
+                    if self.bit_index == size {
+                        self.execute();
+                        self.bit_index = 0;
+                        self.command.fill(0);
+                    }
+                    self.ready_for_write = false;
+                    self.ready_for_stop = false;
+                } else {
+                    self.push_bit(false);
+                }
+            }
+            // P15 low: a 1.
+            1 => {
+                if !self.ready_for_pulse || !self.ready_for_write {
+                    return;
+                }
+                self.ready_for_pulse = false;
+                if self.ready_for_stop {
+                    // A 1 instead of the stop bit. Drop the command.
+                    self.ready_for_write = false;
+                    self.ready_for_stop = false;
+                    self.bit_index = 0;
+                    self.command.fill(0);
+                } else {
+                    self.push_bit(true);
+                }
+            }
+            // Both low: reset pulse, which starts a packet. The packets
+            // after the first continue the command.
+            _ => {
+                if !self.ready_for_pulse {
+                    return;
+                }
+                self.ready_for_pulse = false;
+                self.ready_for_write = true;
+                if !self.bit_index.is_multiple_of(PACKET_SIZE * 8)
+                    || self.bit_index == 0
+                    || self.ready_for_stop
+                {
+                    self.bit_index = 0;
+                    self.command.fill(0);
+                    self.ready_for_stop = false;
+                }
+            }
+        }
+    }
+
+    /// Adjusts a read of P1 for multiplayer. With both lines high, the low
+    /// nibble holds the joypad being read (0xF for the first one). The other
+    /// joypads have no buttons pressed.
+    pub fn read_p1(&self, joyp: u8) -> u8 {
+        if self.players == 1 {
+            joyp
+        } else if joyp & 0x30 == 0x30 {
+            (joyp & 0xF0) | (0x0F - self.player)
+        } else if self.player != 0 {
+            joyp | 0x0F
+        } else {
+            joyp
+        }
+    }
+
+    /// Number of bits of the command being received, from the number of
+    /// packets in its first byte.
+    fn command_size(&self) -> usize {
+        (self.command[0] as usize & 0x07).max(1) * PACKET_SIZE * 8
+    }
+
+    /// Receives a bit of a packet, LSB first.
+    fn push_bit(&mut self, bit: bool) {
+        if self.bit_index >= self.command.len() * 8 {
+            return;
+        }
+        if bit {
+            self.command[self.bit_index / 8] |= 1 << (self.bit_index % 8);
+        }
+        self.bit_index += 1;
+        if self.bit_index.is_multiple_of(PACKET_SIZE * 8) {
+            self.ready_for_stop = true;
+        }
+    }
+
+    /// Runs the command that was just received.
+    fn execute(&mut self) {
+        let c = self.command;
+        match c[0] >> 3 {
+            PAL01 => self.set_palette_pair(0, 1),
+            PAL23 => self.set_palette_pair(2, 3),
+            PAL03 => self.set_palette_pair(0, 3),
+            PAL12 => self.set_palette_pair(1, 2),
+            ATTR_BLK => {
+                let count = (c[1] as usize).min(18);
+                for set in c[2..2 + count * 6].chunks_exact(6) {
+                    self.attr_block(set);
+                }
+            }
+            ATTR_LIN => {
+                let count = (c[1] as usize).min(PACKET_SIZE * MAX_PACKETS - 2);
+                for &line in &c[2..2 + count] {
+                    let n = (line & 0x1F) as usize;
+                    let palette = (line >> 5) & 0x03;
+                    if line & 0x80 != 0 {
+                        self.fill_attr(|_, y| y == n, palette);
+                    } else {
+                        self.fill_attr(|x, _| x == n, palette);
+                    }
+                }
+            }
+            ATTR_DIV => {
+                let after = c[1] & 0x03;
+                let before = (c[1] >> 2) & 0x03;
+                let line = (c[1] >> 4) & 0x03;
+                let n = (c[2] & 0x1F) as usize;
+                let horizontal = c[1] & 0x40 != 0;
+                for y in 0..ATTR_HEIGHT {
+                    for x in 0..ATTR_WIDTH {
+                        let pos = if horizontal { y } else { x };
+                        self.attr_map[y * ATTR_WIDTH + x] = match pos.cmp(&n) {
+                            std::cmp::Ordering::Less => before,
+                            std::cmp::Ordering::Equal => line,
+                            std::cmp::Ordering::Greater => after,
+                        };
+                    }
+                }
+            }
+            ATTR_CHR => {
+                let mut x = (c[1] as usize).min(ATTR_WIDTH - 1);
+                let mut y = (c[2] as usize).min(ATTR_HEIGHT - 1);
+                let count = (u16::from_le_bytes([c[3], c[4]]) as usize)
+                    .min(ATTR_WIDTH * ATTR_HEIGHT)
+                    .min((c.len() - 6) * 4);
+                let vertical = c[5] & 0x01 != 0;
+                for i in 0..count {
+                    let palette = (c[6 + i / 4] >> (6 - (i % 4) * 2)) & 0x03;
+                    self.attr_map[y * ATTR_WIDTH + x] = palette;
+                    if vertical {
+                        y += 1;
+                        if y == ATTR_HEIGHT {
+                            y = 0;
+                            x = (x + 1) % ATTR_WIDTH;
+                        }
+                    } else {
+                        x += 1;
+                        if x == ATTR_WIDTH {
+                            x = 0;
+                            y = (y + 1) % ATTR_HEIGHT;
+                        }
+                    }
+                }
+            }
+            PAL_SET => {
+                for p in 0..4 {
+                    let index = u16::from_le_bytes([c[1 + p * 2], c[2 + p * 2]]) as usize & 0x1FF;
+                    self.palettes[p]
+                        .copy_from_slice(&self.system_palettes[index * 4..index * 4 + 4]);
+                }
+                if c[9] & 0x80 != 0 {
+                    self.apply_atf(c[9] & 0x3F);
+                }
+                if c[9] & 0x40 != 0 {
+                    self.mask = MASK_OFF;
+                }
+            }
+            ATTR_SET => {
+                self.apply_atf(c[1] & 0x3F);
+                if c[1] & 0x40 != 0 {
+                    self.mask = MASK_OFF;
+                }
+            }
+            MLT_REQ => {
+                self.players = match c[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            MASK_EN => self.mask = c[1] & 0x03,
+            PAL_TRN | CHR_TRN | PCT_TRN | ATTR_TRN => self.transfer = Some((c[0] >> 3, c[1])),
+            // Sound, SNES programs, and the rest are not supported.
+            _ => {}
+        }
+    }
+
+    /// Sets color 0 of all palettes, and colors 1-3 of palettes `a` and `b`,
+    /// from a PALxx command.
+    fn set_palette_pair(&mut self, a: usize, b: usize) {
+        let color =
+            |i: usize| u16::from_le_bytes([self.command[1 + i * 2], self.command[2 + i * 2]]);
+        let colors: [u16; 7] = std::array::from_fn(color);
+        self.palettes[0][0] = colors[0];
+        self.palettes[a][1..].copy_from_slice(&colors[1..4]);
+        self.palettes[b][1..].copy_from_slice(&colors[4..7]);
+    }
+
+    /// Applies a data set of ATTR_BLK: a rectangle, with palettes for the
+    /// cells inside it, on its edge, and outside it.
+    fn attr_block(&mut self, set: &[u8]) {
+        let control = set[0] & 0x07;
+        let inside = set[1] & 0x03;
+        let outside = (set[1] >> 4) & 0x03;
+        // With only the inside or only the outside, the edge goes along.
+        let edge = match control {
+            0x01 => Some(inside),
+            0x04 => Some(outside),
+            _ if control & 0x02 != 0 => Some((set[1] >> 2) & 0x03),
+            _ => None,
+        };
+        let (x1, y1) = ((set[2] & 0x1F) as usize, (set[3] & 0x1F) as usize);
+        let (x2, y2) = ((set[4] & 0x1F) as usize, (set[5] & 0x1F) as usize);
+        for y in 0..ATTR_HEIGHT {
+            for x in 0..ATTR_WIDTH {
+                let palette = if x > x1 && x < x2 && y > y1 && y < y2 {
+                    (control & 0x01 != 0).then_some(inside)
+                } else if x >= x1 && x <= x2 && y >= y1 && y <= y2 {
+                    edge
+                } else {
+                    (control & 0x04 != 0).then_some(outside)
+                };
+                if let Some(palette) = palette {
+                    self.attr_map[y * ATTR_WIDTH + x] = palette;
+                }
+            }
+        }
+    }
+
+    /// Sets the palette of the cells that pass the given test.
+    fn fill_attr(&mut self, test: impl Fn(usize, usize) -> bool, palette: u8) {
+        for y in 0..ATTR_HEIGHT {
+            for x in 0..ATTR_WIDTH {
+                if test(x, y) {
+                    self.attr_map[y * ATTR_WIDTH + x] = palette;
+                }
+            }
+        }
+    }
+
+    /// Copies an attribute file to the attribute map.
+    fn apply_atf(&mut self, index: u8) {
+        let index = (index as usize).min(ATF_COUNT - 1);
+        let atf = &self.atfs[index * ATF_SIZE..(index + 1) * ATF_SIZE];
+        for (i, cell) in self.attr_map.iter_mut().enumerate() {
+            *cell = (atf[i / 4] >> (6 - (i % 4) * 2)) & 0x03;
+        }
+    }
+
+    /// Performs the pending VRAM transfer, if any, with the given data.
+    fn transfer(&mut self, data: &[u8]) {
+        let Some((command, argument)) = self.transfer.take() else {
+            return;
+        };
+        match command {
+            PAL_TRN => {
+                for (color, bytes) in self.system_palettes.iter_mut().zip(data.chunks_exact(2)) {
+                    *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+            }
+            CHR_TRN => {
+                let half = (argument & 0x01) as usize * TRANSFER_SIZE;
+                self.border_tiles[half..half + TRANSFER_SIZE].copy_from_slice(data);
+                self.render_border();
+            }
+            PCT_TRN => {
+                self.border_map.copy_from_slice(&data[..BORDER_MAP_SIZE]);
+                for (color, bytes) in self
+                    .border_palettes
+                    .iter_mut()
+                    .zip(data[BORDER_MAP_SIZE..].chunks_exact(2))
+                {
+                    *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+                self.render_border();
+            }
+            ATTR_TRN => self.atfs.copy_from_slice(&data[..ATF_COUNT * ATF_SIZE]),
+            _ => {}
+        }
+    }
+
+    /// Renders the border from its tiles, map, and palettes.
+    fn render_border(&mut self) {
+        for ty in 0..SGB_DISPLAY_HEIGHT / 8 {
+            for tx in 0..SGB_DISPLAY_WIDTH / 8 {
+                let i = (ty * 32 + tx) * 2;
+                let entry = u16::from_le_bytes([self.border_map[i], self.border_map[i + 1]]);
+                let tile = &self.border_tiles[(entry as usize & 0xFF) * 32..][..32];
+                let palette = ((entry >> 10) & 0x03) as usize;
+                let flip_x = entry & 0x4000 != 0;
+                let flip_y = entry & 0x8000 != 0;
+                for row in 0..8 {
+                    let r = if flip_y { 7 - row } else { row };
+                    let planes = [
+                        tile[r * 2],
+                        tile[r * 2 + 1],
+                        tile[16 + r * 2],
+                        tile[17 + r * 2],
+                    ];
+                    for col in 0..8 {
+                        let bit = if flip_x { col } else { 7 - col };
+                        let color = planes
+                            .iter()
+                            .enumerate()
+                            .fold(0, |c, (p, plane)| c | (((plane >> bit) & 1) << p));
+                        let pos = ((ty * 8 + row) * SGB_DISPLAY_WIDTH + tx * 8 + col) * 4;
+                        let rgba = if color == 0 {
+                            [0; 4]
+                        } else {
+                            rgba(self.border_palettes[palette * 16 + color as usize])
+                        };
+                        self.border[pos..pos + 4].copy_from_slice(&rgba);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Called at the start of VBlank. Performs the pending VRAM transfer,
+    /// and colors the frame the PPU just finished.
+    pub fn vblank(&mut self, ppu: &Ppu) {
+        if self.transfer.is_some() {
+            self.transfer(&ppu.screen_tile_data());
+        }
+        self.compose_screen(&ppu.shades);
+        self.compose_frame();
+    }
+
+    /// Colors the game screen from the shades of the PPU, with the palette
+    /// of each cell. Shade 0 always has the shared color 0.
+    fn compose_screen(&mut self, shades: &[u8]) {
+        let backdrop = rgba(self.palettes[0][0]);
+        match self.mask {
+            MASK_FREEZE => {}
+            MASK_BLACK => self
+                .screen
+                .chunks_exact_mut(4)
+                .for_each(|p| p.copy_from_slice(&[0, 0, 0, 0xFF])),
+            MASK_COLOR0 => self
+                .screen
+                .chunks_exact_mut(4)
+                .for_each(|p| p.copy_from_slice(&backdrop)),
+            _ => {
+                for (i, (pixel, &shade)) in self.screen.chunks_exact_mut(4).zip(shades).enumerate()
+                {
+                    let (x, y) = (i % DISPLAY_WIDTH, i / DISPLAY_WIDTH);
+                    let palette = self.attr_map[(y / 8) * ATTR_WIDTH + x / 8] as usize;
+                    let color = match shade & 0x03 {
+                        0 => backdrop,
+                        s => rgba(self.palettes[palette][s as usize]),
+                    };
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    /// Draws the game screen within the border. The transparent pixels of
+    /// the border show color 0.
+    fn compose_frame(&mut self) {
+        let backdrop = rgba(self.palettes[0][0]);
+        for (pixel, border) in self
+            .frame
+            .chunks_exact_mut(4)
+            .zip(self.border.chunks_exact(4))
+        {
+            pixel.copy_from_slice(if border[3] == 0 { &backdrop } else { border });
+        }
+        for (y, row) in self.screen.chunks_exact(DISPLAY_WIDTH * 4).enumerate() {
+            for (x, pixel) in row.chunks_exact(4).enumerate() {
+                let pos = ((SCREEN_Y + y) * SGB_DISPLAY_WIDTH + SCREEN_X + x) * 4;
+                // The border covers the game screen where it is not transparent.
+                if self.border[pos + 3] == 0 {
+                    self.frame[pos..pos + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts an RGB555 color to RGBA bytes.
+fn rgba(rgb555: u16) -> [u8; 4] {
+    let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+    [
+        expand(rgb555 & 0x1F),
+        expand((rgb555 >> 5) & 0x1F),
+        expand((rgb555 >> 10) & 0x1F),
+        0xFF,
+    ]
+}
+
+impl SaveState for Sgb {
+    /// The border and the frame are derived, so they are rendered again.
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.command);
+        w.usize(self.bit_index);
+        w.bool(self.ready_for_pulse);
+        w.bool(self.ready_for_write);
+        w.bool(self.ready_for_stop);
+        w.u8(self.last_p1);
+        for color in self.palettes.iter().flatten() {
+            w.u16(*color);
+        }
+        for color in &self.system_palettes {
+            w.u16(*color);
+        }
+        w.bytes(&self.attr_map);
+        w.bytes(&self.atfs);
+        w.bytes(&self.border_tiles);
+        w.bytes(&self.border_map);
+        for color in &self.border_palettes {
+            w.u16(*color);
+        }
+        w.u8(self.mask);
+        w.u8(self.players);
+        w.u8(self.player);
+        let (command, argument) = self.transfer.unwrap_or((0xFF, 0));
+        w.u8(command);
+        w.u8(argument);
+        w.bytes(&self.screen);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        r.bytes_into(&mut self.command)?;
+        self.bit_index = r.usize()?.min(self.command.len() * 8);
+        self.ready_for_pulse = r.bool()?;
+        self.ready_for_write = r.bool()?;
+        self.ready_for_stop = r.bool()?;
+        self.last_p1 = r.u8()?;
+        for color in self.palettes.iter_mut().flatten() {
+            *color = r.u16()?;
+        }
+        for color in self.system_palettes.iter_mut() {
+            *color = r.u16()?;
+        }
+        r.bytes_into(&mut self.attr_map)?;
+        self.attr_map.iter_mut().for_each(|p| *p &= 0x03);
+        r.bytes_into(&mut self.atfs)?;
+        r.bytes_into(&mut self.border_tiles)?;
+        r.bytes_into(&mut self.border_map)?;
+        for color in self.border_palettes.iter_mut() {
+            *color = r.u16()?;
+        }
+        self.mask = r.u8()? & 0x03;
+        self.players = match r.u8()? {
+            p @ (1 | 2 | 4) => p,
+            _ => 1,
+        };
+        self.player = r.u8()? % self.players;
+        let command = r.u8()?;
+        let argument = r.u8()?;
+        self.transfer = (command != 0xFF).then_some((command, argument));
+        r.bytes_into(&mut self.screen)?;
+        self.render_border();
+        self.compose_frame();
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
 /// # Timer
 /// The timer registers and logic.
 pub struct Timer {
@@ -114,10 +116,9 @@ impl Timer {
             let new_bit = (self.divider >> self.timer_bit) & 1 != 0;
 
             // Update TIMA on falling edge of selected bit
-            if self.enabled
-                && self.last_div_bit && !new_bit {
-                    self.increment_tima();
-                }
+            if self.enabled && self.last_div_bit && !new_bit {
+                self.increment_tima();
+            }
             self.last_div_bit = new_bit;
         }
     }
@@ -135,3 +136,32 @@ impl Timer {
         self.divider
     }
 }
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Timer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.divider);
+        w.bool(self.last_div_bit);
+        w.u8(self.tima);
+        w.u8(self.tma);
+        w.bool(self.enabled);
+        w.u8(self.timer_bit);
+        w.u8(self.i_mask);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader<'_>) -> std::io::Result<()> {
+        self.divider = r.u16()?;
+        self.last_div_bit = r.bool()?;
+        self.tima = r.u8()?;
+        self.tma = r.u8()?;
+        self.enabled = r.bool()?;
+        self.timer_bit = r.u8()?;
+        self.i_mask = r.u8()?;
+        Ok(())
+    }
+}
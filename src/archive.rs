@@ -0,0 +1,259 @@
+use playkid_core::patch::{self, MAX_ROM_SIZE};
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+
+/// Extensions of the Game Boy ROM files, also used in the file dialog.
+pub const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "bin"];
+/// Extensions of the supported archives.
+pub const ARCHIVE_EXTENSIONS: [&str; 3] = ["zip", "gz", "7z"];
+/// Extensions of the supported patches, in the order they are looked up.
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+/// # ROM source
+/// Where a ROM comes from: a plain file, or an entry of a `.zip`, `.gz`,
+/// or `.7z` archive, optionally with an IPS, UPS, or BPS patch applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomSource {
+    /// Path to the ROM file or to the archive.
+    pub path: PathBuf,
+    /// Name of the ROM inside the archive, if any.
+    pub entry: Option<String>,
+    /// Patch to apply to the ROM, if any.
+    pub patch: Option<PathBuf>,
+}
+
+impl RomSource {
+    /// Path of the ROM, as if it were extracted next to the archive. The
+    /// save files (SRAM, save states) are placed next to it, under the
+    /// ROM's own name. A patched ROM is named after the patch instead, so
+    /// that `hack.ips` saves to `hack.ips.sav`, and the saves of the base
+    /// game are left alone.
+    pub fn rom_path(&self) -> PathBuf {
+        let rom = match &self.entry {
+            Some(entry) => {
+                // Entries can be in folders inside the archive.
+                let name = Path::new(entry).file_name().unwrap_or(entry.as_ref());
+                self.path.with_file_name(name)
+            }
+            None => self.path.clone(),
+        };
+        match &self.patch {
+            Some(patch) => {
+                let mut name = patch.file_name().unwrap_or_default().to_os_string();
+                name.push(".");
+                name.push(rom.extension().unwrap_or("gb".as_ref()));
+                patch.with_file_name(name)
+            }
+            None => rom,
+        }
+    }
+
+    /// Uses the given patch, or else the patch next to the ROM with the
+    /// same base name and one of the [PATCH_EXTENSIONS], if there is one.
+    pub fn with_patch(mut self, patch: Option<&Path>) -> Self {
+        self.patch = match patch {
+            Some(patch) => Some(patch.to_path_buf()),
+            None => {
+                let rom = self.rom_path();
+                PATCH_EXTENSIONS
+                    .iter()
+                    .map(|ext| rom.with_extension(ext))
+                    .find(|p| p.is_file())
+            }
+        };
+        self
+    }
+
+    /// Reads the ROM data, extracting it from the archive and applying the
+    /// patch if needed. The files on disk are never modified.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        let data = self.read_unpatched()?;
+        let Some(path) = &self.patch else {
+            return Ok(data);
+        };
+        patch::apply(&data, &std::fs::read(path)?).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to apply patch {}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Reads the ROM data, extracting it from the archive if needed.
+    fn read_unpatched(&self) -> Result<Vec<u8>> {
+        let Some(entry) = &self.entry else {
+            return std::fs::read(&self.path);
+        };
+        match archive_kind(&self.path) {
+            Some("zip") => {
+                let mut zip = zip::ZipArchive::new(File::open(&self.path)?)?;
+                read_limited(zip.by_name(entry)?)
+            }
+            Some("gz") => read_limited(flate2::read::MultiGzDecoder::new(File::open(&self.path)?)),
+            Some("7z") => {
+                let mut reader =
+                    sevenz_rust::SevenZReader::open(&self.path, sevenz_rust::Password::empty())
+                        .map_err(to_io)?;
+                let mut data = None;
+                reader
+                    .for_each_entries(|e, r| {
+                        if data.is_none() && !e.is_directory() && e.name() == entry {
+                            data = Some(read_limited(r));
+                        }
+                        Ok(data.is_none())
+                    })
+                    .map_err(to_io)?;
+                data.unwrap_or_else(|| Err(not_found(&self.path, entry)))
+            }
+            _ => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("Not an archive: {}", self.path.display()),
+            )),
+        }
+    }
+
+    /// Describes the source, for the user.
+    pub fn name(&self) -> String {
+        let name = match &self.entry {
+            Some(entry) => format!("{} ({})", entry, self.path.display()),
+            None => self.path.display().to_string(),
+        };
+        match &self.patch {
+            Some(patch) => format!("{} + {}", name, patch.display()),
+            None => name,
+        }
+    }
+}
+
+/// Lists the ROMs in the file at the given path. A plain ROM file or a
+/// `.gz` file give a single ROM. For `.zip` and `.7z` archives, every entry
+/// with a ROM extension is listed.
+pub fn open(path: &Path) -> Result<Vec<RomSource>> {
+    let entries = match archive_kind(path) {
+        None => {
+            return Ok(vec![RomSource {
+                path: path.to_path_buf(),
+                entry: None,
+                patch: None,
+            }]);
+        }
+        Some("gz") => {
+            // The ROM name is the name of the archive, without `.gz`.
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let name = if has_rom_extension(&name) {
+                name
+            } else {
+                format!("{}.gb", name)
+            };
+            vec![name]
+        }
+        Some("zip") => {
+            let zip = zip::ZipArchive::new(File::open(path)?)?;
+            zip.file_names()
+                .filter(|name| !name.ends_with('/') && has_rom_extension(name))
+                .map(str::to_string)
+                .collect()
+        }
+        Some(_) => {
+            let reader = sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty())
+                .map_err(to_io)?;
+            reader
+                .archive()
+                .files
+                .iter()
+                .filter(|e| !e.is_directory() && has_rom_extension(e.name()))
+                .map(|e| e.name().to_string())
+                .collect()
+        }
+    };
+    let mut sources: Vec<RomSource> = entries
+        .into_iter()
+        .map(|entry| RomSource {
+            path: path.to_path_buf(),
+            entry: Some(entry),
+            patch: None,
+        })
+        .collect();
+    sources.sort_by(|a, b| a.entry.cmp(&b.entry));
+    if sources.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No Game Boy ROM found in {}", path.display()),
+        ));
+    }
+    Ok(sources)
+}
+
+/// Picks the ROM to load among the ones in an archive: the only one, or the
+/// one with the given entry name. The entry name can also be the file name
+/// of the entry, without its folder.
+pub fn select(path: &Path, entry: Option<&str>) -> Result<RomSource> {
+    let mut sources = open(path)?;
+    if let Some(wanted) = entry {
+        return sources
+            .into_iter()
+            .find(|s| {
+                s.entry.as_deref().is_some_and(|e| {
+                    e == wanted || Path::new(e).file_name().is_some_and(|n| n == wanted)
+                })
+            })
+            .ok_or_else(|| not_found(path, wanted));
+    }
+    if sources.len() > 1 {
+        let names: Vec<String> = sources.iter().filter_map(|s| s.entry.clone()).collect();
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Several ROMs in {}, pick one with --entry: {}",
+                path.display(),
+                names.join(", ")
+            ),
+        ));
+    }
+    Ok(sources.remove(0))
+}
+
+/// Gets the kind of archive from the extension of the path, if any.
+fn archive_kind(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    ARCHIVE_EXTENSIONS.into_iter().find(|a| *a == ext)
+}
+
+/// Whether the file name has one of the [ROM_EXTENSIONS].
+fn has_rom_extension(name: &str) -> bool {
+    Path::new(name).extension().is_some_and(|ext| {
+        ROM_EXTENSIONS.contains(&ext.to_string_lossy().to_ascii_lowercase().as_str())
+    })
+}
+
+/// Reads an extracted ROM, up to [MAX_ROM_SIZE] bytes. The sizes declared
+/// in the archive are not trusted, so that a corrupt archive or a
+/// decompression bomb gives an error instead of exhausting the memory.
+fn read_limited(reader: impl Read) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader
+        .take(MAX_ROM_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > MAX_ROM_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("ROM larger than {} bytes", MAX_ROM_SIZE),
+        ));
+    }
+    Ok(data)
+}
+
+fn not_found(path: &Path, entry: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("No ROM named {} in {}", entry, path.display()),
+    )
+}
+
+fn to_io(e: sevenz_rust::Error) -> Error {
+    Error::other(e.to_string())
+}
@@ -0,0 +1,49 @@
+use playkid_core::constants::AUDIO_SAMPLE_RATE;
+use rodio::{OutputStream, Sink, buffer::SamplesBuffer};
+
+/// # Audio output
+/// Plays the samples produced by the emulator core on the default audio
+/// device, using [rodio].
+pub struct AudioOutput {
+    /// Audio device.
+    sink: Sink,
+    /// We must keep the stream alive for audio to play.
+    _stream: OutputStream,
+    /// Whether samples are discarded instead of played.
+    muted: bool,
+}
+
+impl AudioOutput {
+    /// Opens the default audio device.
+    pub fn new() -> Self {
+        // Initialize Rodio.
+        let mut stream_handle =
+            rodio::OutputStreamBuilder::open_default_stream().expect("open default audio stream");
+        stream_handle.log_on_drop(false);
+        let sink = rodio::Sink::connect_new(stream_handle.mixer());
+        Self {
+            sink,
+            _stream: stream_handle,
+            muted: false,
+        }
+    }
+
+    /// Mutes or unmutes the output. Muting also drops the queued samples, so
+    /// that playback resumes cleanly with fresh audio.
+    pub fn set_muted(&mut self, muted: bool) {
+        if muted && !self.muted {
+            self.sink.clear();
+            self.sink.play();
+        }
+        self.muted = muted;
+    }
+
+    /// Queues the given interleaved stereo samples for playback.
+    pub fn play(&mut self, samples: Vec<f32>) {
+        // Drop samples if the sink is too far behind, to prevent latency from building up.
+        if !self.muted && !samples.is_empty() && self.sink.len() < 10 {
+            let source = SamplesBuffer::new(2, AUDIO_SAMPLE_RATE, samples);
+            self.sink.append(source);
+        }
+    }
+}
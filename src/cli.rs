@@ -1,5 +1,7 @@
-use crate::constants;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use playkid_core::constants;
+use playkid_core::model::Model;
+use playkid_core::ppu::Renderer;
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -8,13 +10,18 @@ use std::path::PathBuf;
     version = env!("CARGO_PKG_VERSION"),
     about = "Minimalist Game Boy emulator for the cool kids.",
     author = "Toni Sagristà - tonisagrista.com",
-    help_template = "{name} {version}\n{author}\n\n{about}\n\n{usage-heading} {usage}\n\n{all-args}"
+    help_template = "{name} {version}\n{author}\n\n{about}\n\n{usage-heading} {usage}\n\n{all-args}",
+    args_conflicts_with_subcommands = true
 )]
 /// ## CLI Arguments
 /// Contains the command line interface arguments of the desktop build
 /// of Play Kid.
 pub struct Args {
-    /// Path to the input ROM file to load.
+    /// Subcommand to run. If none is given, the emulator window is opened.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Path to the input ROM file to load. It can also be a `.zip`, `.gz`, or
+    /// `.7z` archive.
     pub input: Option<PathBuf>,
     /// Initial window scale. It can also be resized manually.
     #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..15))]
@@ -26,19 +33,146 @@ pub struct Args {
     #[arg(short, long)]
     pub fps: bool,
     /// Skip global checksum, header checksum, and logo sequence check.
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub skipcheck: bool,
+    /// Boot ROM file (DMG, MGB, or CGB) to run before the game. Without it,
+    /// the boot sequence is skipped.
+    #[arg(long, global = true)]
+    pub boot_rom: Option<PathBuf>,
+    /// ROM to load from a `.zip` or `.7z` archive with several ROMs. By
+    /// default, the only ROM in the archive is loaded.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub entry: Option<String>,
+    /// IPS, UPS, or BPS patch to apply to the ROM in memory. By default, a
+    /// patch next to the ROM with the same base name is applied.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub patch: Option<PathBuf>,
+    /// Hardware model to emulate: dmg0, dmg, mgb, sgb, sgb2, or cgb. By
+    /// default, it is picked from the cartridge.
+    #[arg(long, global = true)]
+    pub model: Option<Model>,
+    /// PPU renderer: fifo, the accurate pixel FIFO, or scanline, the fast
+    /// renderer that draws whole lines at once.
+    #[arg(long, global = true, default_value_t = Renderer::Fifo)]
+    pub renderer: Renderer,
+    /// Listen for a link cable connection from another instance, at this
+    /// port or address.
+    #[arg(
+        long,
+        global = true,
+        value_name = "ADDR",
+        conflicts_with = "link_connect"
+    )]
+    pub link_host: Option<String>,
+    /// Connect the link cable to another instance listening at this port or
+    /// address.
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub link_connect: Option<String>,
+    /// Plug a Game Boy Printer into the serial port, instead of the link cable.
+    #[arg(
+        long,
+        global = true,
+        conflicts_with_all = ["link_host", "link_connect"]
+    )]
+    pub printer: bool,
+    /// Directory of the PNG files printed by the Game Boy Printer.
+    #[arg(long, global = true, value_name = "DIR", default_value = ".")]
+    pub printer_dir: PathBuf,
+    /// Length of the rewind buffer, in seconds. Use 0 to disable rewind.
+    #[arg(long, default_value_t = 120)]
+    pub rewind: u32,
+    /// Play back this movie file after loading the ROM.
+    #[arg(long)]
+    pub movie: Option<PathBuf>,
+}
+
+/// ## Subcommands
+/// The subcommands of the desktop build of Play Kid.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run a ROM file, either in the emulator window or headless.
+    Run(RunArgs),
+    /// Print the header, checksums, and file sizes of a ROM file, and exit.
+    Info(InfoArgs),
+}
+
+/// ## Run arguments
+/// Arguments of the `run` subcommand.
+#[derive(clap::Args, Debug)]
+pub struct RunArgs {
+    /// Path to the input ROM file or archive to run.
+    pub input: PathBuf,
+    /// Run without window and audio device, then write the final frame and exit.
+    #[arg(long)]
+    pub headless: bool,
+    /// Maximum number of frames to run in headless mode.
+    #[arg(long, default_value_t = 600)]
+    pub frames: u64,
+    /// Stop as soon as the program counter reaches this address (hex, e.g. `$0150`).
+    #[arg(long, value_parser = parse_address)]
+    pub until_pc: Option<u16>,
+    /// Stop as soon as the serial output contains this string.
+    #[arg(long)]
+    pub until_serial: Option<String>,
+    /// Write the final frame to this PNG file.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Expected hash of the final frame (hex). Exit with an error if it does not match.
+    #[arg(long, value_parser = parse_hash)]
+    pub expect_hash: Option<u64>,
+    /// Play back this movie file. In headless mode, runs until the end of the
+    /// movie, and exits with an error if it desyncs.
+    #[arg(long)]
+    pub movie: Option<PathBuf>,
+}
+
+/// ## Info arguments
+/// Arguments of the `info` subcommand.
+#[derive(clap::Args, Debug)]
+pub struct InfoArgs {
+    /// Path to the input ROM file or archive to inspect.
+    pub input: PathBuf,
+    /// Print the information as a JSON object.
+    #[arg(long)]
+    pub json: bool,
 }
 
 impl Args {
     /// Creates an Args instance with the default values.
     pub fn default() -> Args {
         Args {
+            command: None,
             input: None,
             scale: 4,
             debug: false,
             fps: false,
             skipcheck: false,
+            boot_rom: None,
+            entry: None,
+            patch: None,
+            model: None,
+            renderer: Renderer::Fifo,
+            link_host: None,
+            link_connect: None,
+            printer: false,
+            printer_dir: PathBuf::from("."),
+            rewind: 120,
+            movie: None,
         }
     }
 }
+
+/// Parses a 16-bit address in hex, with an optional `$` or `0x` prefix.
+fn parse_address(s: &str) -> Result<u16, String> {
+    let digits = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|e| format!("invalid address '{}': {}", s, e))
+}
+
+/// Parses a 64-bit frame hash in hex, with an optional `0x` prefix.
+fn parse_hash(s: &str) -> Result<u64, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    u64::from_str_radix(digits, 16).map_err(|e| format!("invalid hash '{}': {}", s, e))
+}
@@ -1,5 +1,135 @@
 use egui::InputState;
+use gilrs::{Button, EventType};
+use playkid_core::debugmanager::{DebugManager, RunCommand};
+use playkid_core::joypad::Joypad;
+use playkid_core::machine::Machine;
+
 /// Event handler trait.
 pub trait EventHandler {
     fn handle_event(&mut self, i: &InputState) -> bool;
 }
+
+/// Game controller handler trait.
+pub trait ControllerHandler {
+    fn handle_controller_input(&mut self, event: EventType);
+}
+
+impl EventHandler for Machine {
+    /// Polls the events in the queue of the event pump and redirects them to the
+    /// interested partners ;).
+    fn handle_event(&mut self, i: &InputState) -> bool {
+        // Reset cycles.
+        let mut handled = false;
+
+        // Handle general emulator events.
+        if !handled {
+            handled = self.memory.joypad.handle_event(i);
+        }
+
+        // Debug events.
+        if !handled {
+            handled = self.debug.handle_event(i);
+        }
+
+        handled
+    }
+}
+
+impl EventHandler for DebugManager {
+    /// Process keyboard inputs specifically for debugging.
+    /// Returns true if the event was handled.
+    fn handle_event(&mut self, i: &InputState) -> bool {
+        if i.key_released(egui::Key::F6) {
+            self.request_step_instruction();
+            true
+        } else if i.key_released(egui::Key::F7) {
+            self.request_step_scanline();
+            true
+        } else if i.key_released(egui::Key::F8) {
+            self.request_run(RunCommand::StepOver);
+            true
+        } else if i.key_released(egui::Key::F9) {
+            self.toggle_paused();
+            true
+        } else if i.key_released(egui::Key::F10) {
+            self.request_run(RunCommand::StepOut);
+            true
+        } else if i.key_released(egui::Key::F11) {
+            self.request_run(RunCommand::StepFrame);
+            true
+        } else if i.key_released(egui::Key::F12) {
+            self.request_run_to_cursor();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl EventHandler for Joypad {
+    fn handle_event(&mut self, i: &InputState) -> bool {
+        let mut state = self.state();
+        let keys = [
+            (egui::Key::ArrowDown, &mut state.down),
+            (egui::Key::ArrowUp, &mut state.up),
+            (egui::Key::ArrowRight, &mut state.right),
+            (egui::Key::ArrowLeft, &mut state.left),
+            (egui::Key::A, &mut state.a),
+            (egui::Key::B, &mut state.b),
+            (egui::Key::Enter, &mut state.start),
+            (egui::Key::Space, &mut state.select),
+        ];
+        let mut handled = false;
+        for (key, button) in keys {
+            if i.key_pressed(key) {
+                *button = true;
+                handled = true;
+                break;
+            }
+            if i.key_released(key) {
+                *button = false;
+                handled = true;
+                break;
+            }
+        }
+        if handled {
+            self.set_state(state);
+        }
+        handled
+    }
+}
+
+impl ControllerHandler for Joypad {
+    /// Main game controller handler.
+    fn handle_controller_input(&mut self, event: EventType) {
+        let mut state = self.state();
+        match event {
+            EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _) => {
+                let pressed = matches!(event, EventType::ButtonPressed(..));
+                match button {
+                    Button::South | Button::East => state.a = pressed,
+                    Button::North | Button::West => state.b = pressed,
+                    Button::Start => state.start = pressed,
+                    Button::Select => state.select = pressed,
+                    Button::DPadUp => state.up = pressed,
+                    Button::DPadDown => state.down = pressed,
+                    Button::DPadLeft => state.left = pressed,
+                    Button::DPadRight => state.right = pressed,
+                    _ => (),
+                }
+            }
+            EventType::AxisChanged(axis, value, _) => {
+                if axis == gilrs::Axis::LeftStickX {
+                    state.left = value < -0.5;
+                    state.right = value > 0.5;
+                }
+                if axis == gilrs::Axis::LeftStickY {
+                    state.up = value > 0.5;
+                    state.down = value < -0.5;
+                }
+            }
+            _ => (),
+        }
+        self.set_state(state);
+    }
+}
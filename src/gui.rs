@@ -1,12 +1,23 @@
-use crate::constants;
-use crate::instruction::RunInstr;
-use crate::machine::Machine;
-use crate::uistate::UIState;
+use crate::archive::{ARCHIVE_EXTENSIONS, ROM_EXTENSIONS, RomSource};
+use crate::uistate::{LinkRequest, UIState};
 use egui::{
     CollapsingHeader, Color32, Context, FontFamily, FontId, Frame, RichText, ScrollArea, Sense,
     TextEdit, text::LayoutJob, vec2,
 };
 use egui_notify::{Anchor, Toasts};
+use playkid_core::cartridge::RomInfo;
+use playkid_core::cheats::CheatCode;
+use playkid_core::constants;
+use playkid_core::debugmanager::{RunCommand, Watch};
+use playkid_core::instruction::RunInstr;
+use playkid_core::machine::Machine;
+use playkid_core::model::Model;
+use playkid_core::movie::MovieAnchor;
+use playkid_core::ppu::Renderer;
+use playkid_core::printer::PRINTER_WIDTH;
+use playkid_core::ramsearch::{Filter, Operand, RamSearch, ValueSize};
+use playkid_core::rewind::Rewind;
+use playkid_core::savestate::STATE_SLOTS;
 use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use std::time::Duration;
@@ -22,6 +33,26 @@ pub const RED: Color32 = Color32::from_rgb(219, 68, 55);
 pub const YELLOW: Color32 = Color32::from_rgb(60, 52, 0);
 pub const ORANGE: Color32 = Color32::from_rgb(255, 132, 0);
 
+/// Default port of the link cable.
+pub const DEFAULT_LINK_PORT: u16 = 5555;
+
+/// Maximum length of the serial console text. Older text is dropped.
+const SERIAL_CONSOLE_MAX: usize = 64 * 1024;
+
+/// Names of the RAM search filters, in the order of the combo box.
+const SEARCH_FILTERS: [&str; 7] = [
+    "Equal to",
+    "Not equal to",
+    "Greater than",
+    "Less than",
+    "Changed",
+    "Unchanged",
+    "Changed by",
+];
+
+/// Emulation speed presets, as factors of the real speed.
+pub const SPEED_PRESETS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
 /// # GUI
 /// The main GUI of Play Kid. Contains a menu bar, the 'About' window,
 /// the FPS counter, and the debug panel.
@@ -32,6 +63,52 @@ pub struct Gui {
     pub show_debugger: bool,
     /// Show FPS.
     show_fps: bool,
+    /// Show rewind window.
+    show_rewind: bool,
+    /// Show link cable window.
+    show_link: bool,
+    /// Port or address in the link cable window.
+    link_address: String,
+    /// Show serial console window.
+    show_serial: bool,
+    /// Text received through the serial port.
+    serial_text: String,
+    /// Pause when the serial output contains this text, if not empty.
+    serial_stop: String,
+    /// Show printer output window.
+    show_paper: bool,
+    /// Paper roll image, waiting to be uploaded to the texture.
+    paper_image: Option<egui::ColorImage>,
+    /// Paper roll texture. `None` if nothing was printed.
+    paper_texture: Option<egui::TextureHandle>,
+    /// Show cheats window.
+    show_cheats: bool,
+    /// Name of the new cheat in the cheats window.
+    cheat_name: String,
+    /// Code of the new cheat in the cheats window.
+    cheat_code: String,
+    /// Error of the last cheat that was added, if any.
+    cheat_error: Option<String>,
+    /// Show RAM search window.
+    show_ram_search: bool,
+    /// The RAM search in progress.
+    ram_search: RamSearch,
+    /// Value size of the next RAM search.
+    ram_search_size: ValueSize,
+    /// Selected filter, as an index of [SEARCH_FILTERS].
+    ram_search_filter: usize,
+    /// Compare against the value instead of the previous snapshot.
+    ram_search_use_value: bool,
+    /// Value to compare against, or to change by.
+    ram_search_value: String,
+    /// Input error in the RAM search value.
+    ram_search_error: bool,
+    /// ROMs of an archive to pick from, while the ROM picker window is open.
+    rom_choices: Vec<RomSource>,
+    /// Information of the current ROM, while the ROM info window is open.
+    rom_info: Option<RomInfo>,
+    /// Current position of the rewind scrubber.
+    rewind_index: usize,
     /// The FPS timer.
     fps_timer: f32,
     /// The current FPS value.
@@ -56,15 +133,49 @@ pub struct Gui {
     toasts: Toasts,
     /// MPSC sender channel for ROM paths.
     pub load_tx: Sender<Option<PathBuf>>,
+    /// MPSC sender channel for movie paths.
+    pub movie_tx: Sender<Option<PathBuf>>,
+    /// Cached save state slots (label and thumbnail), indexed by slot - 1.
+    state_slots: Vec<Option<(String, egui::TextureHandle)>>,
+    /// The save state slots cache must be refreshed.
+    state_slots_dirty: bool,
 }
 
 impl Gui {
     /// Create a `Gui`.
-    pub fn new(show_debugger: bool, show_fps: bool, load_tx: Sender<Option<PathBuf>>) -> Self {
+    pub fn new(
+        show_debugger: bool,
+        show_fps: bool,
+        load_tx: Sender<Option<PathBuf>>,
+        movie_tx: Sender<Option<PathBuf>>,
+    ) -> Self {
         Self {
             show_about: false,
             show_debugger,
             show_fps,
+            show_rewind: false,
+            show_link: false,
+            link_address: DEFAULT_LINK_PORT.to_string(),
+            show_serial: false,
+            serial_text: String::new(),
+            serial_stop: String::new(),
+            show_paper: false,
+            paper_image: None,
+            paper_texture: None,
+            show_cheats: false,
+            cheat_name: String::new(),
+            cheat_code: String::new(),
+            cheat_error: None,
+            show_ram_search: false,
+            ram_search: RamSearch::new(),
+            ram_search_size: ValueSize::Byte,
+            ram_search_filter: 0,
+            ram_search_use_value: false,
+            ram_search_value: String::new(),
+            ram_search_error: false,
+            rom_choices: Vec::new(),
+            rom_info: None,
+            rewind_index: 0,
             fps_timer: 0.0,
             current_fps: 100.0,
             frame_count: 0.0,
@@ -77,6 +188,9 @@ impl Gui {
             last_pc: 0,
             toasts: Toasts::default().with_anchor(Anchor::BottomLeft),
             load_tx,
+            movie_tx,
+            state_slots: Vec::new(),
+            state_slots_dirty: true,
         }
     }
 
@@ -93,6 +207,30 @@ impl Gui {
             .closable(true);
     }
 
+    /// Adds a warning toast with the given text.
+    pub fn add_warning_toast(&mut self, text: &str) {
+        self.toasts
+            .warning(text)
+            .duration(Some(Duration::from_secs(5)))
+            .level(egui_notify::ToastLevel::Warning)
+            .closable(true);
+    }
+
+    /// Adds an error toast with the given text.
+    pub fn add_error_toast(&mut self, text: &str) {
+        self.toasts
+            .error(text)
+            .duration(Some(Duration::from_secs(5)))
+            .level(egui_notify::ToastLevel::Error)
+            .closable(true);
+    }
+
+    /// Marks the save state slots cache as outdated, so that the labels and
+    /// thumbnails are read again from disk.
+    pub fn invalidate_state_slots(&mut self) {
+        self.state_slots_dirty = true;
+    }
+
     /// Toggle state of FPS.
     pub fn toggle_fps(&mut self) {
         self.show_fps = !self.show_fps;
@@ -102,8 +240,72 @@ impl Gui {
         self.show_debugger = show;
     }
 
+    /// Appends bytes received through the serial port to the serial console.
+    /// Returns the stop text, if it shows up in them.
+    pub fn push_serial(&mut self, bytes: &[u8]) -> Option<String> {
+        let start = self.serial_text.len();
+        for &b in bytes {
+            // Keep printable ASCII, so that every byte is a char.
+            if b == b'\n' || (0x20..0x7F).contains(&b) {
+                self.serial_text.push(b as char);
+            }
+        }
+        // Look for the stop text in the new text, including the tail of the
+        // old text that could be the start of a match.
+        let from = start.saturating_sub(self.serial_stop.len());
+        let stop = (!self.serial_stop.is_empty()
+            && self.serial_text[from..].contains(self.serial_stop.as_str()))
+        .then(|| self.serial_stop.clone());
+        if self.serial_text.len() > SERIAL_CONSOLE_MAX {
+            self.serial_text
+                .drain(..self.serial_text.len() - SERIAL_CONSOLE_MAX / 2);
+        }
+        stop
+    }
+
+    /// Clears the serial console.
+    pub fn clear_serial(&mut self) {
+        self.serial_text.clear();
+    }
+
+    /// Updates the paper roll in the printer output window, given its height
+    /// and RGBA bytes, [PRINTER_WIDTH] pixels wide.
+    pub fn set_paper(&mut self, (height, rgba): (usize, Vec<u8>)) {
+        if height == 0 {
+            self.paper_image = None;
+            self.paper_texture = None;
+        } else {
+            self.paper_image = Some(egui::ColorImage::from_rgba_unmultiplied(
+                [PRINTER_WIDTH, height],
+                &rgba,
+            ));
+        }
+    }
+
+    /// Opens the ROM picker window, to choose one of the ROMs in an archive.
+    pub fn pick_rom(&mut self, sources: Vec<RomSource>) {
+        self.rom_choices = sources;
+    }
+
+    /// Refreshes the ROM info window, if open, after a new ROM is loaded.
+    pub fn refresh_rom_info(&mut self, machine: Option<&Machine>) {
+        if self.rom_info.is_some() {
+            self.rom_info = machine.map(|m| m.memory.cart.info());
+        }
+    }
+
+    /// Clears the RAM search, whose candidates belong to the previous ROM.
+    pub fn clear_ram_search(&mut self) {
+        self.ram_search.clear();
+    }
+
+    /// Whether the rewind scrubber is open. Emulation is paused meanwhile.
+    pub fn is_scrubbing(&self) -> bool {
+        self.show_rewind
+    }
+
     /// Create the UI using egui.
-    pub fn ui(&mut self, ctx: &Context, machine: &mut Option<Machine>) {
+    pub fn ui(&mut self, ctx: &Context, machine: &mut Option<Machine>, rewind: &mut Rewind) {
         let mouse_pos = ctx.input(|i| i.pointer.hover_pos());
         let dt = ctx.input(|i| i.stable_dt);
 
@@ -147,7 +349,11 @@ impl Gui {
                                 let ctx = ctx.clone(); // To request a repaint when done
                                 std::thread::spawn(move || {
                                     let task = rfd::AsyncFileDialog::new()
-                                        .add_filter("Game Boy", &["gb", "gbc", "bin"])
+                                        .add_filter(
+                                            "Game Boy",
+                                            &[&ROM_EXTENSIONS[..], &ARCHIVE_EXTENSIONS[..]]
+                                                .concat(),
+                                        )
                                         .pick_file();
 
                                     let result = pollster::block_on(task);
@@ -160,6 +366,70 @@ impl Gui {
                                 ui.close();
                             }
                         });
+                        ui.add_enabled_ui(machine.is_some(), |ui| {
+                            if ui.button("ROM info...").clicked() {
+                                self.rom_info = machine.as_ref().map(|m| m.memory.cart.info());
+                                ui.close();
+                            }
+                        });
+
+                        ui.separator();
+
+                        // Save states.
+                        ui.add_enabled_ui(machine.is_some(), |ui| {
+                            ui.menu_button("Save state", |ui| {
+                                if let Some(m) = machine {
+                                    self.state_slots_menu(ui, m, true);
+                                }
+                            });
+                            ui.add_enabled_ui(!self.ui_state.is_movie_active(), |ui| {
+                                ui.menu_button("Load state", |ui| {
+                                    if let Some(m) = machine {
+                                        self.state_slots_menu(ui, m, false);
+                                    }
+                                });
+                            });
+                            ui.menu_button("Movie", |ui| {
+                                // The rewind scrubber would break the movie.
+                                let idle = !self.ui_state.is_movie_active() && !self.show_rewind;
+                                ui.add_enabled_ui(idle, |ui| {
+                                    if ui.button("Record from power-on").clicked() {
+                                        self.ui_state.record_movie_requested =
+                                            Some(MovieAnchor::PowerOn);
+                                        ui.close();
+                                    }
+                                    if ui.button("Record from current state").clicked() {
+                                        self.ui_state.record_movie_requested =
+                                            Some(MovieAnchor::SaveState);
+                                        ui.close();
+                                    }
+                                    if ui.button("Play movie...").clicked() {
+                                        let tx = self.movie_tx.clone();
+                                        let ctx = ctx.clone();
+                                        std::thread::spawn(move || {
+                                            let task = rfd::AsyncFileDialog::new()
+                                                .add_filter("Play Kid movie", &["pkm"])
+                                                .pick_file();
+                                            let result = pollster::block_on(task);
+                                            let _ = tx.send(result.map(|h| h.path().to_path_buf()));
+                                            ctx.request_repaint();
+                                        });
+                                        ui.close();
+                                    }
+                                });
+                                ui.add_enabled_ui(!idle, |ui| {
+                                    let label = if self.ui_state.is_recording {
+                                        "Stop recording"
+                                    } else {
+                                        "Stop playback"
+                                    };
+                                    if ui.button(label).clicked() {
+                                        self.ui_state.stop_movie_requested = true;
+                                        ui.close();
+                                    }
+                                });
+                            });
+                        });
 
                         ui.separator();
 
@@ -180,10 +450,21 @@ impl Gui {
                         ui.menu_button("Graphics", |ui| {
                             if let Some(m) = machine {
                                 ui.menu_button("Palette", |ui| {
+                                    let auto = m.memory.ppu.is_auto_palette();
+                                    if m.memory.ppu.has_colorization()
+                                        && ui
+                                            .radio(auto, playkid_core::ppu::AUTO_PALETTE_NAME)
+                                            .clicked()
+                                    {
+                                        m.memory.ppu.set_auto_palette();
+                                    }
                                     let current_palette = m.memory.ppu.get_palette_index();
-                                    for (i, name) in crate::ppu::PALETTE_NAMES.iter().enumerate() {
+                                    for (i, name) in
+                                        playkid_core::ppu::PALETTE_NAMES.iter().enumerate()
+                                    {
                                         let i = i as u8;
-                                        if ui.radio(current_palette == i, *name).clicked() {
+                                        if ui.radio(!auto && current_palette == i, *name).clicked()
+                                        {
                                             m.memory.ppu.set_palette(i);
                                         }
                                     }
@@ -197,7 +478,11 @@ impl Gui {
                         });
 
                         ui.menu_button("Machine", |ui| {
-                            if ui.button("Reset CPU").clicked() {
+                            let movie_active = self.ui_state.is_movie_active();
+                            if ui
+                                .add_enabled(!movie_active, egui::Button::new("Reset CPU"))
+                                .clicked()
+                            {
                                 if let Some(m) = machine {
                                     m.reset();
                                 }
@@ -207,6 +492,91 @@ impl Gui {
                                 self.show_debugger = true;
                                 ui.close();
                             }
+                            if ui
+                                .add_enabled(!movie_active, egui::Button::new("Rewind..."))
+                                .clicked()
+                            {
+                                if let Some(m) = machine
+                                    && !self.show_rewind
+                                {
+                                    // Start at the present.
+                                    rewind.push(m.snapshot());
+                                    self.rewind_index = rewind.len() - 1;
+                                    self.show_rewind = true;
+                                }
+                                ui.close();
+                            }
+                            if ui.button("Cheats...").clicked() {
+                                self.show_cheats = true;
+                                ui.close();
+                            }
+                            if ui.button("RAM search...").clicked() {
+                                self.show_ram_search = true;
+                                ui.close();
+                            }
+                            if ui.button("Serial console...").clicked() {
+                                self.show_serial = true;
+                                ui.close();
+                            }
+                            if ui.button("Link cable...").clicked() {
+                                self.show_link = true;
+                                ui.close();
+                            }
+                            let mut printer = self.ui_state.printer_connected;
+                            if ui.checkbox(&mut printer, "Game Boy Printer").clicked() {
+                                self.ui_state.printer_requested = Some(printer);
+                            }
+                            if ui.button("Printer output...").clicked() {
+                                self.show_paper = true;
+                                ui.close();
+                            }
+                            ui.menu_button("Speed", |ui| {
+                                for speed in SPEED_PRESETS {
+                                    let label = format!("{}%", (speed * 100.0) as u32);
+                                    if ui.radio(self.ui_state.speed == speed, label).clicked() {
+                                        self.ui_state.speed = speed;
+                                    }
+                                }
+                                ui.separator();
+                                ui.checkbox(&mut self.ui_state.turbo, "Turbo (uncapped) [T]");
+                                ui.label(
+                                    RichText::new("Hold [Tab] to fast-forward")
+                                        .color(GRAY)
+                                        .small(),
+                                );
+                            });
+                            ui.menu_button("Model", |ui| {
+                                let mut selected = |ui: &mut egui::Ui, model, label| {
+                                    if ui.radio(self.ui_state.model == model, label).clicked() {
+                                        self.ui_state.model = model;
+                                        self.ui_state.model_requested = true;
+                                        ui.close();
+                                    }
+                                };
+                                selected(ui, None, "Auto");
+                                for model in Model::ALL {
+                                    selected(ui, Some(model), model.description());
+                                }
+                                ui.label(
+                                    RichText::new("Changing the model resets the game")
+                                        .color(GRAY)
+                                        .small(),
+                                );
+                            });
+                            ui.menu_button("Renderer", |ui| {
+                                for renderer in Renderer::ALL {
+                                    if ui
+                                        .radio(
+                                            self.ui_state.renderer == renderer,
+                                            renderer.description(),
+                                        )
+                                        .clicked()
+                                    {
+                                        self.ui_state.renderer = renderer;
+                                        ui.close();
+                                    }
+                                }
+                            });
                             ui.checkbox(&mut self.show_fps, "Show FPS");
                         });
                     });
@@ -254,6 +624,58 @@ impl Gui {
                 });
             });
 
+        // Rewind.
+        if self.show_rewind {
+            if let Some(m) = machine {
+                self.draw_rewind_window(ctx, m, rewind);
+            } else {
+                self.show_rewind = false;
+            }
+        }
+
+        // Serial console.
+        if self.show_serial {
+            self.draw_serial_window(ctx);
+        }
+
+        // Link cable.
+        if self.show_link {
+            self.draw_link_window(ctx);
+        }
+
+        // Printer output.
+        if self.show_paper {
+            self.draw_paper_window(ctx);
+        }
+
+        // Cheats.
+        if self.show_cheats {
+            if let Some(m) = machine {
+                self.draw_cheats_window(ctx, m);
+            } else {
+                self.show_cheats = false;
+            }
+        }
+
+        // RAM search.
+        if self.show_ram_search {
+            if let Some(m) = machine {
+                self.draw_ram_search_window(ctx, m);
+            } else {
+                self.show_ram_search = false;
+            }
+        }
+
+        // ROM picker.
+        if !self.rom_choices.is_empty() {
+            self.draw_rom_picker_window(ctx);
+        }
+
+        // ROM info.
+        if self.rom_info.is_some() {
+            self.draw_rom_info_window(ctx);
+        }
+
         // Debugger.
         // Only attempt to draw if the toggle is ON and the machine is SOME
         if self.show_debugger {
@@ -265,6 +687,33 @@ impl Gui {
             }
         }
 
+        // Movie indicator.
+        if self.ui_state.is_recording || self.ui_state.is_playing {
+            let (text, color) = if self.ui_state.is_recording {
+                ("REC", RED)
+            } else {
+                ("PLAY", GREEN)
+            };
+            egui::Area::new(egui::Id::new("movie_indicator"))
+                .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 25.0))
+                .show(ctx, |ui| {
+                    egui::Frame::NONE
+                        .fill(egui::Color32::from_black_alpha(150))
+                        .corner_radius(2.0)
+                        .inner_margin(5.0)
+                        .show(ui, |ui| {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "{} {}",
+                                    text, self.ui_state.movie_frame
+                                ))
+                                .color(color)
+                                .monospace(),
+                            );
+                        });
+                });
+        }
+
         self.frame_count += 1.0;
         if self.show_fps {
             // Update FPS logic so that it only updates every second.
@@ -298,6 +747,561 @@ impl Gui {
         }
     }
 
+    /// Draws the list of save state slots, to save to or load from. Each
+    /// existing slot shows its thumbnail on hover.
+    fn state_slots_menu(&mut self, ui: &mut egui::Ui, machine: &Machine, save: bool) {
+        if self.state_slots_dirty {
+            self.refresh_state_slots(ui.ctx(), machine);
+        }
+        for slot in 1..=STATE_SLOTS {
+            let cached = &self.state_slots[slot as usize - 1];
+            let label = match cached {
+                Some((label, _)) => format!("Slot {} - {}", slot, label),
+                None => format!("Slot {} - empty", slot),
+            };
+            let selected = self.ui_state.state_slot == slot;
+            let response = ui
+                .add_enabled_ui(save || cached.is_some(), |ui| {
+                    ui.add(egui::Button::new(label).selected(selected))
+                })
+                .inner;
+            let response = match cached {
+                Some((_, texture)) => response.on_hover_ui(|ui| {
+                    ui.image((texture.id(), texture.size_vec2()));
+                }),
+                None => response,
+            };
+            if response.clicked() {
+                self.ui_state.state_slot = slot;
+                if save {
+                    self.ui_state.save_state_requested = true;
+                } else {
+                    self.ui_state.load_state_requested = true;
+                }
+                ui.close();
+            }
+        }
+        ui.separator();
+        ui.label(
+            RichText::new("Select slot [1-9], save [F2], load [F4]")
+                .color(GRAY)
+                .small(),
+        );
+    }
+
+    /// Reads the headers of all the save state slots of the current ROM.
+    fn refresh_state_slots(&mut self, ctx: &Context, machine: &Machine) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.state_slots = (1..=STATE_SLOTS)
+            .map(|slot| {
+                let header = machine.state_slot_header(slot)?;
+                let image = egui::ColorImage::from_rgba_unmultiplied(
+                    [constants::DISPLAY_WIDTH, constants::DISPLAY_HEIGHT],
+                    &header.thumbnail,
+                );
+                let texture = ctx.load_texture(
+                    format!("state_slot_{}", slot),
+                    image,
+                    egui::TextureOptions::NEAREST,
+                );
+                Some((format_age(now.saturating_sub(header.timestamp)), texture))
+            })
+            .collect();
+        self.state_slots_dirty = false;
+    }
+
+    /// Draws the serial console window, with the text sent through the
+    /// serial port. Test ROMs and homebrew print their output there.
+    fn draw_serial_window(&mut self, ctx: &Context) {
+        let mut open = self.show_serial;
+        egui::Window::new("Serial console")
+            .open(&mut open)
+            .default_size(vec2(400.0, 300.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Pause on:");
+                    ui.add(TextEdit::singleline(&mut self.serial_stop).desired_width(140.0))
+                        .on_hover_text("Pause when the serial output contains this text");
+                    if ui.button("Clear").clicked() {
+                        self.serial_text.clear();
+                    }
+                });
+                ui.separator();
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        ui.label(
+                            RichText::new(&self.serial_text)
+                                .font(FontId::new(12.0, FontFamily::Monospace)),
+                        );
+                    });
+            });
+        self.show_serial = open;
+    }
+
+    /// Draws the printer output window, with a preview of the paper roll of
+    /// the Game Boy Printer.
+    fn draw_paper_window(&mut self, ctx: &Context) {
+        if let Some(image) = self.paper_image.take() {
+            self.paper_texture =
+                Some(ctx.load_texture("paper_roll", image, egui::TextureOptions::NEAREST));
+        }
+        let mut open = self.show_paper;
+        egui::Window::new("Printer output")
+            .open(&mut open)
+            .default_size(vec2(PRINTER_WIDTH as f32 * 2.0 + 20.0, 400.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let status = if self.ui_state.printer_connected {
+                        "Plugged in"
+                    } else {
+                        "Not plugged in"
+                    };
+                    ui.label(format!("Printer: {}", status));
+                    if ui.button("Clear").clicked() {
+                        self.ui_state.clear_paper_requested = true;
+                    }
+                });
+                ui.separator();
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| match &self.paper_texture {
+                        Some(texture) => {
+                            ui.image((texture.id(), texture.size_vec2() * 2.0));
+                        }
+                        None => {
+                            ui.label(RichText::new("Nothing printed yet").color(GRAY));
+                        }
+                    });
+            });
+        self.show_paper = open;
+    }
+
+    /// Draws the cheats window, to add, name, toggle, and remove the cheats of
+    /// the current ROM.
+    fn draw_cheats_window(&mut self, ctx: &Context, machine: &mut Machine) {
+        let mut open = self.show_cheats;
+        let mut cheats = machine.cheats().clone();
+        let mut changed = false;
+        egui::Window::new("Cheats")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                // The cheats are part of the movie.
+                let movie_active = self.ui_state.is_movie_active();
+                if movie_active {
+                    ui.label(
+                        RichText::new("Stop the movie to change the cheats")
+                            .color(ORANGE)
+                            .small(),
+                    );
+                }
+                ui.add_enabled_ui(!movie_active, |ui| {
+                    if cheats.is_empty() {
+                        ui.label(RichText::new("No cheats yet").color(GRAY));
+                    }
+                    let mut toggled = None;
+                    let mut remove = None;
+                    egui::Grid::new("cheats_grid")
+                        .num_columns(3)
+                        .spacing([10.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (i, cheat) in cheats.cheats().iter().enumerate() {
+                                let mut enabled = cheat.enabled;
+                                let name = if cheat.name.is_empty() {
+                                    "Unnamed"
+                                } else {
+                                    cheat.name.as_str()
+                                };
+                                if ui.checkbox(&mut enabled, name).changed() {
+                                    toggled = Some((i, enabled));
+                                }
+                                ui.monospace(&cheat.code);
+                                if ui.small_button("×").on_hover_text("Remove").clicked() {
+                                    remove = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    if let Some((i, enabled)) = toggled {
+                        cheats.set_enabled(i, enabled);
+                        changed = true;
+                    }
+                    if let Some(i) = remove {
+                        cheats.remove(i);
+                        changed = true;
+                    }
+                    ui.separator();
+                    egui::Grid::new("new_cheat_grid")
+                        .num_columns(2)
+                        .spacing([10.0, 4.0])
+                        .show(ui, |ui| {
+                            ui.label("Name:");
+                            ui.text_edit_singleline(&mut self.cheat_name);
+                            ui.end_row();
+                            ui.label("Code:");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.cheat_code)
+                                    .font(egui::TextStyle::Monospace)
+                                    .hint_text("ABC-DEF-GHI, 01VVAAAA, or C0A5=FF"),
+                            );
+                            ui.end_row();
+                        });
+                    ui.horizontal(|ui| {
+                        if ui.button("Add").clicked() {
+                            match cheats.add(&self.cheat_name, &self.cheat_code) {
+                                Ok(()) => {
+                                    self.cheat_name.clear();
+                                    self.cheat_code.clear();
+                                    self.cheat_error = None;
+                                    changed = true;
+                                }
+                                Err(e) => self.cheat_error = Some(e.to_string()),
+                            }
+                        }
+                        ui.label(
+                            RichText::new("Join several codes with +")
+                                .color(GRAY)
+                                .small(),
+                        );
+                    });
+                    if let Some(error) = &self.cheat_error {
+                        ui.label(RichText::new(error).color(RED));
+                    }
+                });
+            });
+        if changed {
+            machine.set_cheats(cheats);
+            self.ui_state.cheats_changed = true;
+        }
+        self.show_cheats = open;
+    }
+
+    /// Draws the RAM search window, to narrow down the addresses of a game
+    /// variable with successive snapshots and comparisons.
+    fn draw_ram_search_window(&mut self, ctx: &Context, machine: &mut Machine) {
+        let mut open = self.show_ram_search;
+        let mut freeze = None;
+        egui::Window::new("RAM search")
+            .open(&mut open)
+            .default_width(380.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Size:");
+                    ui.radio_value(&mut self.ram_search_size, ValueSize::Byte, "8-bit");
+                    ui.radio_value(&mut self.ram_search_size, ValueSize::Word, "16-bit LE");
+                    if ui
+                        .button("New search")
+                        .on_hover_text("Snapshot WRAM, HRAM, and cartridge RAM")
+                        .clicked()
+                    {
+                        self.ram_search.start(&machine.memory, self.ram_search_size);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("ram_search_filter")
+                        .selected_text(SEARCH_FILTERS[self.ram_search_filter])
+                        .show_ui(ui, |ui| {
+                            for (i, name) in SEARCH_FILTERS.iter().enumerate() {
+                                ui.selectable_value(&mut self.ram_search_filter, i, *name);
+                            }
+                        });
+                    // The first four compare against the previous snapshot
+                    // or a value, and "Changed by" always takes a value.
+                    let compares = self.ram_search_filter < 4;
+                    if compares {
+                        ui.radio_value(&mut self.ram_search_use_value, false, "previous");
+                        ui.radio_value(&mut self.ram_search_use_value, true, "value");
+                    }
+                    if (compares && self.ram_search_use_value) || self.ram_search_filter == 6 {
+                        if self.ram_search_error {
+                            ui.visuals_mut().override_text_color = Some(RED);
+                        }
+                        let input = TextEdit::singleline(&mut self.ram_search_value)
+                            .hint_text("10, $0A")
+                            .font(egui::TextStyle::Monospace)
+                            .desired_width(60.0);
+                        if ui.add(input).changed() {
+                            self.ram_search_error = false;
+                        }
+                        ui.visuals_mut().override_text_color = None;
+                    }
+                    let searching = !self.ram_search.candidates().is_empty();
+                    if ui
+                        .add_enabled(searching, egui::Button::new("Search"))
+                        .clicked()
+                    {
+                        match self.search_filter() {
+                            Some(filter) => self.ram_search.filter(&machine.memory, filter),
+                            None => self.ram_search_error = true,
+                        }
+                    }
+                });
+
+                let candidates = self.ram_search.candidates();
+                ui.label(RichText::new(format!("{} candidates", candidates.len())).color(GRAY));
+                let size = self.ram_search.size();
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + 4.0;
+                ScrollArea::vertical()
+                    .max_height(300.0)
+                    .auto_shrink([false, true])
+                    .show_rows(ui, row_height, candidates.len(), |ui, rows| {
+                        for &address in &candidates[rows] {
+                            let value = self.ram_search.value(&machine.memory, address);
+                            let previous = self.ram_search.previous(address);
+                            ui.horizontal(|ui| {
+                                ui.label(
+                                    RichText::new(format!("${:04x}", address))
+                                        .color(GRAY)
+                                        .monospace(),
+                                );
+                                ui.label(
+                                    RichText::new(format_value(size, value))
+                                        .color(if value != previous { ORANGE } else { WHITE })
+                                        .monospace(),
+                                )
+                                .on_hover_text(format!(
+                                    "Previous: {}",
+                                    format_value(size, previous)
+                                ));
+                                if ui
+                                    .small_button("👁 Watch")
+                                    .on_hover_text("Add a watch to the debug panel")
+                                    .clicked()
+                                {
+                                    machine.debug.add_watch(Watch { address, size });
+                                }
+                                if ui
+                                    .add_enabled(
+                                        !self.ui_state.is_movie_active(),
+                                        egui::Button::new("❄ Freeze").small(),
+                                    )
+                                    .on_hover_text("Add a cheat that keeps the current value")
+                                    .clicked()
+                                {
+                                    freeze = Some((address, value));
+                                }
+                            });
+                        }
+                    });
+            });
+        if let Some((address, value)) = freeze {
+            let [low, high] = value.to_le_bytes();
+            let code = match self.ram_search.size() {
+                ValueSize::Byte => CheatCode::freeze_code(address, low),
+                ValueSize::Word => format!(
+                    "{}+{}",
+                    CheatCode::freeze_code(address, low),
+                    CheatCode::freeze_code(address + 1, high)
+                ),
+            };
+            let mut cheats = machine.cheats().clone();
+            match cheats.add(&format!("RAM ${:04X}", address), &code) {
+                Ok(()) => {
+                    machine.set_cheats(cheats);
+                    self.ui_state.cheats_changed = true;
+                    self.add_info_toast(&format!("Cheat added: {}", code));
+                }
+                Err(e) => self.add_error_toast(&format!("Failed to add cheat: {}", e)),
+            }
+        }
+        self.show_ram_search = open;
+    }
+
+    /// Builds the selected RAM search filter, if its value is valid.
+    fn search_filter(&self) -> Option<Filter> {
+        let max = match self.ram_search.size() {
+            ValueSize::Byte => u8::MAX as i32,
+            ValueSize::Word => u16::MAX as i32,
+        };
+        let value = || parse_number(&self.ram_search_value);
+        let operand = || {
+            if self.ram_search_use_value {
+                value()
+                    .filter(|v| (0..=max).contains(v))
+                    .map(|v| Operand::Value(v as u16))
+            } else {
+                Some(Operand::Previous)
+            }
+        };
+        match self.ram_search_filter {
+            0 => operand().map(Filter::Equal),
+            1 => operand().map(Filter::NotEqual),
+            2 => operand().map(Filter::Greater),
+            3 => operand().map(Filter::Less),
+            4 => Some(Filter::Changed),
+            5 => Some(Filter::Unchanged),
+            _ => value()
+                .filter(|v| (-max..=max).contains(v))
+                .map(Filter::ChangedBy),
+        }
+    }
+
+    /// Draws the ROM picker window, with the ROMs of an archive to choose
+    /// from.
+    fn draw_rom_picker_window(&mut self, ctx: &Context) {
+        let mut open = true;
+        let mut picked = None;
+        egui::Window::new("Select ROM")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if let Some(source) = self.rom_choices.first() {
+                    ui.label(format!(
+                        "The archive {} contains several ROMs:",
+                        source.path.display()
+                    ));
+                }
+                ui.separator();
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for source in &self.rom_choices {
+                        let name = source.entry.as_deref().unwrap_or_default();
+                        if ui.button(name).clicked() {
+                            picked = Some(source.clone());
+                        }
+                    }
+                });
+            });
+        if picked.is_some() || !open {
+            self.ui_state.rom_source_requested = picked;
+            self.rom_choices.clear();
+        }
+    }
+
+    /// Draws the ROM info window, with the header, checksums, and file sizes
+    /// of the current ROM.
+    fn draw_rom_info_window(&mut self, ctx: &Context) {
+        let Some(info) = &self.rom_info else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("ROM info")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("rom_info_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (label, value) in info.rows() {
+                            ui.label(RichText::new(label).color(GRAY));
+                            let bad = value.ends_with("(MISMATCH)")
+                                || value == "MISMATCH"
+                                || (label == "Mapper" && info.mapper.is_none());
+                            if bad {
+                                ui.monospace(RichText::new(value).color(RED));
+                            } else {
+                                ui.monospace(value);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        if !open {
+            self.rom_info = None;
+        }
+    }
+
+    /// Draws the link cable window, to host or join a link cable connection
+    /// with another instance.
+    fn draw_link_window(&mut self, ctx: &Context) {
+        let mut open = self.show_link;
+        egui::Window::new("Link cable")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let status = self.ui_state.link_status.as_deref();
+                ui.label(format!("Status: {}", status.unwrap_or("Not connected")));
+                ui.add_enabled_ui(status.is_none(), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Port or address:");
+                        ui.add(TextEdit::singleline(&mut self.link_address).desired_width(140.0));
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Host").clicked() {
+                            self.ui_state.link_requested =
+                                Some(LinkRequest::Host(self.link_address.clone()));
+                        }
+                        if ui.button("Connect").clicked() {
+                            self.ui_state.link_requested =
+                                Some(LinkRequest::Connect(self.link_address.clone()));
+                        }
+                    });
+                });
+                if status.is_some() && ui.button("Disconnect").clicked() {
+                    self.ui_state.link_requested = Some(LinkRequest::Disconnect);
+                }
+                ui.label(
+                    RichText::new("One instance hosts, and the other connects to it")
+                        .color(GRAY)
+                        .small(),
+                );
+            });
+        self.show_link = open;
+    }
+
+    /// Draws the rewind window, with a scrubber over the snapshots in the
+    /// rewind buffer. Moving the scrubber restores the machine to that point.
+    /// Emulation resumes from the selected point when the window is closed.
+    fn draw_rewind_window(&mut self, ctx: &Context, machine: &mut Machine, rewind: &mut Rewind) {
+        let mut open = true;
+        let mut resume = false;
+        let len = rewind.len();
+        let interval = rewind.interval() as f64;
+        egui::Window::new("Rewind")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if len == 0 {
+                    ui.label("The rewind buffer is empty.");
+                    return;
+                }
+                let seconds_ago = |index: f64| {
+                    (len as f64 - 1.0 - index) * interval / constants::TARGET_FPS as f64
+                };
+                ui.label(format!(
+                    "{} snapshots, {:.1} s, {:.1} MiB",
+                    len,
+                    seconds_ago(0.0),
+                    rewind.memory_usage() as f32 / (1024.0 * 1024.0)
+                ));
+                ui.spacing_mut().slider_width = 300.0;
+                let slider = egui::Slider::new(&mut self.rewind_index, 0..=len - 1)
+                    .custom_formatter(|i, _| format!("-{:.1} s", seconds_ago(i)))
+                    .show_value(true);
+                if ui.add(slider).changed()
+                    && let Some(snapshot) = rewind.get(self.rewind_index)
+                    && let Err(e) = machine.restore_snapshot(&snapshot)
+                {
+                    self.add_error_toast(&format!("Failed to rewind: {}", e));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Resume here").clicked() {
+                        resume = true;
+                    }
+                });
+                ui.label(
+                    RichText::new("Newer snapshots are discarded on resume")
+                        .color(GRAY)
+                        .small(),
+                );
+            });
+        if !open || resume {
+            rewind.truncate(self.rewind_index);
+            self.show_rewind = false;
+        }
+    }
+
     fn draw_debugger_window(&mut self, ctx: &Context, machine: &mut Machine) {
         egui::SidePanel::right("🐛 Debug Panel")
             .default_width(300.0)
@@ -308,113 +1312,86 @@ impl Gui {
             )
             .show(ctx, |ui| {
                 let pc = machine.registers.pc;
-                let opcode = machine.memory.read8(pc);
+                let opcode = machine.memory.peek8(pc);
                 ui.vertical(|ui| {
                     // Control Buttons.
+                    let paused = machine.debug.is_paused();
                     ui.horizontal_top(|ui| {
-                        let button_size = egui::vec2(120.0, 0.0);
-
                         // Step instruction.
-                        let mut stepi_label = LayoutJob::default();
-                        RichText::new("⤴ Step Instr")
-                            .color(BLUE)
-                            .font(FontId::new(14.0, FontFamily::Proportional))
-                            .strong()
-                            .append_to(
-                                &mut stepi_label,
-                                ui.style(),
-                                egui::FontSelection::Default,
-                                egui::Align::Center,
-                            );
-                        RichText::new("   [F6]")
-                            .color(GRAY)
-                            .font(FontId::new(10.0, FontFamily::Proportional))
-                            .weak()
-                            .append_to(
-                                &mut stepi_label,
-                                ui.style(),
-                                egui::FontSelection::Default,
-                                egui::Align::Center,
-                            );
-                        if ui
-                            .add_enabled_ui(machine.debug.is_paused(), |ui| {
-                                ui.add_sized(button_size, egui::Button::new(stepi_label))
-                                    .on_hover_text("Step one instruction. [F6]")
-                            })
-                            .inner
+                        if control_button(ui, "⤴ Step Instr", BLUE, "F6", paused)
+                            .on_hover_text("Step one instruction. [F6]")
                             .clicked()
                         {
                             machine.debug.request_step_instruction();
                         }
 
                         // Step scanline.
-                        let mut steps_label = LayoutJob::default();
-                        RichText::new("⮫ Step Line")
-                            .color(BLUE)
-                            .font(FontId::new(14.0, FontFamily::Proportional))
-                            .strong()
-                            .append_to(
-                                &mut steps_label,
-                                ui.style(),
-                                egui::FontSelection::Default,
-                                egui::Align::Center,
-                            );
-                        RichText::new("   [F7]")
-                            .color(GRAY)
-                            .font(FontId::new(10.0, FontFamily::Proportional))
-                            .weak()
-                            .append_to(
-                                &mut steps_label,
-                                ui.style(),
-                                egui::FontSelection::Default,
-                                egui::Align::Center,
-                            );
-                        if ui
-                            .add_enabled_ui(machine.debug.is_paused(), |ui| {
-                                ui.add_sized(button_size, egui::Button::new(steps_label))
-                                    .on_hover_text("Step a scanline. [F7]")
-                            })
-                            .inner
+                        if control_button(ui, "⮫ Step Line", BLUE, "F7", paused)
+                            .on_hover_text("Step a scanline. [F7]")
                             .clicked()
                         {
                             machine.debug.request_step_scanline();
                         }
 
                         // Continue/Pause
-                        let pause_text = if machine.debug.is_paused() {
-                            "▶ Continue"
-                        } else {
-                            "⏸ Pause"
-                        };
-                        let mut pause_label = LayoutJob::default();
-                        RichText::new(pause_text)
-                            .color(GREEN)
-                            .font(FontId::new(14.0, FontFamily::Proportional))
-                            .strong()
-                            .append_to(
-                                &mut pause_label,
-                                ui.style(),
-                                egui::FontSelection::Default,
-                                egui::Align::Center,
-                            );
-                        RichText::new("   [F9]")
-                            .color(GRAY)
-                            .font(FontId::new(10.0, FontFamily::Proportional))
-                            .weak()
-                            .append_to(
-                                &mut pause_label,
-                                ui.style(),
-                                egui::FontSelection::Default,
-                                egui::Align::Center,
-                            );
-                        if ui
-                            .add_sized(button_size, egui::Button::new(pause_label))
+                        let pause_text = if paused { "▶ Continue" } else { "⏸ Pause" };
+                        if control_button(ui, pause_text, GREEN, "F9", true)
                             .on_hover_text("Continue/pause. [F9]")
                             .clicked()
                         {
                             machine.debug.toggle_paused();
                         }
                     });
+                    ui.horizontal_top(|ui| {
+                        // Step over.
+                        if control_button(ui, "↷ Step Over", BLUE, "F8", paused)
+                            .on_hover_text("Step one instruction, running calls to their return. [F8]")
+                            .clicked()
+                        {
+                            machine.debug.request_run(RunCommand::StepOver);
+                        }
+
+                        // Step out.
+                        if control_button(ui, "⤶ Step Out", BLUE, "F10", paused)
+                            .on_hover_text("Run until the current function returns. [F10]")
+                            .clicked()
+                        {
+                            machine.debug.request_run(RunCommand::StepOut);
+                        }
+
+                        // Step frame.
+                        if control_button(ui, "⏭ Step Frame", BLUE, "F11", paused)
+                            .on_hover_text("Run until the next VBlank. [F11]")
+                            .clicked()
+                        {
+                            machine.debug.request_run(RunCommand::StepFrame);
+                        }
+                    });
+                    ui.horizontal_top(|ui| {
+                        // Run to cursor.
+                        let cursor = machine.debug.get_cursor();
+                        if control_button(
+                            ui,
+                            "⇥ Run to Cursor",
+                            BLUE,
+                            "F12",
+                            paused && cursor.is_some(),
+                        )
+                        .on_hover_text(
+                            "Run until the address selected in the disassembly (right-click). [F12]",
+                        )
+                        .clicked()
+                        {
+                            machine.debug.request_run_to_cursor();
+                        }
+                        if let Some(addr) = cursor {
+                            ui.label(
+                                RichText::new(format!("Cursor: ${:04x}", addr))
+                                    .color(GRAY)
+                                    .monospace(),
+                            );
+                        }
+                    });
 
                     ui.add_space(8.0);
 
@@ -825,6 +1802,49 @@ impl Gui {
                                         );
                                     });
                                 });
+
+                            ui.add_space(8.0);
+
+                            // WATCHES.
+                            CollapsingHeader::new("👁 Watches")
+                                .default_open(true)
+                                .show(ui, |ui| {
+                                    let watches = machine.debug.get_watches().to_vec();
+                                    if watches.is_empty() {
+                                        ui.label(
+                                            RichText::new("-empty- (add from RAM search)")
+                                                .color(BLUE)
+                                                .monospace(),
+                                        );
+                                    }
+                                    for watch in watches {
+                                        let value = match watch.size {
+                                            ValueSize::Byte => {
+                                                machine.memory.peek8(watch.address) as u16
+                                            }
+                                            ValueSize::Word => machine.memory.peek16(watch.address),
+                                        };
+                                        ui.horizontal(|ui| {
+                                            ui.label(
+                                                RichText::new(format!("${:04x}", watch.address))
+                                                    .color(GRAY)
+                                                    .monospace(),
+                                            );
+                                            ui.label(
+                                                RichText::new(format_value(watch.size, value))
+                                                    .color(CYAN)
+                                                    .monospace(),
+                                            );
+                                            if ui
+                                                .small_button("❌")
+                                                .on_hover_text("Remove")
+                                                .clicked()
+                                            {
+                                                machine.debug.delete_watch(watch);
+                                            }
+                                        });
+                                    }
+                                });
                         });
                         // RIGHT: Disassembly.
                         columns[1].vertical(|ui| {
@@ -870,8 +1890,10 @@ impl Gui {
                                         |ui, row_range| {
                                             for addr in row_range {
                                                 let is_current_pc = addr == current_pc as usize;
+                                                let is_cursor =
+                                                    machine.debug.get_cursor() == Some(addr as u16);
 
-                                                let opcode = machine.memory.read8(addr as u16);
+                                                let opcode = machine.memory.peek8(addr as u16);
                                                 let i = RunInstr::new(
                                                     opcode,
                                                     &machine.memory,
@@ -915,6 +1937,17 @@ impl Gui {
                                                 if response.clicked() {
                                                     machine.debug.toggle_breakpoint(addr as u16);
                                                 }
+                                                if response.secondary_clicked() {
+                                                    machine.debug.set_cursor(Some(addr as u16));
+                                                }
+                                                if is_cursor && !is_current_pc {
+                                                    ui.painter().rect_stroke(
+                                                        rect,
+                                                        0.0,
+                                                        egui::Stroke::new(1.0, BLUE),
+                                                        egui::StrokeKind::Inside,
+                                                    );
+                                                }
                                                 if is_current_pc {
                                                     ui.painter().rect_filled(rect, 0.0, YELLOW);
 
@@ -940,3 +1973,71 @@ impl Gui {
             });
     }
 }
+
+/// Parses a decimal number, or a hex one with a `$` or `0x` prefix.
+fn parse_number(text: &str) -> Option<i32> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = match text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        Some(hex) => i32::from_str_radix(hex, 16).ok()?,
+        None => text.parse::<i32>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Formats a RAM value in hex and decimal.
+fn format_value(size: ValueSize, value: u16) -> String {
+    match size {
+        ValueSize::Byte => format!("${:02x} {:>3}", value, value),
+        ValueSize::Word => format!("${:04x} {:>5}", value, value),
+    }
+}
+
+/// Adds a debugger control button, with the label in the given color
+/// followed by the hotkey hint.
+fn control_button(
+    ui: &mut egui::Ui,
+    text: &str,
+    color: Color32,
+    key: &str,
+    enabled: bool,
+) -> egui::Response {
+    let mut label = LayoutJob::default();
+    RichText::new(text)
+        .color(color)
+        .font(FontId::new(14.0, FontFamily::Proportional))
+        .strong()
+        .append_to(
+            &mut label,
+            ui.style(),
+            egui::FontSelection::Default,
+            egui::Align::Center,
+        );
+    RichText::new(format!("   [{}]", key))
+        .color(GRAY)
+        .font(FontId::new(10.0, FontFamily::Proportional))
+        .weak()
+        .append_to(
+            &mut label,
+            ui.style(),
+            egui::FontSelection::Default,
+            egui::Align::Center,
+        );
+    ui.add_enabled_ui(enabled, |ui| {
+        ui.add_sized(egui::vec2(120.0, 0.0), egui::Button::new(label))
+    })
+    .inner
+}
+
+/// Formats an age in seconds as a short human-readable string.
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
@@ -0,0 +1,205 @@
+use crate::archive;
+use crate::cli::{Args, RunArgs};
+use crate::link::open_link;
+use crate::paper::PaperRoll;
+use crate::playkid::save_png;
+
+use colored::Colorize;
+use playkid_core::cartridge::Cartridge;
+use playkid_core::machine::Machine;
+use playkid_core::movie::{Movie, MoviePlayer};
+use playkid_core::printer::Printer;
+use playkid_core::serial::Link;
+use std::io::Write;
+
+/// Exit status when the run finished successfully.
+pub const EXIT_OK: i32 = 0;
+/// Exit status when the stop condition was not met, or the frame hash did not match.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status when the ROM could not be loaded, or the output could not be written.
+pub const EXIT_ERROR: i32 = 2;
+
+/// Runs the ROM given in `args` without a window or an audio device, and
+/// returns the process exit status.
+pub fn run(args: &RunArgs, global: &Args) -> i32 {
+    let source = match archive::select(&args.input, global.entry.as_deref()) {
+        Ok(source) => source.with_patch(global.patch.as_deref()),
+        Err(e) => {
+            eprintln!("{}: Failed to read ROM: {}", "ERR".red(), e);
+            return EXIT_ERROR;
+        }
+    };
+    let data = match source.read() {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{}: Failed to read ROM: {}", "ERR".red(), e);
+            return EXIT_ERROR;
+        }
+    };
+    if let Some(patch) = &source.patch {
+        println!("{}: Patch applied: {}", "OK".green(), patch.display());
+    }
+    let mut machine = match Cartridge::from_bytes(data, global.skipcheck) {
+        Ok(cart) => match global.model {
+            Some(model) => Machine::with_model(cart, model, false),
+            None => Machine::new(cart, false),
+        },
+        Err(e) => {
+            eprintln!("{}: Failed to load ROM: {}", "ERR".red(), e);
+            return EXIT_ERROR;
+        }
+    };
+    machine.memory.ppu.set_renderer(global.renderer);
+    if let Some(path) = &global.boot_rom
+        && let Err(e) = std::fs::read(path).and_then(|data| machine.set_boot_rom(data))
+    {
+        eprintln!("{}: Failed to load boot ROM: {}", "ERR".red(), e);
+        return EXIT_ERROR;
+    }
+
+    // Game Boy Printer. It takes the place of the link cable.
+    let printer = global.printer.then(Printer::new);
+    let mut paper = PaperRoll::new(&global.printer_dir);
+    if let Some(printer) = &printer {
+        machine.set_link(Some(Box::new(printer.clone())));
+    }
+
+    // Link cable.
+    if printer.is_none()
+        && let Some(link) = open_link(global)
+    {
+        match link {
+            Ok(link) => {
+                if !link.is_connected() {
+                    println!("{}: Link cable: {}", "OK".green(), link.status());
+                    link.wait_connected();
+                }
+                machine.set_link(Some(Box::new(link)));
+            }
+            Err(e) => {
+                eprintln!("{}: Failed to open link cable: {}", "ERR".red(), e);
+                return EXIT_ERROR;
+            }
+        }
+    }
+
+    // Movie playback.
+    let mut player = None;
+    if let Some(path) = &args.movie {
+        match Movie::load(path).and_then(|movie| MoviePlayer::new(movie, &mut machine)) {
+            Ok(p) => {
+                println!(
+                    "{}: Playing movie: {} ({} frames)",
+                    "OK".green(),
+                    path.display(),
+                    p.movie().frames()
+                );
+                player = Some(p);
+            }
+            Err(e) => {
+                eprintln!("{}: Failed to load movie: {}", "ERR".red(), e);
+                return EXIT_ERROR;
+            }
+        }
+    }
+    let max_frames = match &player {
+        Some(p) => p.movie().frames() as u64,
+        None => args.frames,
+    };
+
+    let has_condition = args.until_pc.is_some() || args.until_serial.is_some();
+    let mut serial = Vec::new();
+    let mut condition_met = false;
+    let mut frames = 0;
+
+    while frames < max_frames && !condition_met {
+        if let Some(p) = &mut player {
+            p.begin_frame(&mut machine);
+        }
+        condition_met = machine.run_frame_until(|m| Some(m.registers.pc) == args.until_pc);
+        frames += 1;
+        if let Some(p) = &mut player
+            && let Some(frame) = p.end_frame(&machine)
+        {
+            eprintln!("{}: Movie desynced at frame {}", "ERR".red(), frame);
+        }
+
+        for strip in printer.iter().flat_map(|p| p.take_printed()) {
+            match paper.print(strip) {
+                Ok(path) => println!("{}: Printed: {}", "OK".green(), path.display()),
+                Err(e) => eprintln!("{}: Failed to save print: {}", "ERR".red(), e),
+            }
+        }
+
+        let output = machine.take_serial_output();
+        if !output.is_empty() && printer.is_none() {
+            // Echo the serial output. Printer packets are not text.
+            print!("{}", String::from_utf8_lossy(&output));
+            let _ = std::io::stdout().flush();
+        }
+        serial.extend(output);
+        if let Some(text) = &args.until_serial
+            && String::from_utf8_lossy(&serial).contains(text.as_str())
+        {
+            condition_met = true;
+        }
+    }
+
+    if serial.last().is_some_and(|b| *b != b'\n') {
+        println!();
+    }
+    if has_condition {
+        if condition_met {
+            println!(
+                "{}: Stop condition met after {} frames",
+                "OK".green(),
+                frames
+            );
+        } else {
+            eprintln!(
+                "{}: Stop condition not met after {} frames",
+                "ERR".red(),
+                frames
+            );
+        }
+    } else {
+        println!("{}: Ran {} frames", "OK".green(), frames);
+    }
+
+    // Final frame.
+    if let Some(path) = &args.output {
+        let (width, height, frame) = machine.display();
+        if let Err(e) = save_png(path, width, height, frame) {
+            eprintln!("{}: Failed to write frame: {}", "ERR".red(), e);
+            return EXIT_ERROR;
+        }
+        println!("{}: Frame written: {}", "OK".green(), path.display());
+    }
+
+    let hash = machine.frame_hash();
+    println!("{}: Frame hash: {:016x}", "OK".green(), hash);
+
+    if let Some(expected) = args.expect_hash
+        && expected != hash
+    {
+        eprintln!(
+            "{}: Frame hash mismatch, expected {:016x}",
+            "ERR".red(),
+            expected
+        );
+        return EXIT_FAILURE;
+    }
+
+    if let Some(p) = &player {
+        if p.desync().is_some() {
+            return EXIT_FAILURE;
+        }
+        println!("{}: Movie played in sync", "OK".green());
+    }
+
+    if has_condition && !condition_met {
+        EXIT_FAILURE
+    } else {
+        EXIT_OK
+    }
+}
@@ -0,0 +1,39 @@
+use crate::archive;
+use crate::cli::{Args, InfoArgs};
+use crate::headless::{EXIT_ERROR, EXIT_OK};
+use colored::Colorize;
+use playkid_core::cartridge::{CartridgeError, RomInfo};
+
+/// Prints the information of the ROM file in the arguments, as text or as
+/// JSON. Returns the exit code.
+pub fn run(args: &InfoArgs, global: &Args) -> i32 {
+    // ROMs in archives are described as if they were extracted next to the
+    // archive, which is where their save files go.
+    let info = archive::select(&args.input, global.entry.as_deref())
+        .map(|source| source.with_patch(global.patch.as_deref()))
+        .and_then(|source| Ok((source.read()?, source.rom_path())))
+        .map_err(CartridgeError::from)
+        .and_then(|(data, path)| RomInfo::new(&data, Some(&path)));
+    let info = match info {
+        Ok(info) => info,
+        Err(e) => {
+            eprintln!(
+                "{}: Could not read ROM file {}: {}",
+                "ERR".red(),
+                args.input.display(),
+                e
+            );
+            return EXIT_ERROR;
+        }
+    };
+    if args.json {
+        println!("{}", info.to_json());
+    } else {
+        let rows = info.rows();
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        for (label, value) in rows {
+            println!("{}  {}", format!("{:width$}", label).bold(), value);
+        }
+    }
+    EXIT_OK
+}
@@ -0,0 +1,231 @@
+use crate::cli::Args;
+use colored::Colorize;
+use playkid_core::serial::{Link, Reply};
+use std::io::{Read, Result, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Message with the byte of a transfer clocked by the sender.
+const MSG_TRANSFER: u8 = 0;
+/// Message with the byte answering a transfer.
+const MSG_REPLY: u8 = 1;
+/// How long to wait for the other end to answer a transfer. Past that, the
+/// transfer shifts in 0xFF, like with no cable.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long to wait when connecting to the other end.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// # TCP link
+/// Link cable between two Play Kid instances, over a TCP socket. Every
+/// message is two bytes: the kind ([MSG_TRANSFER] or [MSG_REPLY]), and the
+/// data. A reader thread queues the incoming messages, so that the
+/// emulation never blocks. A transfer it clocks holds its serial clock
+/// until the answer of the other end arrives, while the rest of the machine
+/// keeps running. The wait is measured in wall-clock time, up to
+/// [REPLY_TIMEOUT], so the two instances are not kept in emulated-time sync.
+///
+/// It is cheap to clone, and all clones share the same connection.
+#[derive(Clone)]
+pub struct TcpLink {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// Local address when hosting, or remote address when connecting.
+    address: SocketAddr,
+    /// Write half of the connection, once connected.
+    stream: Mutex<Option<TcpStream>>,
+    /// Incoming messages.
+    rx: Mutex<Receiver<[u8; 2]>>,
+    /// When the last transfer clocked by this end was sent, until answered.
+    sent_at: Mutex<Option<Instant>>,
+    /// The other end is connected.
+    connected: AtomicBool,
+    /// The link was closed by the user.
+    closed: AtomicBool,
+}
+
+impl TcpLink {
+    /// Listens for a connection from another instance at the given address.
+    /// The connection is accepted in the background.
+    pub fn host(address: &str) -> Result<Self> {
+        let listener = TcpListener::bind(resolve(address)?)?;
+        listener.set_nonblocking(true)?;
+        let (tx, link) = Self::create(listener.local_addr()?);
+        let inner = link.inner.clone();
+        std::thread::spawn(move || {
+            while !inner.closed.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        println!("{}: Link cable connected: {}", "OK".green(), peer);
+                        inner.start(stream, tx);
+                        return;
+                    }
+                    Err(_) => std::thread::sleep(Duration::from_millis(50)),
+                }
+            }
+        });
+        Ok(link)
+    }
+
+    /// Connects to another instance listening at the given address.
+    pub fn connect(address: &str) -> Result<Self> {
+        let address = resolve(address)?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        println!("{}: Link cable connected: {}", "OK".green(), address);
+        let (tx, link) = Self::create(address);
+        link.inner.start(stream, tx);
+        Ok(link)
+    }
+
+    fn create(address: SocketAddr) -> (Sender<[u8; 2]>, Self) {
+        let (tx, rx) = channel();
+        let link = Self {
+            inner: Arc::new(Inner {
+                address,
+                stream: Mutex::new(None),
+                rx: Mutex::new(rx),
+                sent_at: Mutex::new(None),
+                connected: AtomicBool::new(false),
+                closed: AtomicBool::new(false),
+            }),
+        };
+        (tx, link)
+    }
+
+    /// Blocks until the other end connects, or the link is closed.
+    pub fn wait_connected(&self) {
+        while !self.is_connected() && !self.inner.closed.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Closes the connection, or stops listening.
+    pub fn close(&self) {
+        self.inner.closed.store(true, Ordering::Relaxed);
+        self.inner.connected.store(false, Ordering::Relaxed);
+        if let Some(stream) = self.inner.stream.lock().unwrap().take() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    /// Describes the state of the link, for the user.
+    pub fn status(&self) -> String {
+        if self.is_connected() {
+            "Connected".to_string()
+        } else if self.inner.stream.lock().unwrap().is_some()
+            || self.inner.closed.load(Ordering::Relaxed)
+        {
+            "Disconnected".to_string()
+        } else {
+            format!("Waiting for connection on {}", self.inner.address)
+        }
+    }
+
+    /// Sends a message to the other end.
+    fn send_message(&self, kind: u8, data: u8) -> bool {
+        let mut stream = self.inner.stream.lock().unwrap();
+        match stream.as_mut().map(|s| s.write_all(&[kind, data])) {
+            Some(Ok(())) => true,
+            _ => {
+                self.inner.connected.store(false, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+}
+
+impl Inner {
+    /// Starts using the given connection, with a reader thread that queues
+    /// the incoming messages.
+    fn start(self: &Arc<Self>, stream: TcpStream, tx: Sender<[u8; 2]>) {
+        let _ = stream.set_nodelay(true);
+        let Ok(mut reader) = stream.try_clone() else {
+            return;
+        };
+        *self.stream.lock().unwrap() = Some(stream);
+        self.connected.store(true, Ordering::Relaxed);
+        let inner = self.clone();
+        std::thread::spawn(move || {
+            let mut msg = [0u8; 2];
+            while reader.read_exact(&mut msg).is_ok() {
+                if tx.send(msg).is_err() {
+                    break;
+                }
+            }
+            if inner.connected.swap(false, Ordering::Relaxed) {
+                println!("{}: Link cable disconnected", "WARN".yellow());
+            }
+        });
+    }
+}
+
+impl Link for TcpLink {
+    fn send(&mut self, data: u8) {
+        let sent = self.send_message(MSG_TRANSFER, data);
+        *self.inner.sent_at.lock().unwrap() = sent.then(Instant::now);
+    }
+
+    fn receive(&mut self) -> Reply {
+        let mut sent_at = self.inner.sent_at.lock().unwrap();
+        let Some(time) = *sent_at else {
+            return Reply::Lost;
+        };
+        let rx = self.inner.rx.lock().unwrap();
+        let reply = loop {
+            match rx.try_recv() {
+                Ok([MSG_REPLY, reply]) => break Reply::Data(reply),
+                // Both ends clocked a transfer at the same time. Nobody answers.
+                Ok(_) => continue,
+                Err(TryRecvError::Empty) if time.elapsed() < REPLY_TIMEOUT => {
+                    return Reply::Pending;
+                }
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => break Reply::Lost,
+            }
+        };
+        *sent_at = None;
+        reply
+    }
+
+    fn poll(&mut self) -> Option<u8> {
+        let rx = self.inner.rx.lock().unwrap();
+        // Late replies to transfers that already timed out are dropped.
+        rx.try_iter()
+            .find(|[kind, _]| *kind == MSG_TRANSFER)
+            .map(|[_, data]| data)
+    }
+
+    fn reply(&mut self, data: u8) {
+        self.send_message(MSG_REPLY, data);
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.connected.load(Ordering::Relaxed)
+    }
+}
+
+/// Opens the link cable given in the CLI arguments, if any.
+pub fn open_link(args: &Args) -> Option<Result<TcpLink>> {
+    if let Some(address) = &args.link_host {
+        Some(TcpLink::host(address))
+    } else {
+        args.link_connect.as_deref().map(TcpLink::connect)
+    }
+}
+
+/// Resolves a link address. A bare port number means a port on this machine.
+fn resolve(address: &str) -> Result<SocketAddr> {
+    let address = match address.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{}", port),
+        Err(_) => address.to_string(),
+    };
+    address.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid link address: {}", address),
+        )
+    })
+}
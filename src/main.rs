@@ -1,27 +1,26 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod apu;
-mod cartridge;
+mod archive;
+mod audio;
 mod cli;
-mod constants;
-mod debugmanager;
 mod eventhandler;
 mod gui;
-mod instruction;
-mod joypad;
-mod machine;
-mod memory;
+mod headless;
+mod info;
+mod link;
+mod paper;
 mod playkid;
-mod ppu;
-mod registers;
-mod timer;
+mod rumble;
 mod uistate;
 
 use clap::Parser;
-use cli::Args;
-use constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use cli::{Args, Command};
 use playkid::PlayKid;
+use playkid_core::constants;
+use playkid_core::constants::{
+    DISPLAY_HEIGHT, DISPLAY_WIDTH, SGB_DISPLAY_HEIGHT, SGB_DISPLAY_WIDTH,
+};
 
 use eframe::egui;
 use eframe::egui::Visuals;
@@ -30,10 +29,33 @@ use eframe::egui::Visuals;
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result {
     env_logger::init();
-    let args = Args::parse();
+    let mut args = Args::parse();
 
-    let mut window_width = DISPLAY_WIDTH as f32 * args.scale as f32;
-    let window_height = DISPLAY_HEIGHT as f32 * args.scale as f32;
+    match args.command.take() {
+        // Headless run, without window or audio device.
+        Some(Command::Run(run)) if run.headless => {
+            std::process::exit(headless::run(&run, &args));
+        }
+        // ROM information, without running it.
+        Some(Command::Info(info)) => {
+            std::process::exit(info::run(&info, &args));
+        }
+        // Regular run of the given ROM.
+        Some(Command::Run(run)) => {
+            args.input = Some(run.input);
+            args.movie = run.movie;
+        }
+        None => (),
+    }
+
+    // The SGB border makes the picture bigger.
+    let (width, height) = if args.model.is_some_and(|m| m.is_sgb()) {
+        (SGB_DISPLAY_WIDTH, SGB_DISPLAY_HEIGHT)
+    } else {
+        (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    };
+    let mut window_width = width as f32 * args.scale as f32;
+    let window_height = height as f32 * args.scale as f32;
 
     // If debugging is enabled, add extra width for the SidePanel.
     if args.debug {
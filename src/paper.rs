@@ -0,0 +1,73 @@
+use crate::playkid::save_png;
+use playkid_core::printer::{PRINTER_WIDTH, PrintedStrip};
+use std::path::{Path, PathBuf};
+
+/// Height of a paper feed, in pixels.
+const FEED_HEIGHT: usize = 16;
+/// Gray levels of the thermal paper, from white to black.
+const PAPER_COLORS: [[u8; 3]; 4] = [[0xFF; 3], [0xAA; 3], [0x55; 3], [0x00; 3]];
+
+/// # Paper roll
+/// Collects the strips printed by the Game Boy Printer. Every strip is
+/// written to a PNG file in the output directory, and kept in the roll for
+/// the preview.
+pub struct PaperRoll {
+    /// Directory of the PNG files.
+    dir: PathBuf,
+    /// Printed strips, oldest first.
+    strips: Vec<PrintedStrip>,
+    /// Number of strips printed so far, including the torn off ones.
+    printed: usize,
+}
+
+impl PaperRoll {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            strips: Vec::new(),
+            printed: 0,
+        }
+    }
+
+    /// Adds a strip to the roll, and writes it to a PNG file named
+    /// `print_[time]_[n].png`, where `n` counts the prints of the session.
+    /// Existing files are never overwritten. Returns the path of the file.
+    pub fn print(&mut self, strip: PrintedStrip) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let path = loop {
+            let path = self
+                .dir
+                .join(format!("print_{}_{}.png", timestamp, self.printed));
+            self.printed += 1;
+            if !path.exists() {
+                break path;
+            }
+        };
+        let rgba = strip.to_rgba(&PAPER_COLORS);
+        self.strips.push(strip);
+        std::fs::create_dir_all(&self.dir)?;
+        let height = rgba.len() / (PRINTER_WIDTH * 4);
+        save_png(&path, PRINTER_WIDTH, height, &rgba)?;
+        Ok(path)
+    }
+
+    /// Tears off the paper.
+    pub fn clear(&mut self) {
+        self.strips.clear();
+    }
+
+    /// Renders the whole roll, with the paper feeds between strips, as RGBA
+    /// bytes [PRINTER_WIDTH] pixels wide. Returns the height and the bytes.
+    pub fn image(&self) -> (usize, Vec<u8>) {
+        let blank = |feeds: u8| vec![0xFF; feeds as usize * FEED_HEIGHT * PRINTER_WIDTH * 4];
+        let mut rgba = Vec::new();
+        for strip in &self.strips {
+            rgba.extend(blank(strip.margin_before));
+            rgba.extend(strip.to_rgba(&PAPER_COLORS));
+            rgba.extend(blank(strip.margin_after));
+        }
+        (rgba.len() / (PRINTER_WIDTH * 4), rgba)
+    }
+}
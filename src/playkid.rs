@@ -1,20 +1,33 @@
 #![deny(clippy::all)]
 
-use crate::cartridge::Cartridge;
+use crate::archive::{self, RomSource};
+use crate::audio::AudioOutput;
 use crate::cli::Args;
-use crate::constants;
-use crate::eventhandler::EventHandler;
+use crate::eventhandler::{ControllerHandler, EventHandler};
 use crate::gui::Gui;
-use crate::machine::Machine;
+use crate::link::{TcpLink, open_link};
+use crate::paper::PaperRoll;
+use crate::rumble::Rumble;
+use crate::uistate::LinkRequest;
 
 use colored::Colorize;
-use constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH, TARGET_FRAME_DURATION};
 use eframe::egui;
 use gilrs::{Event, EventType, Gilrs};
-use std::path::PathBuf;
+use playkid_core::cartridge::{Cartridge, CartridgeError};
+use playkid_core::cheats::CheatList;
+use playkid_core::constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH, TARGET_FRAME_DURATION};
+use playkid_core::machine::Machine;
+use playkid_core::movie::{Movie, MovieAnchor, MoviePlayer, MovieRecorder};
+use playkid_core::printer::Printer;
+use playkid_core::rewind::{REWIND_INTERVAL, Rewind};
+use playkid_core::serial::Link;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, channel};
 use std::time::{Duration, Instant};
 
+/// Wall-clock time spent emulating per UI frame in turbo mode.
+const TURBO_TIME_BUDGET: Duration = Duration::from_millis(12);
+
 /// # Play Kid
 /// The Play Kid application as an [eframe] app. Contains the
 /// main state, the [Machine], the [Gui], and the LCD texture.
@@ -22,21 +35,51 @@ pub struct PlayKid {
     running: bool,
     gui: Gui,
     machine: Option<Machine>,
+    /// Where the ROM of the current machine comes from.
+    rom_source: Option<RomSource>,
     last_update: Instant,
     // The GPU handle for the Game Boy screen.
     screen_texture: egui::TextureHandle,
     /// Game controller library.
     gilrs: Gilrs,
+    /// Rumble of the game controllers.
+    rumble: Rumble,
+    /// Audio output device.
+    audio: AudioOutput,
     /// CLI args.
     args: Args,
     /// MPSC receiver channel for ROM file paths.
     rx_load: Receiver<Option<PathBuf>>,
+    /// Rewind buffer.
+    rewind: Rewind,
+    /// The rewind key is held down.
+    rewind_key: bool,
+    /// The rewind button of the game controller is held down.
+    rewind_button: bool,
+    /// The fast-forward key is held down.
+    fast_forward_key: bool,
+    /// The fast-forward button of the game controller is held down.
+    fast_forward_button: bool,
+    /// Movie being recorded.
+    recorder: Option<MovieRecorder>,
+    /// Movie being played back.
+    player: Option<MoviePlayer>,
+    /// MPSC receiver channel for movie file paths.
+    rx_movie: Receiver<Option<PathBuf>>,
+    /// Link cable to another instance, plugged into the current machine.
+    link: Option<TcpLink>,
+    /// Game Boy Printer, plugged into the current machine instead of the
+    /// link cable.
+    printer: Option<Printer>,
+    /// Paper printed by the Game Boy Printer.
+    paper: PaperRoll,
 }
 
 #[allow(dead_code)]
 impl PlayKid {
     pub fn new(_cc: &eframe::CreationContext<'_>, args: Args) -> Self {
         let (tx_load, rx_load) = channel();
+        let (tx_movie, rx_movie) = channel();
         // Create LCD texture.
         let texture = _cc.egui_ctx.load_texture(
             "lcd_screen",
@@ -47,15 +90,21 @@ impl PlayKid {
             egui::TextureOptions::NEAREST,
         );
 
-        let mut machine = None;
+        let mut gui = Gui::new(args.debug, args.fps, tx_load, tx_movie);
+        gui.ui_state.model = args.model;
+        gui.ui_state.renderer = args.renderer;
 
-        if let Some(path) = &args.input {
-            if let Some(m) = Self::create_machine(path, args.skipcheck, args.debug) {
-                machine = Some(m);
+        // Link cable.
+        let link = match open_link(&args) {
+            Some(Ok(link)) => Some(link),
+            Some(Err(e)) => {
+                gui.add_error_toast(&format!("Failed to open link cable: {}", e));
+                None
             }
-        }
-
-        let gui = Gui::new(args.debug, args.fps, tx_load);
+            None => None,
+        };
+        let printer = args.printer.then(Printer::new);
+        let paper = PaperRoll::new(&args.printer_dir);
 
         // Use Gilrs to handle gamepad input.
         let gilrs = Gilrs::new().unwrap();
@@ -69,19 +118,45 @@ impl PlayKid {
             )
         });
         // Return instance.
-        Self {
+        let mut playkid = Self {
             running: true,
             gui,
-            machine,
+            machine: None,
+            rom_source: None,
             last_update: Instant::now(),
             screen_texture: texture,
             gilrs,
+            rumble: Rumble::new(),
+            audio: AudioOutput::new(),
+            rewind: Rewind::new(args.rewind, REWIND_INTERVAL),
+            rewind_key: false,
+            rewind_button: false,
+            fast_forward_key: false,
+            fast_forward_button: false,
+            recorder: None,
+            player: None,
+            rx_movie,
             args,
             rx_load,
+            link,
+            printer,
+            paper,
+        };
+        if let Some(path) = playkid.args.input.clone() {
+            let entry = playkid.args.entry.clone();
+            let patch = playkid.args.patch.clone();
+            playkid.open_rom(&path, entry.as_deref(), patch.as_deref());
         }
+
+        // Movie playback.
+        if let Some(path) = playkid.args.movie.clone() {
+            playkid.play_movie(&path);
+        }
+        playkid
     }
     pub fn new_wasm(_cc: &eframe::CreationContext<'_>, rom: String) -> Self {
         let (tx_load, rx_load) = channel();
+        let (tx_movie, rx_movie) = channel();
         let texture = _cc.egui_ctx.load_texture(
             "gb_screen",
             egui::ColorImage::new(
@@ -92,33 +167,233 @@ impl PlayKid {
         );
         let args = Args::default();
         // Create machine.
-        let machine =
-            Self::create_machine(&std::path::PathBuf::from(&rom), args.skipcheck, args.debug);
-        let gui = Gui::new(false, false, tx_load);
+        let mut gui = Gui::new(false, false, tx_load, tx_movie);
+        let source = RomSource {
+            path: PathBuf::from(&rom),
+            entry: None,
+            patch: None,
+        };
+        let machine = Self::create_machine(&source, &args, &mut gui);
         Self {
             running: true,
             gui,
             machine,
+            rom_source: Some(source),
             last_update: Instant::now(),
             screen_texture: texture,
             gilrs: Gilrs::new().unwrap(),
-            args: Args::default(),
+            rumble: Rumble::new(),
+            audio: AudioOutput::new(),
+            rewind: Rewind::new(args.rewind, REWIND_INTERVAL),
+            rewind_key: false,
+            rewind_button: false,
+            fast_forward_key: false,
+            fast_forward_button: false,
+            recorder: None,
+            player: None,
+            rx_movie,
+            args,
             rx_load,
+            link: None,
+            printer: None,
+            paper: PaperRoll::new(Path::new(".")),
+        }
+    }
+
+    /// Helper to create a machine instance from a ROM source. Errors and
+    /// warnings are reported as toasts.
+    fn create_machine(source: &RomSource, args: &Args, gui: &mut Gui) -> Option<Machine> {
+        let cart = source
+            .read()
+            .map_err(CartridgeError::from)
+            .and_then(|data| Cartridge::from_bytes(data, args.skipcheck));
+        let mut cart = match cart {
+            Ok(cart) => cart,
+            Err(e) => {
+                eprintln!("{}: Failed to load ROM: {}", "ERR".red(), e);
+                gui.add_error_toast(&format!("Failed to load ROM: {}", e));
+                return None;
+            }
+        };
+        if let Some(patch) = &source.patch {
+            println!("{}: Patch applied: {}", "OK".green(), patch.display());
+            gui.add_info_toast(&format!("Patch applied: {}", patch.display()));
+        }
+        // Save files go next to the ROM, or next to the archive under the
+        // ROM's own name.
+        cart.set_rom_path(source.rom_path());
+        for warning in cart.warnings() {
+            gui.add_warning_toast(&warning.to_string());
+        }
+        cart.load_sram();
+        let mut machine = match args.model {
+            Some(model) => Machine::with_model(cart, model, args.debug),
+            None => Machine::new(cart, args.debug),
+        };
+        machine.memory.ppu.set_renderer(args.renderer);
+        if let Some(boot_rom) = &args.boot_rom
+            && let Err(e) = std::fs::read(boot_rom).and_then(|data| machine.set_boot_rom(data))
+        {
+            eprintln!(
+                "{}: Failed to load boot ROM, skipping boot: {}",
+                "ERR".red(),
+                e
+            );
+            gui.add_error_toast(&format!("Failed to load boot ROM: {}", e));
+        }
+        if let Some(path) = machine.cheats_path()
+            && path.exists()
+        {
+            match CheatList::load(&path) {
+                Ok(cheats) => {
+                    println!("{}: Cheats loaded: {}", "OK".green(), path.display());
+                    machine.set_cheats(cheats);
+                }
+                Err(e) => gui.add_error_toast(&format!("Failed to load cheats: {}", e)),
+            }
+        }
+        Some(machine)
+    }
+
+    /// Opens the ROM file or archive at the given path. If the archive holds
+    /// several ROMs, and no entry is given, the user is asked to pick one.
+    /// The given patch, or else the one next to the ROM, is applied.
+    /// Returns whether a ROM was loaded.
+    fn open_rom(&mut self, path: &Path, entry: Option<&str>, patch: Option<&Path>) -> bool {
+        let sources = match entry {
+            Some(entry) => archive::select(path, Some(entry)).map(|s| vec![s]),
+            None => archive::open(path),
+        }
+        .map(|sources| {
+            sources
+                .into_iter()
+                .map(|source| source.with_patch(patch))
+                .collect::<Vec<_>>()
+        });
+        match sources {
+            Ok(sources) if sources.len() == 1 => {
+                self.load_rom(&sources[0]);
+                self.machine.is_some()
+            }
+            Ok(sources) => {
+                self.gui.pick_rom(sources);
+                false
+            }
+            Err(e) => {
+                eprintln!("{}: Failed to open ROM: {}", "ERR".red(), e);
+                self.gui
+                    .add_error_toast(&format!("Failed to open ROM: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Replaces the current machine with a new one running the given ROM.
+    fn load_rom(&mut self, source: &RomSource) {
+        if let Some(ref mut m) = self.machine {
+            m.memory.cart.save_sram();
+        }
+        self.rumble.set(&mut self.gilrs, false);
+        self.machine = Self::create_machine(source, &self.args, &mut self.gui);
+        self.rom_source = self.machine.as_ref().map(|_| source.clone());
+        self.gui.clear_serial();
+        self.gui.refresh_rom_info(self.machine.as_ref());
+        self.gui.clear_ram_search();
+        self.plug_serial_device();
+        self.gui.invalidate_state_slots();
+        self.rewind.clear();
+        self.recorder = None;
+        self.player = None;
+        self.last_update = std::time::Instant::now();
+    }
+
+    /// Opens or closes the link cable, as requested from the GUI.
+    fn handle_link_request(&mut self, request: LinkRequest) {
+        if let Some(link) = self.link.take() {
+            link.close();
+        }
+        let link = match request {
+            LinkRequest::Host(address) => Some(TcpLink::host(&address)),
+            LinkRequest::Connect(address) => Some(TcpLink::connect(&address)),
+            LinkRequest::Disconnect => None,
+        };
+        match link {
+            Some(Ok(link)) => {
+                // The link cable takes the place of the printer.
+                self.printer = None;
+                self.link = Some(link);
+            }
+            Some(Err(e)) => self
+                .gui
+                .add_error_toast(&format!("Failed to open link cable: {}", e)),
+            None => {}
+        }
+        self.plug_serial_device();
+    }
+
+    /// Plugs the printer, or else the link cable, into the serial port of
+    /// the current machine.
+    fn plug_serial_device(&mut self) {
+        let Some(m) = &mut self.machine else {
+            return;
+        };
+        let device: Option<Box<dyn Link>> = match (&self.printer, &self.link) {
+            (Some(printer), _) => Some(Box::new(printer.clone())),
+            (None, Some(link)) => Some(Box::new(link.clone())),
+            (None, None) => None,
+        };
+        m.set_link(device);
+    }
+
+    /// Plugs in or unplugs the Game Boy Printer.
+    fn set_printer(&mut self, on: bool) {
+        if on {
+            if let Some(link) = self.link.take() {
+                link.close();
+            }
+            self.printer = Some(Printer::new());
+        } else {
+            self.printer = None;
+        }
+        self.plug_serial_device();
+    }
+
+    /// Saves the cheats of the current machine to the cheat file of the ROM.
+    fn save_cheats(&mut self) {
+        let Some(m) = &self.machine else {
+            return;
+        };
+        if let Some(path) = m.cheats_path()
+            && let Err(e) = m.cheats().save(&path)
+        {
+            eprintln!("{}: Failed to save cheats: {}", "ERR".red(), e);
+            self.gui
+                .add_error_toast(&format!("Failed to save cheats: {}", e));
         }
     }
 
-    /// Helper to create a machine instance from a path
-    fn create_machine(path: &std::path::PathBuf, skipcheck: bool, debug: bool) -> Option<Machine> {
-        if let Some(rom_str) = path.to_str() {
-            match Cartridge::new(rom_str, skipcheck) {
-                Ok(mut cart) => {
-                    cart.load_sram();
-                    return Some(Machine::new(cart, debug));
+    /// Collects the strips printed by the Game Boy Printer.
+    fn collect_prints(&mut self) {
+        let Some(printer) = &self.printer else {
+            return;
+        };
+        let strips = printer.take_printed();
+        if strips.is_empty() {
+            return;
+        }
+        for strip in strips {
+            match self.paper.print(strip) {
+                Ok(path) => {
+                    println!("{}: Printed: {}", "OK".green(), path.display());
+                    self.gui
+                        .add_info_toast(&format!("Printed: {}", path.display()));
                 }
-                Err(e) => eprintln!("Failed to load ROM: {}", e),
+                Err(e) => self
+                    .gui
+                    .add_error_toast(&format!("Failed to save print: {}", e)),
             }
         }
-        None
+        self.gui.set_paper(self.paper.image());
     }
 
     /// Handle requests from the GUI.
@@ -127,10 +402,190 @@ impl PlayKid {
             self.running = false;
             self.gui.ui_state.exit_requested = false
         }
+        if self.gui.ui_state.model_requested {
+            self.gui.ui_state.model_requested = false;
+            self.args.model = self.gui.ui_state.model;
+            if let Some(source) = self.rom_source.clone() {
+                self.load_rom(&source);
+                let model = self.machine.as_ref().map(|m| m.memory.model);
+                if let Some(model) = model {
+                    self.gui
+                        .add_info_toast(&format!("Model: {}", model.description()));
+                }
+            }
+        }
+        // Save states and rewind snapshots bring their own renderer.
+        if let Some(machine) = self.machine.as_ref()
+            && machine.memory.ppu.renderer() != self.args.renderer
+            && self.gui.ui_state.renderer == self.args.renderer
+        {
+            self.args.renderer = machine.memory.ppu.renderer();
+            self.gui.ui_state.renderer = self.args.renderer;
+        }
+        if self.gui.ui_state.renderer != self.args.renderer {
+            self.args.renderer = self.gui.ui_state.renderer;
+            if let Some(machine) = self.machine.as_mut() {
+                machine.memory.ppu.set_renderer(self.args.renderer);
+            }
+            self.gui
+                .add_info_toast(&format!("Renderer: {}", self.args.renderer.description()));
+        }
+        if let Some(source) = self.gui.ui_state.rom_source_requested.take() {
+            self.load_rom(&source);
+            if self.machine.is_some() {
+                self.gui
+                    .add_info_toast(&format!("ROM Loaded: {}", source.name()));
+            }
+        }
+        if self.gui.ui_state.cheats_changed {
+            self.gui.ui_state.cheats_changed = false;
+            self.save_cheats();
+        }
+        if let Some(on) = self.gui.ui_state.printer_requested.take() {
+            self.set_printer(on);
+        }
+        if self.gui.ui_state.clear_paper_requested {
+            self.gui.ui_state.clear_paper_requested = false;
+            self.paper.clear();
+            self.gui.set_paper(self.paper.image());
+        }
+        self.gui.ui_state.printer_connected = self.printer.is_some();
+        if let Some(request) = self.gui.ui_state.link_requested.take() {
+            self.handle_link_request(request);
+        }
+        self.gui.ui_state.link_status = self.link.as_ref().map(|l| l.status());
         if self.gui.ui_state.screenshot_requested {
             self.screenshot();
             self.gui.ui_state.screenshot_requested = false;
         }
+        if self.gui.ui_state.save_state_requested {
+            self.save_state();
+            self.gui.ui_state.save_state_requested = false;
+        }
+        if self.gui.ui_state.load_state_requested {
+            self.load_state();
+            self.gui.ui_state.load_state_requested = false;
+        }
+        if let Some(anchor) = self.gui.ui_state.record_movie_requested.take() {
+            self.start_recording(anchor);
+        }
+        if self.gui.ui_state.stop_movie_requested {
+            self.stop_movie();
+            self.gui.ui_state.stop_movie_requested = false;
+        }
+        self.gui.ui_state.is_recording = self.recorder.is_some();
+        self.gui.ui_state.is_playing = self.player.is_some();
+    }
+
+    /// Starts recording a movie from the given anchor.
+    fn start_recording(&mut self, anchor: MovieAnchor) {
+        if let Some(ref mut machine) = self.machine {
+            if let Some(player) = self.player.take() {
+                player.finish(machine);
+            }
+            self.recorder = Some(MovieRecorder::new(machine, anchor));
+            self.gui.add_info_toast("Movie recording started");
+        }
+    }
+
+    /// Stops the current movie. A recorded movie is written next to the ROM
+    /// file, with the name `[rom]_[time].pkm`.
+    fn stop_movie(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let movie = recorder.finish();
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let rom = self
+                .machine
+                .as_ref()
+                .and_then(|m| m.memory.cart.get_rom_path().cloned())
+                .unwrap_or_else(|| PathBuf::from("movie"));
+            let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+            let path = rom.with_file_name(format!("{}_{}.pkm", stem, timestamp));
+            match movie.save(&path) {
+                Ok(()) => {
+                    println!("Movie saved: {}", path.display());
+                    self.gui.add_info_toast(&format!(
+                        "Movie saved ({} frames): {:?}",
+                        movie.frames(),
+                        path
+                    ));
+                }
+                Err(e) => self
+                    .gui
+                    .add_error_toast(&format!("Failed to save movie: {}", e)),
+            }
+        }
+        if let Some(player) = self.player.take() {
+            if let Some(ref mut machine) = self.machine {
+                player.finish(machine);
+            }
+            self.gui.add_info_toast("Movie playback stopped");
+        }
+    }
+
+    /// Starts playing back the movie in the given file.
+    fn play_movie(&mut self, path: &Path) {
+        if let Some(ref mut machine) = self.machine {
+            self.recorder = None;
+            if let Some(player) = self.player.take() {
+                player.finish(machine);
+            }
+            match Movie::load(path).and_then(|movie| MoviePlayer::new(movie, machine)) {
+                Ok(player) => {
+                    self.gui.add_info_toast(&format!(
+                        "Playing movie ({} frames)",
+                        player.movie().frames()
+                    ));
+                    self.player = Some(player);
+                }
+                Err(e) => self
+                    .gui
+                    .add_error_toast(&format!("Failed to load movie: {}", e)),
+            }
+        }
+    }
+
+    /// Saves the state of the machine to the selected slot.
+    fn save_state(&mut self) {
+        if let Some(ref machine) = self.machine {
+            let slot = self.gui.ui_state.state_slot;
+            match machine.save_state_slot(slot) {
+                Ok(path) => {
+                    println!("State saved: {}", path.display());
+                    self.gui
+                        .add_info_toast(&format!("State saved to slot {}", slot));
+                }
+                Err(e) => self
+                    .gui
+                    .add_error_toast(&format!("Failed to save state: {}", e)),
+            }
+            self.gui.invalidate_state_slots();
+        }
+    }
+
+    /// Loads the state of the machine from the selected slot.
+    fn load_state(&mut self) {
+        // Loading a state would break the movie being recorded or played back.
+        if self.recorder.is_some() || self.player.is_some() {
+            self.gui
+                .add_warning_toast("Stop the movie before loading a state");
+            return;
+        }
+        if let Some(ref mut machine) = self.machine {
+            let slot = self.gui.ui_state.state_slot;
+            match machine.load_state_slot(slot) {
+                Ok(()) => {
+                    self.gui
+                        .add_info_toast(&format!("State loaded from slot {}", slot));
+                }
+                Err(e) => self
+                    .gui
+                    .add_error_toast(&format!("Failed to load state: {}", e)),
+            }
+        }
     }
 
     /// Handle keyboard and mouse input.
@@ -159,8 +614,13 @@ impl PlayKid {
 
                 // Reset.
                 if i.key_pressed(egui::Key::R) {
-                    machine.reset();
-                    self.gui.add_info_toast("CPU reset");
+                    if self.recorder.is_some() || self.player.is_some() {
+                        self.gui
+                            .add_warning_toast("Stop the movie before resetting");
+                    } else {
+                        machine.reset();
+                        self.gui.add_info_toast("CPU reset");
+                    }
                     handled = true;
                 }
 
@@ -186,14 +646,64 @@ impl PlayKid {
                     if machine.memory.cart.is_dirty() {
                         machine.memory.cart.save_sram();
                         machine.memory.cart.consume_dirty();
-                        self.gui.add_info_toast(&format!(
-                            "SRAM file written: {:?}",
-                            machine.memory.cart.get_sram_path()
-                        ));
+                        if let Some(path) = machine.memory.cart.get_sram_path() {
+                            self.gui
+                                .add_info_toast(&format!("SRAM file written: {:?}", path));
+                        }
                     }
                     handled = true;
                 }
 
+                // Rewind, while held down.
+                self.rewind_key = i.key_down(egui::Key::Backspace);
+
+                // Fast-forward, while held down.
+                self.fast_forward_key = i.key_down(egui::Key::Tab);
+
+                // Turbo.
+                if !handled && i.key_pressed(egui::Key::T) {
+                    self.gui.ui_state.turbo = !self.gui.ui_state.turbo;
+                    self.gui.clear_toasts();
+                    self.gui.add_info_toast(if self.gui.ui_state.turbo {
+                        "Turbo on"
+                    } else {
+                        "Turbo off"
+                    });
+                    handled = true;
+                }
+
+                // Save state slot selection.
+                let slot_keys = [
+                    egui::Key::Num1,
+                    egui::Key::Num2,
+                    egui::Key::Num3,
+                    egui::Key::Num4,
+                    egui::Key::Num5,
+                    egui::Key::Num6,
+                    egui::Key::Num7,
+                    egui::Key::Num8,
+                    egui::Key::Num9,
+                ];
+                for (slot, key) in slot_keys.iter().enumerate() {
+                    if !handled && i.key_pressed(*key) {
+                        self.gui.ui_state.state_slot = slot as u8 + 1;
+                        self.gui.clear_toasts();
+                        self.gui
+                            .add_info_toast(&format!("State slot {} selected", slot + 1));
+                        handled = true;
+                    }
+                }
+
+                // Save and load state.
+                if !handled && i.key_pressed(egui::Key::F2) {
+                    self.gui.ui_state.save_state_requested = true;
+                    handled = true;
+                }
+                if !handled && i.key_pressed(egui::Key::F4) {
+                    self.gui.ui_state.load_state_requested = true;
+                    handled = true;
+                }
+
                 // Machine-specific input handling.
                 if !handled {
                     handled = machine.handle_event(i);
@@ -207,11 +717,12 @@ impl PlayKid {
         });
     }
 
-    /// Creates a screenshot from the front frame buffer of the PPU.
+    /// Creates a screenshot from the front frame buffer of the PPU, within
+    /// the border in SGB mode.
     fn screenshot(&mut self) {
         if let Some(ref machine) = self.machine {
-            let fb = &machine.memory.ppu.fb_front;
-            if let Ok(name) = save_screenshot(DISPLAY_WIDTH, DISPLAY_HEIGHT, fb) {
+            let (width, height, fb) = machine.display();
+            if let Ok(name) = save_screenshot(width, height, fb) {
                 println!("Screenshot saved: {}", name);
                 self.gui
                     .add_info_toast(&format!("Screenshot saved: {}", name));
@@ -225,12 +736,14 @@ impl PlayKid {
         while let Some(Event { id, event, .. }) = self.gilrs.next_event() {
             let mut handled = match event {
                 EventType::Connected => {
+                    self.rumble.reset();
                     let gamepad = self.gilrs.gamepad(id);
                     self.gui
                         .add_info_toast(&format!("Gamepad connected: {}", gamepad.name()));
                     true
                 }
                 EventType::Disconnected => {
+                    self.rumble.reset();
                     self.gui.add_info_toast("Gamepad disconnected");
                     true
                 }
@@ -238,26 +751,40 @@ impl PlayKid {
             };
 
             // Trigger Machine-specific controller logic.
-            if !handled {
-                if let Some(ref mut machine) = self.machine {
-                    handled = match event {
-                        EventType::ButtonReleased(button, _) => match button {
-                            gilrs::Button::LeftTrigger => {
-                                machine.memory.ppu.cycle_palette_rev();
-                                true
-                            }
-                            gilrs::Button::RightTrigger => {
-                                machine.memory.ppu.cycle_palette();
-                                true
-                            }
-                            _ => false,
-                        },
+            if !handled && let Some(ref mut machine) = self.machine {
+                handled = match event {
+                    EventType::ButtonPressed(gilrs::Button::LeftTrigger2, _) => {
+                        self.rewind_button = true;
+                        true
+                    }
+                    EventType::ButtonReleased(gilrs::Button::LeftTrigger2, _) => {
+                        self.rewind_button = false;
+                        true
+                    }
+                    EventType::ButtonPressed(gilrs::Button::RightTrigger2, _) => {
+                        self.fast_forward_button = true;
+                        true
+                    }
+                    EventType::ButtonReleased(gilrs::Button::RightTrigger2, _) => {
+                        self.fast_forward_button = false;
+                        true
+                    }
+                    EventType::ButtonReleased(button, _) => match button {
+                        gilrs::Button::LeftTrigger => {
+                            machine.memory.ppu.cycle_palette_rev();
+                            true
+                        }
+                        gilrs::Button::RightTrigger => {
+                            machine.memory.ppu.cycle_palette();
+                            true
+                        }
                         _ => false,
-                    };
+                    },
+                    _ => false,
+                };
 
-                    if !handled {
-                        machine.memory.joypad.handle_controller_input(event);
-                    }
+                if !handled {
+                    machine.memory.joypad.handle_controller_input(event);
                 }
             }
         }
@@ -271,17 +798,18 @@ impl eframe::App for PlayKid {
             self.gui.ui_state.is_picking_file = false;
             if let Some(rom_path) = maybe_rom_path {
                 // A file was actually chosen.
-                if let Some(ref mut m) = self.machine {
-                    m.memory.cart.save_sram();
+                if self.open_rom(&rom_path, None, None) {
+                    self.gui
+                        .add_info_toast(&format!("ROM Loaded: {:?}", rom_path));
                 }
-                self.machine =
-                    Self::create_machine(&rom_path, self.args.skipcheck, self.args.debug);
-                self.last_update = std::time::Instant::now();
-                self.gui
-                    .add_info_toast(&format!("ROM Loaded: {:?}", rom_path));
             }
         }
 
+        // Check if movie playback has been requested via the channel.
+        if let Ok(Some(movie_path)) = self.rx_movie.try_recv() {
+            self.play_movie(&movie_path);
+        }
+
         // Mouse/Kbd input.
         self.handle_inputs(ctx);
         // Controller input.
@@ -307,25 +835,100 @@ impl eframe::App for PlayKid {
             }
 
             // Update.
-            while dt >= TARGET_FRAME_DURATION {
-                machine.update();
-                dt -= TARGET_FRAME_DURATION;
-                self.last_update += TARGET_FRAME_DURATION;
+            // Rewinding would break the movie being recorded or played back.
+            let movie_active = self.recorder.is_some() || self.player.is_some();
+            let rewinding = (self.rewind_key || self.rewind_button) && !movie_active;
+            // Turbo runs as many frames as fit in the time budget. Otherwise,
+            // the frame duration is scaled by the speed.
+            let turbo =
+                self.fast_forward_key || self.fast_forward_button || self.gui.ui_state.turbo;
+            let frame_duration = TARGET_FRAME_DURATION.div_f32(self.gui.ui_state.speed);
+            let deadline = now + TURBO_TIME_BUDGET;
+            // Audio is only played at real speed.
+            self.audio
+                .set_muted(turbo || self.gui.ui_state.speed != 1.0);
+            while (turbo && Instant::now() < deadline) || (!turbo && dt >= frame_duration) {
+                if self.gui.is_scrubbing() {
+                    // Paused while the rewind scrubber is open.
+                } else if rewinding {
+                    // Go back one snapshot every interval, which plays the
+                    // game backwards at real-time speed.
+                    if self.rewind.tick()
+                        && let Some(snapshot) = self.rewind.pop()
+                    {
+                        let _ = machine.restore_snapshot(&snapshot);
+                    }
+                } else {
+                    let running = !machine.debug.is_paused();
+                    if running && let Some(ref mut player) = self.player {
+                        player.begin_frame(machine);
+                    }
+                    machine.update();
+                    self.audio.play(machine.take_audio_samples());
+                    if running && let Some(ref mut recorder) = self.recorder {
+                        recorder.end_frame(machine);
+                        self.gui.ui_state.movie_frame = recorder.frames();
+                    }
+                    if running && let Some(ref mut player) = self.player {
+                        if let Some(frame) = player.end_frame(machine) {
+                            self.gui
+                                .add_error_toast(&format!("Movie desynced at frame {}", frame));
+                        }
+                        self.gui.ui_state.movie_frame = player.frame();
+                        if player.is_finished() {
+                            self.gui.add_info_toast("Movie playback finished");
+                            if let Some(player) = self.player.take() {
+                                player.finish(machine);
+                            }
+                        }
+                    }
+                    if !machine.debug.is_paused() && self.args.rewind > 0 && self.rewind.tick() {
+                        self.rewind.push(machine.snapshot());
+                    }
+                }
+                if turbo {
+                    self.last_update = Instant::now();
+                } else {
+                    dt -= frame_duration;
+                    self.last_update += frame_duration;
+                }
                 frame_ready = true;
             }
 
-            // Render LCD to texture.
+            // Serial console.
+            let serial = machine.take_serial_output();
+            if !serial.is_empty()
+                && let Some(stop) = self.gui.push_serial(&serial)
+            {
+                if !machine.debug.is_debugging() {
+                    machine.debug.toggle_debugging();
+                }
+                machine.debug.set_paused(true);
+                self.gui.show_debugger(true);
+                self.gui
+                    .add_info_toast(&format!("Serial output contains {:?}", stop));
+            }
+
+            // Forward the rumble motor to the gamepads.
             if frame_ready {
-                let size = [DISPLAY_WIDTH, DISPLAY_HEIGHT];
-                let color_image =
-                    egui::ColorImage::from_rgba_unmultiplied(size, &machine.memory.ppu.fb_front);
+                let rumble = machine.memory.cart.take_rumble() && !machine.debug.is_paused();
+                self.rumble.set(&mut self.gilrs, rumble);
+            }
+
+            // Render LCD to texture.
+            if frame_ready || self.gui.is_scrubbing() {
+                let (width, height, fb) = machine.display();
+                let color_image = egui::ColorImage::from_rgba_unmultiplied([width, height], fb);
                 self.screen_texture
                     .set(color_image, egui::TextureOptions::NEAREST);
             }
         }
 
+        // Game Boy Printer.
+        self.collect_prints();
+
         // Render GUI.
-        self.gui.ui(ctx, &mut self.machine);
+        self.gui.ui(ctx, &mut self.machine, &mut self.rewind);
 
         // Draw the LCD.
         egui::CentralPanel::default()
@@ -334,19 +937,19 @@ impl eframe::App for PlayKid {
                 // Get the actual space left after egui::SidePanel/egui::TopBottomPanel take their share.
                 let available_size = ui.available_size();
 
-                // Calculate scale factors for both width and height.
-                let scale_x = (available_size.x / DISPLAY_WIDTH as f32).floor();
-                let scale_y = (available_size.y / DISPLAY_HEIGHT as f32).floor();
+                // Calculate scale factors for both width and height. The
+                // SGB border makes the picture bigger.
+                let [width, height] = self.screen_texture.size().map(|s| s as f32);
+                let scale_x = (available_size.x / width).floor();
+                let scale_y = (available_size.y / height).floor();
 
                 // Use the smaller of the two to ensure it fits the "letterbox" or "pillarbox".
                 let scale = scale_x.min(scale_y).max(1.0);
 
                 ui.centered_and_justified(|ui| {
                     ui.add(
-                        egui::Image::new(&self.screen_texture).fit_to_exact_size(egui::vec2(
-                            DISPLAY_WIDTH as f32 * scale,
-                            DISPLAY_HEIGHT as f32 * scale,
-                        )),
+                        egui::Image::new(&self.screen_texture)
+                            .fit_to_exact_size(egui::vec2(width * scale, height * scale)),
                     );
                 });
             });
@@ -368,6 +971,24 @@ fn save_screenshot(
     height: usize,
     frame: &[u8],
 ) -> Result<String, Box<dyn std::error::Error>> {
+    // Generate a filename with a timestamp.
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let filename = format!("screenshot_{}.png", timestamp);
+
+    save_png(Path::new(&filename), width, height, frame)?;
+
+    Ok(filename)
+}
+
+/// Saves the given RGBA frame to a PNG file.
+pub fn save_png(
+    path: &Path,
+    width: usize,
+    height: usize,
+    frame: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
     use image::{ImageBuffer, Rgba};
 
     // Create an ImageBuffer from the raw pixels.
@@ -375,14 +996,8 @@ fn save_screenshot(
         ImageBuffer::from_raw(width as u32, height as u32, frame.to_vec())
             .ok_or("Failed to create image buffer from pixels")?;
 
-    // Generate a filename with a timestamp.
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs();
-    let filename = format!("screenshot_{}.png", timestamp);
-
     // Save as PNG.
-    img.save(&filename)?;
+    img.save(path)?;
 
-    Ok(filename)
+    Ok(())
 }
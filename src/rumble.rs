@@ -0,0 +1,73 @@
+use colored::Colorize;
+use gilrs::Gilrs;
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks};
+
+/// Strength of the rumble effect, in [0, 65535].
+const RUMBLE_MAGNITUDE: u16 = 40_000;
+
+/// # Rumble
+/// Forwards the rumble motor of the cartridge to the connected gamepads,
+/// as a force feedback effect.
+pub struct Rumble {
+    /// The force feedback effect, created when first needed.
+    effect: Option<Effect>,
+    /// Whether the effect is playing.
+    on: bool,
+}
+
+impl Rumble {
+    pub fn new() -> Self {
+        Self {
+            effect: None,
+            on: false,
+        }
+    }
+
+    /// Starts or stops the rumble effect.
+    pub fn set(&mut self, gilrs: &mut Gilrs, on: bool) {
+        if on == self.on {
+            return;
+        }
+        self.on = on;
+        if self.effect.is_none() && on {
+            self.effect = Self::create_effect(gilrs);
+        }
+        if let Some(effect) = &self.effect {
+            let _ = if on { effect.play() } else { effect.stop() };
+        }
+    }
+
+    /// Drops the effect, so that it is created again for the gamepads that
+    /// are connected now. Call it when a gamepad is connected or disconnected.
+    pub fn reset(&mut self) {
+        self.effect = None;
+        self.on = false;
+    }
+
+    /// Creates the effect for all gamepads that support force feedback.
+    fn create_effect(gilrs: &mut Gilrs) -> Option<Effect> {
+        let ids: Vec<_> = gilrs
+            .gamepads()
+            .filter(|(_, g)| g.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+        if ids.is_empty() {
+            return None;
+        }
+        EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: RUMBLE_MAGNITUDE,
+                },
+                scheduling: Replay {
+                    play_for: Ticks::from_ms(50),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .gamepads(&ids)
+            .finish(gilrs)
+            .inspect_err(|e| println!("{}: Rumble not available: {}", "ERR".red(), e))
+            .ok()
+    }
+}
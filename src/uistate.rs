@@ -1,9 +1,62 @@
+use crate::archive::RomSource;
+use playkid_core::model::Model;
+use playkid_core::movie::MovieAnchor;
+use playkid_core::ppu::Renderer;
+
+/// Link cable request from the GUI.
+pub enum LinkRequest {
+    /// Listen for a connection at the given port or address.
+    Host(String),
+    /// Connect to the given port or address.
+    Connect(String),
+    /// Close the link.
+    Disconnect,
+}
+
 /// # UI state
 /// UI action queue to send requests to the main app.
 pub struct UIState {
     pub exit_requested: bool,
     pub screenshot_requested: bool,
     pub is_picking_file: bool,
+    /// Load the given ROM, picked among the ones in an archive.
+    pub rom_source_requested: Option<RomSource>,
+    /// Currently selected save state slot.
+    pub state_slot: u8,
+    pub save_state_requested: bool,
+    pub load_state_requested: bool,
+    /// Start recording a movie from the given anchor.
+    pub record_movie_requested: Option<MovieAnchor>,
+    /// Stop recording or playing back the current movie.
+    pub stop_movie_requested: bool,
+    /// A movie is being recorded.
+    pub is_recording: bool,
+    /// A movie is being played back.
+    pub is_playing: bool,
+    /// Current frame of the movie being recorded or played back.
+    pub movie_frame: usize,
+    /// Emulation speed, as a factor of the real speed.
+    pub speed: f32,
+    /// Run as fast as possible, ignoring the speed.
+    pub turbo: bool,
+    /// Selected hardware model. If `None`, it is picked from the cartridge.
+    pub model: Option<Model>,
+    /// Reload the ROM with the selected hardware model.
+    pub model_requested: bool,
+    /// Selected PPU renderer. It can be changed without resetting.
+    pub renderer: Renderer,
+    /// Open or close the link cable.
+    pub link_requested: Option<LinkRequest>,
+    /// State of the link cable, if there is one.
+    pub link_status: Option<String>,
+    /// Plug in (`true`) or unplug (`false`) the Game Boy Printer.
+    pub printer_requested: Option<bool>,
+    /// The Game Boy Printer is plugged in.
+    pub printer_connected: bool,
+    /// Tear off the paper printed so far.
+    pub clear_paper_requested: bool,
+    /// The cheats were edited, and must be saved.
+    pub cheats_changed: bool,
 }
 
 impl UIState {
@@ -12,6 +65,33 @@ impl UIState {
             exit_requested: false,
             screenshot_requested: false,
             is_picking_file: false,
+            rom_source_requested: None,
+            state_slot: 1,
+            save_state_requested: false,
+            load_state_requested: false,
+            record_movie_requested: None,
+            stop_movie_requested: false,
+            is_recording: false,
+            is_playing: false,
+            movie_frame: 0,
+            speed: 1.0,
+            turbo: false,
+            model: None,
+            model_requested: false,
+            renderer: Renderer::Fifo,
+            link_requested: None,
+            link_status: None,
+            printer_requested: None,
+            printer_connected: false,
+            clear_paper_requested: false,
+            cheats_changed: false,
         }
     }
+
+    /// Whether a movie is being recorded or played back. Actions that
+    /// replace the machine state are blocked meanwhile, since they would
+    /// break the movie.
+    pub fn is_movie_active(&self) -> bool {
+        self.is_recording || self.is_playing
+    }
 }